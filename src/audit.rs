@@ -0,0 +1,373 @@
+// Copyright 2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// audit log: every item that is removed from the cache is recorded as one line of json in
+// ${CARGO_HOME}/.cargo-cache/audit-log.jsonl
+// "cargo cache log" filters and prints the log
+
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::date::parse_date;
+use crate::library::Error;
+use crate::tables::format_table;
+
+use chrono::{DateTime, Local};
+use clap::ArgMatches;
+use humansize::{file_size_opts, FileSize};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// get the path of the audit log
+pub(crate) fn audit_log_path(cargo_home: &Path) -> PathBuf {
+    cargo_home.join(".cargo-cache").join("audit-log.jsonl")
+}
+
+/// the operation that caused an item to be removed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Operation {
+    Trim,
+    CleanUnref,
+    Date,
+    RemoveDir,
+    KeepDuplicates,
+    Autoclean,
+    EmptyTrash,
+    Tui,
+}
+
+impl Operation {
+    /// every operation, the names of these are what "cargo cache log --operation" accepts
+    pub(crate) const ALL: [Self; 8] = [
+        Self::Trim,
+        Self::CleanUnref,
+        Self::Date,
+        Self::RemoveDir,
+        Self::KeepDuplicates,
+        Self::Autoclean,
+        Self::EmptyTrash,
+        Self::Tui,
+    ];
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Self::Trim => "trim",
+            Self::CleanUnref => "clean-unref",
+            Self::Date => "date",
+            Self::RemoveDir => "remove-dir",
+            Self::KeepDuplicates => "keep-duplicates",
+            Self::Autoclean => "autoclean",
+            Self::EmptyTrash => "empty-trash",
+            Self::Tui => "tui",
+        }
+    }
+
+    /// the names of all operations
+    pub(crate) fn names() -> Vec<&'static str> {
+        Self::ALL.iter().map(|operation| operation.name()).collect()
+    }
+}
+
+impl std::str::FromStr for Operation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|operation| operation.name() == s)
+            .ok_or_else(|| s.to_string())
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// what happened to the item
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Outcome {
+    Removed,
+    Quarantined,
+    Failed,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Removed => "removed",
+            Self::Quarantined => "quarantined",
+            Self::Failed => "failed",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// a single line of the audit log
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Record {
+    /// time of the removal (rfc3339)
+    timestamp: String,
+    /// the full command line cargo-cache was invoked with
+    command: String,
+    operation: Operation,
+    path: PathBuf,
+    /// size of the item in bytes, if known
+    size: Option<u64>,
+    outcome: Outcome,
+    /// error message if the removal failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// appends records of removed items to the audit log
+#[derive(Debug, Clone)]
+pub(crate) struct AuditLog {
+    path: PathBuf,
+    command: String,
+    operation: Operation,
+}
+
+impl AuditLog {
+    /// create a new log handle for the given operation
+    pub(crate) fn new(cargo_home: &Path, operation: Operation) -> Self {
+        Self {
+            path: audit_log_path(cargo_home),
+            command: std::env::args().collect::<Vec<String>>().join(" "),
+            operation,
+        }
+    }
+
+    /// append a record for a path, failing to write the log only prints a warning
+    pub(crate) fn record(
+        &self,
+        path: &Path,
+        size: Option<u64>,
+        outcome: Outcome,
+        error: Option<String>,
+    ) {
+        let record = Record {
+            timestamp: Local::now().to_rfc3339(),
+            command: self.command.clone(),
+            operation: self.operation,
+            path: path.to_path_buf(),
+            size,
+            outcome,
+            error,
+        };
+
+        if let Err(write_error) = self.append(&record) {
+            eprintln!(
+                "Warning: failed to write to audit log \"{}\": {}",
+                self.path.display(),
+                write_error
+            );
+        }
+    }
+
+    fn append(&self, record: &Record) -> std::io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        let line = serde_json::to_string(record)?;
+        writeln!(file, "{}", line)
+    }
+}
+
+/// read all records from the audit log, an empty list if there is no log yet
+/// lines that can not be parsed (a write interrupted by a crash or a full disk) are skipped with a warning
+fn read_records(log_path: &Path) -> Result<Vec<Record>, Error> {
+    if !log_path.is_file() {
+        return Ok(Vec::new());
+    }
+    let content = fs::read(log_path).map_err(|e| Error::AuditLogIo(log_path.to_path_buf(), e))?;
+
+    Ok(String::from_utf8_lossy(&content)
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .filter_map(|(number, line)| match serde_json::from_str(line) {
+            Ok(record) => Some(record),
+            Err(e) => {
+                eprintln!(
+                    "Warning: {}, skipping it.",
+                    Error::AuditLogCorrupt(log_path.to_path_buf(), number + 1, e)
+                );
+                None
+            }
+        })
+        .collect())
+}
+
+/// "cargo cache log": print the (filtered) audit log
+pub(crate) fn log_subcmd(cargo_home: &Path, log_config: &ArgMatches<'_>) -> Result<(), Error> {
+    let operation: Option<Operation> = match log_config.value_of("operation") {
+        Some(op) => Some(op.parse().map_err(Error::AuditLogUnknownOperation)?),
+        None => None,
+    };
+
+    let since_date = match log_config.value_of("since") {
+        Some(date) => Some(parse_date(date)?),
+        None => None,
+    };
+
+    let query = log_config.value_of("QUERY").unwrap_or("" /* default */);
+    let re = match Regex::new(query) {
+        Ok(re) => re,
+        Err(_e) => {
+            return Err(Error::QueryRegexFailedParsing(query.to_string()));
+        }
+    };
+
+    let show_command = log_config.is_present("command");
+
+    let records = read_records(&audit_log_path(cargo_home))?;
+
+    let mut table: Vec<Vec<String>> = vec![{
+        let mut header = vec![
+            "Time".to_string(),
+            "Operation".to_string(),
+            "Outcome".to_string(),
+            "Size".to_string(),
+            "Path".to_string(),
+        ];
+        if show_command {
+            header.push("Command".to_string());
+        }
+        header
+    }];
+
+    let matching = records
+        .into_iter()
+        .filter(|record| match operation {
+            Some(op) => record.operation == op,
+            None => true,
+        })
+        .filter(|record| re.is_match(&record.path.display().to_string()));
+
+    for record in matching {
+        // show the time in the local timezone
+        let time = match DateTime::parse_from_rfc3339(&record.timestamp) {
+            Ok(time) => time.with_timezone(&Local).naive_local(),
+            // skip unparsable lines
+            Err(_) => continue,
+        };
+        if let Some(since) = since_date {
+            if time < since {
+                continue;
+            }
+        }
+
+        let mut row = vec![
+            time.format("%Y-%m-%d %H:%M:%S").to_string(),
+            record.operation.to_string(),
+            match record.error {
+                Some(ref message) => format!("{} ({})", record.outcome, message),
+                None => record.outcome.to_string(),
+            },
+            record.size.map_or_else(String::new, |size| {
+                size.file_size(file_size_opts::DECIMAL).unwrap()
+            }),
+            record.path.display().to_string(),
+        ];
+        if show_command {
+            row.push(record.command.clone());
+        }
+        table.push(row);
+    }
+
+    if table.len() == 1 {
+        println!("No matching entries in the audit log.");
+    } else {
+        println!("{}", format_table(&table, 1).trim_end());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod audittests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn operation_roundtrip() {
+        for op in &Operation::ALL {
+            assert_eq!(op.to_string().parse::<Operation>(), Ok(*op));
+            // the audit log uses the same names
+            assert_eq!(
+                serde_json::to_string(op).unwrap(),
+                format!("\"{}\"", op.name())
+            );
+        }
+        assert_eq!("foo".parse::<Operation>(), Err("foo".to_string()));
+    }
+
+    #[test]
+    fn record_and_read() {
+        let cargo_home = PathBuf::from("target/audit_tests/record_and_read");
+        let _ = fs::remove_dir_all(&cargo_home);
+
+        let log = AuditLog::new(&cargo_home, Operation::Trim);
+        log.record(
+            &cargo_home.join("registry").join("src"),
+            Some(42),
+            Outcome::Removed,
+            None,
+        );
+        log.record(
+            &cargo_home.join("git").join("db"),
+            None,
+            Outcome::Failed,
+            Some("permission denied".into()),
+        );
+
+        let records = read_records(&audit_log_path(&cargo_home)).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].operation, Operation::Trim);
+        assert_eq!(records[0].size, Some(42));
+        assert_eq!(records[0].outcome, Outcome::Removed);
+        assert_eq!(records[1].outcome, Outcome::Failed);
+        assert_eq!(records[1].error, Some("permission denied".into()));
+
+        // a truncated line in the middle does not hide the other records
+        let log_path = audit_log_path(&cargo_home);
+        let mut content = fs::read_to_string(&log_path).unwrap();
+        content.insert_str(0, "{\"timestamp\":\"2020-08-01T12:00:00+02:00\",\"comm\n");
+        fs::write(&log_path, content).unwrap();
+        assert_eq!(read_records(&log_path).unwrap(), records);
+    }
+
+    #[test]
+    fn serialized_format() {
+        let record = Record {
+            timestamp: "2020-08-01T12:00:00+02:00".into(),
+            command: "cargo cache trim --limit 1G".into(),
+            operation: Operation::CleanUnref,
+            path: PathBuf::from("/home/user/.cargo/registry/cache/serde-1.0.0.crate"),
+            size: Some(1000),
+            outcome: Outcome::Quarantined,
+            error: None,
+        };
+        assert_eq!(
+            serde_json::to_string(&record).unwrap(),
+            r#"{"timestamp":"2020-08-01T12:00:00+02:00","command":"cargo cache trim --limit 1G","operation":"clean-unref","path":"/home/user/.cargo/registry/cache/serde-1.0.0.crate","size":1000,"outcome":"quarantined"}"#
+        );
+    }
+}
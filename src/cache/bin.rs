@@ -7,13 +7,42 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use std::collections::BTreeSet;
+use std::ffi::OsStr;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use crate::cache::caches::Cache;
+use crate::library::Error;
 
 use rayon::iter::*;
 
+/// binaries inside `${CARGO_HOME}/bin` that belong to rustup, removing them breaks the toolchain
+const RUSTUP_PROXIES: [&str; 14] = [
+    "cargo",
+    "cargo-clippy",
+    "cargo-fmt",
+    "cargo-miri",
+    "clippy-driver",
+    "rls",
+    "rust-analyzer",
+    "rust-gdb",
+    "rust-gdbgui",
+    "rust-lldb",
+    "rustc",
+    "rustdoc",
+    "rustfmt",
+    "rustup",
+];
+
+/// whether a binary is a rustup proxy which must never be removed
+pub(crate) fn is_rustup_proxy(path: &Path) -> bool {
+    match path.file_stem().and_then(OsStr::to_str) {
+        Some(name) => RUSTUP_PROXIES.contains(&name),
+        None => false,
+    }
+}
+
 pub(crate) struct BinaryCache {
     path: PathBuf,
     number_of_files: Option<usize>,
@@ -23,6 +52,15 @@ pub(crate) struct BinaryCache {
 }
 
 impl BinaryCache {
+    /// all binaries except for the rustup proxies
+    pub(crate) fn removable_items(&mut self) -> Vec<PathBuf> {
+        self.items()
+            .iter()
+            .filter(|binary| !is_rustup_proxy(binary))
+            .cloned()
+            .collect()
+    }
+
     pub(crate) fn number_of_files(&mut self) -> usize {
         if self.number_of_files.is_some() {
             self.number_of_files.unwrap()
@@ -113,3 +151,132 @@ impl Cache for BinaryCache {
         self.files().len()
     }
 }
+
+/// drop removed binaries from cargo's install metadata (.crates2.json and .crates.toml) so that
+/// "cargo install" does not consider them installed anymore.
+/// Packages without any binaries left are dropped completely.
+pub(crate) fn forget_installed_binaries(
+    cargo_home: &Path,
+    removed: &[PathBuf],
+) -> Result<(), Error> {
+    let names: BTreeSet<&str> = removed
+        .iter()
+        .filter_map(|binary| binary.file_name().and_then(OsStr::to_str))
+        .collect();
+    if names.is_empty() {
+        return Ok(());
+    }
+    let was_removed = |bin: Option<&str>| matches!(bin, Some(name) if names.contains(name));
+
+    // {"installs": {"<pkgid>": {"bins": ["rg"], ...}}}
+    let crates2 = cargo_home.join(".crates2.json");
+    if crates2.is_file() {
+        let unparsable = |e: String| Error::UnparsableInstallMetadata(crates2.clone(), e);
+        let content = fs::read_to_string(&crates2).map_err(|e| unparsable(e.to_string()))?;
+        let mut metadata: serde_json::Value =
+            serde_json::from_str(&content).map_err(|e| unparsable(e.to_string()))?;
+        if let Some(installs) = metadata
+            .get_mut("installs")
+            .and_then(serde_json::Value::as_object_mut)
+        {
+            for install in installs.values_mut() {
+                if let Some(bins) = install
+                    .get_mut("bins")
+                    .and_then(serde_json::Value::as_array_mut)
+                {
+                    bins.retain(|bin| !was_removed(bin.as_str()));
+                }
+            }
+            *installs = std::mem::take(installs)
+                .into_iter()
+                .filter(|(_, install)| {
+                    let bins_left = install.get("bins").and_then(serde_json::Value::as_array);
+                    !matches!(bins_left, Some(list) if list.is_empty())
+                })
+                .collect();
+        }
+        let updated =
+            serde_json::to_string(&metadata).expect("failed to serialize install metadata");
+        fs::write(&crates2, updated).map_err(|e| Error::InstallMetadataIo(crates2.clone(), e))?;
+    }
+
+    // [v1]
+    // "<pkgid>" = ["rg"]
+    let crates_toml = cargo_home.join(".crates.toml");
+    if crates_toml.is_file() {
+        let unparsable = |e: String| Error::UnparsableInstallMetadata(crates_toml.clone(), e);
+        let content = fs::read_to_string(&crates_toml).map_err(|e| unparsable(e.to_string()))?;
+        let mut metadata: toml::Value =
+            toml::from_str(&content).map_err(|e| unparsable(e.to_string()))?;
+        if let Some(v1) = metadata.get_mut("v1").and_then(toml::Value::as_table_mut) {
+            for bins in v1.iter_mut().filter_map(|(_, bins)| bins.as_array_mut()) {
+                bins.retain(|bin| !was_removed(bin.as_str()));
+            }
+            *v1 = std::mem::take(v1)
+                .into_iter()
+                .filter(|(_, bins)| !matches!(bins.as_array(), Some(list) if list.is_empty()))
+                .collect();
+        }
+        let updated = toml::to_string(&metadata).map_err(|e| unparsable(e.to_string()))?;
+        fs::write(&crates_toml, updated)
+            .map_err(|e| Error::InstallMetadataIo(crates_toml.clone(), e))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod bintests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn rustup_proxies() {
+        assert!(is_rustup_proxy(Path::new("/home/user/.cargo/bin/cargo")));
+        assert!(is_rustup_proxy(Path::new(
+            "/home/user/.cargo/bin/rustc.exe"
+        )));
+        assert!(!is_rustup_proxy(Path::new(
+            "/home/user/.cargo/bin/cargo-cache"
+        )));
+    }
+
+    #[test]
+    fn forget_binaries() {
+        let cargo_home = PathBuf::from("target/bin_tests/forget_binaries");
+        let _ = fs::remove_dir_all(&cargo_home);
+        fs::create_dir_all(&cargo_home).unwrap();
+
+        fs::write(
+            cargo_home.join(".crates2.json"),
+            r#"{"installs":{"ripgrep 12.1.1 (registry+https://github.com/rust-lang/crates.io-index)":{"version_req":null,"bins":["rg"],"profile":"release"},"tool 1.0.0 (registry+https://github.com/rust-lang/crates.io-index)":{"bins":["tool-a","tool-b"]}}}"#,
+        )
+        .unwrap();
+        fs::write(
+            cargo_home.join(".crates.toml"),
+            "[v1]\n\"ripgrep 12.1.1 (registry+https://github.com/rust-lang/crates.io-index)\" = [\"rg\"]\n\"tool 1.0.0 (registry+https://github.com/rust-lang/crates.io-index)\" = [\"tool-a\", \"tool-b\"]\n",
+        )
+        .unwrap();
+
+        let bin_dir = cargo_home.join("bin");
+        forget_installed_binaries(&cargo_home, &[bin_dir.join("rg"), bin_dir.join("tool-a")])
+            .unwrap();
+
+        let crates2: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(cargo_home.join(".crates2.json")).unwrap())
+                .unwrap();
+        assert_eq!(
+            crates2,
+            serde_json::json!({"installs": {"tool 1.0.0 (registry+https://github.com/rust-lang/crates.io-index)": {"bins": ["tool-b"]}}})
+        );
+
+        let crates_toml: toml::Value =
+            toml::from_str(&fs::read_to_string(cargo_home.join(".crates.toml")).unwrap()).unwrap();
+        let v1 = crates_toml["v1"].as_table().unwrap();
+        assert_eq!(v1.len(), 1);
+        assert_eq!(
+            v1["tool 1.0.0 (registry+https://github.com/rust-lang/crates.io-index)"],
+            toml::Value::Array(vec![toml::Value::String("tool-b".into())])
+        );
+    }
+}
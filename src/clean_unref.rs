@@ -10,14 +10,21 @@
 // remove all crates from a cache that are not referenced by a Cargo lockfile
 
 use std::ffi::OsStr;
-use std::path::PathBuf;
+use std::fs;
+use std::path::{Path, PathBuf};
 
+use crate::audit::AuditLog;
 use crate::cache::caches::*;
 use crate::cache::*;
+use crate::confirm::{confirm_removal, Confirm, PlannedRemoval};
 use crate::library::*;
 use crate::library::{CargoCachePaths, Error};
+use crate::lockfile::{lockfile_of_manifest, required_items_from_lockfiles};
 use crate::remove::*;
+use crate::trash::Trash;
 use cargo_metadata::{CargoOpt, MetadataCommand};
+use clap::ArgMatches;
+use walkdir::WalkDir;
 
 // the source of a crate inside the cargo cache can be represented in form of
 // an extracted .crate or a checked out git repository
@@ -29,6 +36,7 @@ enum SourceKind {
 }
 
 // get the path contained in a SourceKind
+#[allow(dead_code)]
 impl SourceKind {
     fn inner(self) -> PathBuf {
         match self {
@@ -37,177 +45,434 @@ impl SourceKind {
     }
 }
 
-fn find_crate_name_git(toml_path: &PathBuf, cargo_home: &PathBuf) -> Option<SourceKind> {
+fn find_crate_name_git(toml_path: &Path, cargo_home: &Path) -> Result<SourceKind, Error> {
     // ~/.cargo/git/checkouts/home-fb9469891e5cfbe6/3a6eccd/cargo.toml  => ~/.cargo/git/checkouts/home-fb9469891e5cfbe6/3a6eccd/
+    let malformed = || Error::MalformedGitCheckoutPath(toml_path.to_path_buf());
 
-    // get the segments of the path
-    let v: Vec<&OsStr> = toml_path.iter().collect();
+    // get the segments of the path, relative to the cargo home so that a "checkouts"
+    // directory somewhere above the cargo home does not confuse us
+    let v: Vec<&OsStr> = toml_path
+        .strip_prefix(cargo_home)
+        .map_err(|_| malformed())?
+        .iter()
+        .collect();
 
-    // if we could not find a position, return None
-    let checkouts_pos = v.iter().position(|i| i == &"checkouts")?;
+    let checkouts_pos = v
+        .iter()
+        .position(|i| i == &"checkouts")
+        .ok_or_else(malformed)?;
 
     // assuming git:
     // git checkouts repo-name ref
-    let path_segments = &v[(checkouts_pos - 1)..(checkouts_pos + 3)];
+    let path_segments = v
+        .get(checkouts_pos.checked_sub(1).ok_or_else(malformed)?..(checkouts_pos + 3))
+        .ok_or_else(malformed)?;
 
-    let mut path = cargo_home.clone();
+    let mut path = cargo_home.to_path_buf();
     path_segments.iter().for_each(|p| path.push(p));
 
-    Some(SourceKind::Git(path))
+    Ok(SourceKind::Git(path))
 }
 
-fn find_crate_name_crate(toml_path: &PathBuf, cargo_home: &PathBuf) -> Option<SourceKind> {
+fn find_crate_name_crate(toml_path: &Path, cargo_home: &Path) -> Result<SourceKind, Error> {
     //  ~/.cargo/registry/src/github.com-1ecc6299db9ec823/winapi-0.3.8/Cargo.toml => ~/.cargo/registry/src/github.com-1ecc6299db9ec823/winapi-0.3.8/
+    let malformed = || Error::MalformedRegistrySourcePath(toml_path.to_path_buf());
 
-    let v: Vec<&OsStr> = toml_path.iter().collect();
-
-    // if we could not find a position, return None
-    let registry_pos = v.iter().position(|i| i == &"registry")?;
+    let v: Vec<&OsStr> = toml_path
+        .strip_prefix(cargo_home)
+        .map_err(|_| malformed())?
+        .iter()
+        .collect();
 
-    let path_segments = &v[(registry_pos)..(registry_pos + 4)];
-    let mut path = cargo_home.clone();
+    let registry_pos = v
+        .iter()
+        .position(|i| i == &"registry")
+        .ok_or_else(malformed)?;
+
+    // registry src registry-name crate-version
+    let path_segments = v
+        .get(registry_pos..(registry_pos + 4))
+        .ok_or_else(malformed)?;
+    let mut path = cargo_home.to_path_buf();
     path_segments.iter().for_each(|p| path.push(p));
 
-    Some(SourceKind::Crate(path))
+    Ok(SourceKind::Crate(path))
 }
 
-#[allow(clippy::too_many_arguments)]
-pub(crate) fn clean_unref(
+/// a dependency whose source lives inside the cargo home
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct RequiredPackage {
+    pub(crate) name: String,
+    pub(crate) version: String,
+    /// "registry+<index url>" or "git+<repo url>#<rev>"
+    pub(crate) source: String,
+    /// the .crate archive or bare git repo inside the cargo home
+    pub(crate) path: PathBuf,
+    /// checksum of the .crate archive, if known
+    pub(crate) checksum: Option<String>,
+}
+
+/// the items inside the cargo home that a crate needs to build
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct RequiredItems {
+    /// all dependencies that originate from the cargo home
+    pub(crate) packages: Vec<RequiredPackage>,
+    /// .crate archives inside the registry cache
+    pub(crate) crates: Vec<PathBuf>,
+    /// bare git repos inside git/db
+    pub(crate) git_repos: Vec<PathBuf>,
+    /// extracted .crate archives inside registry/src
+    pub(crate) sources: Vec<PathBuf>,
+    /// git checkouts inside git/checkouts
+    pub(crate) checkouts: Vec<PathBuf>,
+    /// the entries of the crates inside the cache of the registry index
+    pub(crate) index_entries: Vec<PathBuf>,
+    /// sources inside the cargo home that we could not map to a cache item, these are kept
+    pub(crate) unknown: Vec<PathBuf>,
+}
+
+/// get the path of the entry of a crate inside the cache of a registry index
+/// this mirrors the layout of the index itself:
+/// "a" => 1/a, "ab" => 2/ab, "abc" => 3/a/abc, "serde" => se/rd/serde
+pub(crate) fn index_cache_entry(index_dir: &Path, crate_name: &str) -> PathBuf {
+    let name = crate_name.to_lowercase();
+    let mut path = index_dir.join(".cache");
+    match name.len() {
+        1 => path.push("1"),
+        2 => path.push("2"),
+        3 => {
+            path.push("3");
+            path.push(&name[..1]);
+        }
+        _ => {
+            path.push(&name[..2]);
+            path.push(&name[2..4]);
+        }
+    }
+    path.push(&name);
+    path
+}
+
+/// read a file that lists one manifest per line, empty lines and lines starting with '#' are ignored
+/// relative paths are relative to the directory of the list
+fn read_manifest_list(list: &Path) -> Result<Vec<PathBuf>, Error> {
+    let content =
+        fs::read_to_string(list).map_err(|e| Error::ManifestListIo(list.to_path_buf(), e))?;
+    let base = list.parent().unwrap_or_else(|| Path::new(""));
+
+    Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| base.join(line))
+        .collect())
+}
+
+/// find all workspaces below a directory
+/// once we found a Cargo.toml we don't descend any further since "cargo metadata" on the
+/// topmost manifest already covers all members of a workspace
+/// target directories, hidden directories and the cargo home itself are skipped
+pub(crate) fn scan_for_manifests(dir: &Path, cargo_home: &Path) -> Vec<PathBuf> {
+    let mut manifests = Vec::new();
+    let mut walker = WalkDir::new(dir)
+        .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+        .into_iter();
+
+    while let Some(next) = walker.next() {
+        let entry = match next {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+        if !entry.file_type().is_dir() {
+            continue;
+        }
+        let path = entry.path();
+        let hidden = entry.depth() > 0 && entry.file_name().to_string_lossy().starts_with('.');
+        if hidden || entry.file_name() == "target" || path.starts_with(cargo_home) {
+            walker.skip_current_dir();
+            continue;
+        }
+        let manifest = path.join("Cargo.toml");
+        if manifest.is_file() {
+            manifests.push(manifest);
+            walker.skip_current_dir();
+        }
+    }
+    manifests
+}
+
+/// collect the manifests passed via "--manifest-path" (can be given several times),
+/// "--manifest-list <file>" and "--scan <dir>"
+/// if none of these are given, try to find a close manifest somewhere
+pub(crate) fn manifests_from_cli(
+    cargo_home: &Path,
+    config: &ArgMatches<'_>,
+) -> Result<Vec<PathBuf>, Error> {
+    let mut manifests: Vec<PathBuf> = config
+        .values_of("manifest-path")
+        .map(|paths| paths.map(PathBuf::from).collect())
+        .unwrap_or_default();
+
+    if let Some(list) = config.value_of("manifest-list") {
+        manifests.extend(read_manifest_list(Path::new(list))?);
+    }
+
+    if let Some(dir) = config.value_of("scan") {
+        let found = scan_for_manifests(Path::new(dir), cargo_home);
+        if found.is_empty() {
+            // an empty set of manifests would mean that we remove everything
+            return Err(Error::ScanNoManifests(PathBuf::from(dir)));
+        }
+        manifests.extend(found);
+    }
+
+    if manifests.is_empty() {
+        manifests.push(crate::local::get_manifest()?);
+    }
+
+    manifests.sort();
+    manifests.dedup();
+    Ok(manifests)
+}
+
+/// get the items a set of manifests needs, the result is the union of the dependencies of all manifests
+/// if offline is set, the Cargo.lock files are parsed directly instead of running "cargo metadata"
+pub(crate) fn required_items(
     cargo_cache_paths: &CargoCachePaths,
-    manifest_path: &Option<&str>,
-    checkouts_cache: &mut git_checkouts::GitCheckoutCache,
-    bare_repos_cache: &mut git_bare_repos::GitRepoCache,
-    registry_pkg_caches: &mut registry_pkg_cache::RegistryPkgCaches,
-    registry_sources_caches: &mut registry_sources::RegistrySourceCaches,
-    dry_run: bool,
-    size_changed: &mut bool,
-) -> Result<(), Error> {
-    // first get a list of all dependencies of the project
+    manifests: &[PathBuf],
+    offline: bool,
+) -> Result<RequiredItems, Error> {
+    if offline {
+        let mut lockfiles = manifests
+            .iter()
+            .map(|manifest| lockfile_of_manifest(manifest))
+            .collect::<Result<Vec<PathBuf>, Error>>()?;
+        lockfiles.sort();
+        lockfiles.dedup();
+        required_items_from_lockfiles(cargo_cache_paths, &lockfiles)
+    } else {
+        required_items_from_metadata(cargo_cache_paths, manifests)
+    }
+}
 
+/// resolve the dependencies of the manifests via "cargo metadata" and map them to the
+/// .crate archives and bare git repos inside the cargo home that they originate from
+fn required_items_from_metadata(
+    cargo_cache_paths: &CargoCachePaths,
+    manifests: &[PathBuf],
+) -> Result<RequiredItems, Error> {
     let cargo_home = &cargo_cache_paths.cargo_home;
 
-    // if "--manifest-path" is passed to the subcommand, take this
-    // if it is not passed, try to find a close manifest somewhere
-    let manifest = match manifest_path {
-        Some(path_str) => PathBuf::from(path_str),
-        None => crate::local::get_manifest()?,
-    };
+    // resolve everything before we touch anything, if a single manifest fails
+    // we would otherwise remove the dependencies of that project
+    let mut dependencies = Vec::new();
+    for manifest in manifests {
+        let metadata = MetadataCommand::new()
+            .manifest_path(manifest)
+            .features(CargoOpt::AllFeatures)
+            .exec()
+            .map_err(|e| Error::UnparsableManifest(manifest.clone(), e))?;
+        dependencies.extend(metadata.packages);
+    }
 
-    let metadata = MetadataCommand::new()
-        .manifest_path(&manifest)
-        .features(CargoOpt::AllFeatures)
-        .exec()
-        .map_err(|e| Error::UnparsableManifest(manifest, e))?;
+    let mut required = RequiredItems::default();
 
-    let dependencies = metadata.packages;
+    let mut unknown = Vec::new();
 
     // get the path inside the CARGO_HOME of the source of the dependency
-    #[allow(clippy::filter_map)]
-    let required_packages = dependencies
+    dependencies
         .iter()
-        .map(|pkg| &pkg.manifest_path)
         // we only care about tomls that are not local, i.e. tomls that are inside the $CARGO_HOME
-        .filter(|toml_path| toml_path.starts_with(&cargo_home))
+        .filter(|pkg| pkg.manifest_path.starts_with(&cargo_home))
         // map the manifest paths to paths to the roots of the crates inside the cargo_home
-        .map(|toml_path| {
-            if toml_path.starts_with(&cargo_cache_paths.git_checkouts) {
-                find_crate_name_git(toml_path, cargo_home).unwrap_or_else(|| {
-                    panic!("Failed to find 'checkouts' in {} ", toml_path.display())
-                })
+        .filter_map(|pkg| {
+            let toml_path = &pkg.manifest_path;
+            let sourcekind = if toml_path.starts_with(&cargo_cache_paths.git_checkouts) {
+                find_crate_name_git(toml_path, cargo_home)
             } else if toml_path.starts_with(&cargo_cache_paths.registry_sources) {
-                find_crate_name_crate(toml_path, cargo_home).unwrap_or_else(|| {
-                    panic!("Failed to find 'registry' in {} ", toml_path.display())
-                })
+                find_crate_name_crate(toml_path, cargo_home)
             } else {
-                // if we find a source path that is neither a git nor a crate dep, this probably indicates a bug
-                panic!("Failed to parse toml path: '{}'", toml_path.display());
+                // vendored sources or [patch]es that live inside the cargo home
+                Err(Error::UnknownSourcePath(toml_path.clone()))
+            };
+            match sourcekind {
+                Ok(kind) => Some((pkg, kind)),
+                Err(error) => {
+                    // we don't know what this belongs to, so don't remove anything that contains it
+                    eprintln!("Warning: {}, keeping it.", error);
+                    unknown.push(toml_path.clone());
+                    None
+                }
             }
         })
         // we need to map the git repo checkouts to bare git repos
         // and the source-checkouts to pkg cache archives!
-        .map(|sourcekind| match sourcekind {
-            SourceKind::Crate(registry_src_path) => {
-                // ~/.cargo/registry/src/github.com-1ecc6299db9ec823/semver-0.9.0
-                // =>
-                // ~/.cargo/registry/cache/github.com-1ecc6299db9ec823/semver-0.9.0.crate
-                let path = registry_src_path.iter().collect::<Vec<&OsStr>>();
-                let package_name = path[path.len() - 1];
-                let registry = path[path.len() - 2];
-                let mut registry_cache_path = cargo_cache_paths.registry_pkg_cache.clone();
-                // we need to push the registry index as well
-                registry_cache_path.push(registry);
-                // this can probably be
-                // can't use .set_extension() here because "cratename-0.1.3" will detect the ".3" as extension
-                // and change it
-                registry_cache_path.push(format!(
-                    "{}{}",
-                    package_name.to_os_string().into_string().unwrap(),
-                    ".crate"
-                ));
-                SourceKind::Crate(registry_cache_path)
-            }
-            SourceKind::Git(gitpath) => {
-                // ~/.cargo/git/checkouts/cargo-e7ff1db891893a9e/258c896
-                // =>
-                // ~/.cargo/git/db/cargo-e7ff1db891893a9e
-                let mut repo_name = gitpath;
-                let _ = repo_name.pop(); // remove /258c896
-                let repo_name = repo_name.iter().last().unwrap(); // cargo-e7ff1db891893a9e
-
-                let mut db_name = cargo_cache_paths.git_repos_bare.clone();
-                db_name.push(repo_name);
-                // ~/.cargo/git/db/cargo-e7ff1db891893a9e
-                SourceKind::Git(db_name)
-            }
+        .for_each(|(pkg, sourcekind)| {
+            let path = match sourcekind {
+                SourceKind::Crate(registry_src_path) => {
+                    required.sources.push(registry_src_path.clone());
+                    // ~/.cargo/registry/src/github.com-1ecc6299db9ec823/semver-0.9.0
+                    // =>
+                    // ~/.cargo/registry/cache/github.com-1ecc6299db9ec823/semver-0.9.0.crate
+                    let path = registry_src_path.iter().collect::<Vec<&OsStr>>();
+                    let package_name = path[path.len() - 1];
+                    let registry = path[path.len() - 2];
+                    let mut registry_cache_path = cargo_cache_paths.registry_pkg_cache.clone();
+                    // we need to push the registry index as well
+                    registry_cache_path.push(registry);
+                    // this can probably be
+                    // can't use .set_extension() here because "cratename-0.1.3" will detect the ".3" as extension
+                    // and change it
+                    registry_cache_path.push(format!(
+                        "{}{}",
+                        package_name.to_string_lossy(),
+                        ".crate"
+                    ));
+                    required.crates.push(registry_cache_path.clone());
+                    required.index_entries.push(index_cache_entry(
+                        &cargo_cache_paths.registry_index.join(registry),
+                        &pkg.name,
+                    ));
+                    registry_cache_path
+                }
+                SourceKind::Git(gitpath) => {
+                    required.checkouts.push(gitpath.clone());
+                    // ~/.cargo/git/checkouts/cargo-e7ff1db891893a9e/258c896
+                    // =>
+                    // ~/.cargo/git/db/cargo-e7ff1db891893a9e
+                    let mut db_name = cargo_cache_paths.git_repos_bare.clone();
+                    // cargo-e7ff1db891893a9e
+                    if let Some(repo_name) = gitpath.parent().and_then(Path::file_name) {
+                        db_name.push(repo_name);
+                    }
+                    // ~/.cargo/git/db/cargo-e7ff1db891893a9e
+                    required.git_repos.push(db_name.clone());
+                    db_name
+                }
+            };
+            required.packages.push(RequiredPackage {
+                name: pkg.name.clone(),
+                version: pkg.version.to_string(),
+                source: pkg
+                    .source
+                    .as_ref()
+                    .map_or_else(String::new, |source| source.repr.clone()),
+                path,
+                checksum: None,
+            });
         });
 
-    // now we have a list of all cargo-home-entries a crate needs to build
-    // we can walk the cargo-cache and remove everything that is not referenced;
-    // remove: git checkouts, registry sources
-    // keep, if referenced: registry pkg cache, bare git repos
-
-    // debug
-    // println!("required packages:");
-    // required_packages.inspect(|toml| println!("{:?}", toml));
-
-    // remove the git checkout cache since it is not needed
-    remove_file(
-        &cargo_cache_paths.git_checkouts,
-        dry_run,
-        size_changed,
-        None,
-        &DryRunMessage::Default,
-        Some(checkouts_cache.total_size()),
-    );
-    // invalidate cache
-    let _ = &checkouts_cache.invalidate();
-
-    // remove the registry_sources_cache as well
-    remove_file(
-        &cargo_cache_paths.registry_sources,
-        dry_run,
-        size_changed,
-        None,
-        &DryRunMessage::Default,
-        Some(registry_sources_caches.total_size()),
-    );
-    // invalidate cache
-    let _ = &registry_sources_caches.invalidate();
+    required.packages.sort();
+    required.packages.dedup();
+    required.crates.sort();
+    required.crates.dedup();
+    required.git_repos.sort();
+    required.git_repos.dedup();
+    required.sources.sort();
+    required.sources.dedup();
+    required.checkouts.sort();
+    required.checkouts.dedup();
+    required.index_entries.sort();
+    required.index_entries.dedup();
+    required.unknown = unknown;
+
+    Ok(required)
+}
 
-    let (required_crates, required_git_repos): (Vec<SourceKind>, Vec<SourceKind>) =
-        required_packages.partition(|dep| match dep {
-            SourceKind::Crate(_) => true,
-            SourceKind::Git(_) => false,
-        });
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn clean_unref(
+    cargo_cache_paths: &CargoCachePaths,
+    manifests: &[PathBuf],
+    offline: bool,
+    aggressive: bool,
+    checkouts_cache: &mut git_checkouts::GitCheckoutCache,
+    bare_repos_cache: &mut git_bare_repos::GitRepoCache,
+    registry_pkg_caches: &mut registry_pkg_cache::RegistryPkgCaches,
+    registry_sources_caches: &mut registry_sources::RegistrySourceCaches,
+    dry_run: bool,
+    confirm: Confirm,
+    size_changed: &mut bool,
+    trash: Option<&Trash>,
+    audit_log: &AuditLog,
+) -> Result<(), Error> {
+    // first get a list of all dependencies of the project
+    let required = required_items(cargo_cache_paths, manifests, offline)?;
+    if manifests.len() > 1 {
+        println!("Keeping the dependencies of {} manifests.", manifests.len());
+    }
 
-    // extract the paths from the SouceKinds
-    let mut required_crates = required_crates.into_iter().map(SourceKind::inner);
+    // now we have a list of all cargo-home-entries a crate needs to build
+    // we can walk the cargo-cache and remove everything that is not referenced;
+    // keep, if referenced: git checkouts, registry sources, registry pkg cache, bare git repos
+    // in aggressive mode, the git checkouts and registry sources are removed completely
+    // since they can be rebuilt from the bare repos and .crate archives
+
+    // collect everything we are going to remove first so that we can ask before removing it
+    let mut plan: Vec<PlannedRemoval> = Vec::new();
+
+    if aggressive {
+        // remove the git checkout cache since it is not needed
+        plan.push(PlannedRemoval::new(
+            cargo_cache_paths.git_checkouts.clone(),
+            &Component::GitRepos.to_string(),
+            checkouts_cache.total_size(),
+        ));
+
+        // remove the registry_sources_cache as well
+        plan.push(PlannedRemoval::new(
+            cargo_cache_paths.registry_sources.clone(),
+            &Component::RegistrySources.to_string(),
+            registry_sources_caches.total_size(),
+        ));
+    } else {
+        let checkouts = checkouts_cache
+            .items()
+            .iter()
+            .filter(|checkout| !required.checkouts.contains(checkout))
+            .map(|checkout| (checkout, Component::GitRepos));
+        let sources = registry_sources_caches
+            .items()
+            .iter()
+            .filter(|source| !required.sources.contains(source))
+            .map(|source| (source, Component::RegistrySources));
+
+        plan.extend(
+            checkouts
+                .chain(sources)
+                // items that contain a source we could not identify are kept as well
+                .filter(|(item, _)| {
+                    !required
+                        .unknown
+                        .iter()
+                        .any(|unknown| unknown.starts_with(item))
+                })
+                .map(|(item, component)| {
+                    PlannedRemoval::new(item.clone(), &component.to_string(), size_of_path(item))
+                }),
+        );
+    }
 
-    let mut required_git_repos = required_git_repos.into_iter().map(SourceKind::inner);
     // for the bare_repos_cache and registry_package_cache,
     // remove all items but the ones that are referenced
 
-    let bare_repos = bare_repos_cache.items();
+    // filter git repos
+    plan.extend(
+        bare_repos_cache
+            .items()
+            .iter()
+            .filter(|repo_in_cache|
+                // in the iterator, only keep crates that are not contained in
+                // our dependency list and remove them
+
+                !required.git_repos.contains(repo_in_cache))
+            .map(|repo| {
+                PlannedRemoval::new(
+                    repo.clone(),
+                    &Component::GitDB.to_string(),
+                    size_of_path(repo),
+                )
+            }),
+    );
 
     // get all .crates found in the cache (we need to check all subcaches)
     // @TODO add method to get all .crates of all caches via single method?
@@ -217,45 +482,44 @@ pub(crate) fn clean_unref(
         crates.extend(cache.files());
     }
 
-    // filter and remove git repos
-    bare_repos
-        .iter()
-        .filter(|repo_in_cache|
-            // in the iterator, only keep crates that are not contained in
-            // our dependency list and remove them
-
-            !required_git_repos.any(|repo| repo == **repo_in_cache))
-        .for_each(|repo| {
-            /* remove the repo */
-            remove_file(
-                repo,
-                dry_run,
-                size_changed,
-                None,
-                &DryRunMessage::Default,
-                Some(size_of_path(repo)),
-            );
-        });
+    // filter crate archives
+    plan.extend(
+        crates
+            .iter()
+            .filter(|crate_in_cache|
+                // in the iterator, only keep crates that are not contained in
+                // our dependency list and remove them
+
+                !required.crates.contains(crate_in_cache))
+            .map(|krate| {
+                PlannedRemoval::new(
+                    (*krate).clone(),
+                    &Component::RegistryCrateCache.to_string(),
+                    size_of_path(krate),
+                )
+            }),
+    );
 
-    // filter and remove crate archives
-    crates
-        .iter()
-        .filter(|crate_in_cache|
-            // in the iterator, only keep crates that are not contained in
-            // our dependency list and remove them
-
-            !required_crates.any(|repo| &repo == **crate_in_cache))
-        .for_each(|krate| {
-            /* remove the crate */
-            remove_file(
-                krate,
-                dry_run,
-                size_changed,
-                None,
-                &DryRunMessage::Default,
-                Some(size_of_path(krate)),
-            );
-        });
+    confirm_removal(confirm, &plan)?;
+
+    for item in &plan {
+        remove_file(
+            &item.path,
+            dry_run,
+            size_changed,
+            None,
+            &DryRunMessage::Default,
+            Some(item.size),
+            trash,
+            audit_log,
+        );
+    }
+
+    // invalidate caches
+    let _ = &checkouts_cache.invalidate();
+    let _ = &registry_sources_caches.invalidate();
+    bare_repos_cache.invalidate();
+    registry_pkg_caches.invalidate();
 
     Ok(())
 }
@@ -274,13 +538,82 @@ mod clitests {
         assert_eq!(sk_git.inner(), PathBuf::from("def"));
     }
 
+    #[test]
+    fn scan_finds_topmost_manifests() {
+        let root = PathBuf::from("target/clean_unref_tests/scan_finds_topmost_manifests");
+        let _ = fs::remove_dir_all(&root);
+        let cargo_home = root.join(".cargo");
+        for dir in &[
+            root.join("a"),
+            root.join("a").join("member"), // workspace member, covered by a
+            root.join("b").join("nested"),
+            root.join("b").join("target").join("package").join("c"), // inside target dir
+            root.join(".hidden"),
+            cargo_home.join("registry").join("src").join("r").join("d"),
+        ] {
+            fs::create_dir_all(dir).unwrap();
+            fs::write(dir.join("Cargo.toml"), "").unwrap();
+        }
+
+        let manifests = scan_for_manifests(&root, &cargo_home);
+        assert_eq!(
+            manifests,
+            vec![
+                root.join("a").join("Cargo.toml"),
+                root.join("b").join("nested").join("Cargo.toml"),
+            ]
+        );
+    }
+
+    #[test]
+    fn manifest_list() {
+        let root = PathBuf::from("target/clean_unref_tests/manifest_list");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let list = root.join("manifests.txt");
+        fs::write(
+            &list,
+            "# projects on this machine\n\nfoo/Cargo.toml\n  /abs/bar/Cargo.toml  \n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            read_manifest_list(&list).unwrap(),
+            vec![
+                root.join("foo").join("Cargo.toml"),
+                PathBuf::from("/abs/bar/Cargo.toml"),
+            ]
+        );
+    }
+
+    #[test]
+    fn index_cache_entries() {
+        let index = PathBuf::from("index/github.com-1ecc6299db9ec823");
+        assert_eq!(
+            index_cache_entry(&index, "a"),
+            index.join(".cache").join("1").join("a")
+        );
+        assert_eq!(
+            index_cache_entry(&index, "cc"),
+            index.join(".cache").join("2").join("cc")
+        );
+        assert_eq!(
+            index_cache_entry(&index, "syn"),
+            index.join(".cache").join("3").join("s").join("syn")
+        );
+        assert_eq!(
+            index_cache_entry(&index, "Inflector"),
+            index.join(".cache").join("in").join("fl").join("inflector")
+        );
+    }
+
     #[test]
     fn crate_name_git_some() {
         let toml_path =
             PathBuf::from(".cargo/git/checkouts/home-fb9469891e5cfbe6/3a6eccd/Cargo.toml");
         let cargo_home = PathBuf::from(".cargo/");
 
-        let name = find_crate_name_git(&toml_path, &cargo_home);
+        let name = find_crate_name_git(&toml_path, &cargo_home).ok();
 
         assert_eq!(
             name,
@@ -292,14 +625,27 @@ mod clitests {
 
     #[test]
     fn crate_name_git_none() {
-        // pare failure should return None
+        // parse failure should return an error
         let toml_path =
             PathBuf::from(".cargo/git/failuretoparse/home-fb9469891e5cfbe6/3a6eccd/Cargo.toml");
         let cargo_home = PathBuf::from(".cargo/");
 
-        let name = find_crate_name_git(&toml_path, &cargo_home);
+        match find_crate_name_git(&toml_path, &cargo_home) {
+            Err(Error::MalformedGitCheckoutPath(path)) => assert_eq!(path, toml_path),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn crate_name_git_truncated() {
+        // a manifest directly inside git/checkouts must not make us index out of bounds
+        let toml_path = PathBuf::from(".cargo/git/checkouts/Cargo.toml");
+        let cargo_home = PathBuf::from(".cargo/");
 
-        assert_eq!(name, None);
+        match find_crate_name_git(&toml_path, &cargo_home) {
+            Err(Error::MalformedGitCheckoutPath(path)) => assert_eq!(path, toml_path),
+            other => panic!("unexpected result: {:?}", other),
+        }
     }
 
     #[test]
@@ -309,7 +655,7 @@ mod clitests {
         );
         let cargo_home = PathBuf::from(".cargo/");
 
-        let name = find_crate_name_crate(&toml_path, &cargo_home);
+        let name = find_crate_name_crate(&toml_path, &cargo_home).ok();
 
         assert_eq!(
             name,
@@ -321,14 +667,33 @@ mod clitests {
 
     #[test]
     fn crate_name_crate_none() {
-        // parse failure should return None
+        // parse failure should return an error
         let toml_path = PathBuf::from(
             ".cargo/AAAAAAHH/src/github.com-1ecc6299db9ec823/winapi-0.3.8/Cargo.toml",
         );
         let cargo_home = PathBuf::from(".cargo/");
 
-        let name = find_crate_name_crate(&toml_path, &cargo_home);
+        match find_crate_name_crate(&toml_path, &cargo_home) {
+            Err(Error::MalformedRegistrySourcePath(path)) => assert_eq!(path, toml_path),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn crate_name_crate_registry_above_cargo_home() {
+        // a "registry" directory above the cargo home must not be picked up
+        let toml_path = PathBuf::from(
+            "registry/.cargo/registry/src/github.com-1ecc6299db9ec823/winapi-0.3.8/Cargo.toml",
+        );
+        let cargo_home = PathBuf::from("registry/.cargo/");
+
+        let name = find_crate_name_crate(&toml_path, &cargo_home).ok();
 
-        assert_eq!(name, None,);
+        assert_eq!(
+            name,
+            Some(SourceKind::Crate(PathBuf::from(
+                "registry/.cargo/registry/src/github.com-1ecc6299db9ec823/winapi-0.3.8/",
+            ))),
+        );
     }
 }
@@ -12,6 +12,8 @@ use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 
 use rustc_tools_util::*;
 
+use crate::audit::Operation;
+
 /// generates the version info with what we have in the build.rs
 pub(crate) fn get_version() -> String {
     // remove the "cargo-cache" since CLAP already adds that by itself
@@ -31,7 +33,7 @@ pub(crate) fn gen_clap<'a>() -> ArgMatches<'a> {
         .help("List all found directory paths");
 
     let remove_dir = Arg::with_name("remove-dir").short("r").long("remove-dir")
-        .help("Remove directories, accepted values: all,git-db,git-repos,\nregistry-sources,registry-crate-cache,registry-index,registry")
+        .help("Remove directories, accepted values: all,git-db,git-repos,\nregistry-sources,registry-crate-cache,registry-index,registry,\nbin (installed binaries except for the rustup proxies, not part of \"all\")")
         .takes_value(true)
         .value_name("dir1,dir2,dir3");
 
@@ -85,8 +87,7 @@ pub(crate) fn gen_clap<'a>() -> ArgMatches<'a> {
     let remove_if_older = Arg::with_name("remove-if-older-than")
         .short("o")
         .long("remove-if-older-than")
-        .help("Removes items older than specified date: YYYY.MM.DD or HH:MM:SS")
-        .conflicts_with("remove-if-younger-than") // fix later
+        .help("Removes items older than specified date: YYYY.MM.DD, YYYY-MM-DD, HH:MM:SS, ISO 8601 or relative (30d, 2w, 6months)")
         .requires("remove-dir")
         .takes_value(true)
         .value_name("date");
@@ -94,12 +95,32 @@ pub(crate) fn gen_clap<'a>() -> ArgMatches<'a> {
     let remove_if_younger = Arg::with_name("remove-if-younger-than")
         .short("y")
         .long("remove-if-younger-than")
-        .help("Removes items younger than the specified date: YYYY.MM.DD or HH:MM:SS")
-        .conflicts_with("remove-if-older-than") // fix later
+        .help("Removes items younger than the specified date (same formats as -o), together with -o: items used between both dates")
         .requires("remove-dir")
         .takes_value(true)
         .value_name("date");
 
+    let outside_range = Arg::with_name("outside-range")
+        .long("outside-range")
+        .help("together with -o and -y: remove the items used before the date of -o or after the date of -y instead")
+        .requires_all(&["remove-if-older-than", "remove-if-younger-than"]);
+
+    let time_source = Arg::with_name("time-source")
+        .long("time-source")
+        .help("the timestamp that tells when an item was last used: access, modification or status change time or the newest of them")
+        .takes_value(true)
+        .possible_values(&["atime", "mtime", "ctime", "newest"])
+        .default_value("atime")
+        .value_name("SOURCE");
+
+    let quarantine = Arg::with_name("quarantine")
+        .long("quarantine")
+        .help("Move removed items into a trash inside the cargo home instead of deleting them");
+
+    let yes = Arg::with_name("yes")
+        .long("yes")
+        .help("Don't ask for confirmation before removing items");
+
     let debug = Arg::with_name("debug")
         .long("debug")
         .help("print some debug stats")
@@ -143,12 +164,31 @@ pub(crate) fn gen_clap<'a>() -> ArgMatches<'a> {
     // </query>
 
     //<local>
+    let local_scan = Arg::with_name("scan")
+        .long("scan")
+        .help("list the target dirs of all workspaces found below this directory")
+        .takes_value(true)
+        .value_name("DIR");
+
+    let local_order = Arg::with_name("sort")
+        .short("s")
+        .long("sort-by")
+        .help("sort the target dirs found by --scan by size (biggest first, the default), name or last build (oldest first)")
+        .takes_value(true)
+        .requires("scan")
+        .possible_values(&["size", "name", "time"])
+        .value_name("ORDER");
+
     // local subcommand
-    let local =
-        SubCommand::with_name("local").about("check local build cache (target) of a rust project");
+    let local = SubCommand::with_name("local")
+        .about("check local build cache (target) of a rust project")
+        .arg(&local_scan)
+        .arg(&local_order);
     // shorter local subcommand (l)
-    let local_short =
-        SubCommand::with_name("l").about("check local build cache (target) of a rust project");
+    let local_short = SubCommand::with_name("l")
+        .about("check local build cache (target) of a rust project")
+        .arg(&local_scan)
+        .arg(&local_order);
     //</local>
 
     // <registry>
@@ -164,9 +204,13 @@ pub(crate) fn gen_clap<'a>() -> ArgMatches<'a> {
 
     //<sccache>
     // local subcommand
-    let sccache = SubCommand::with_name("sccache").about("gather stats on a local sccache cache");
+    let sccache = SubCommand::with_name("sccache")
+        .about("gather stats on a local sccache cache")
+        .arg(&time_source);
     // shorter local subcommand (l)
-    let sccache_short = SubCommand::with_name("sc").about("gather stats on a local sccache cache");
+    let sccache_short = SubCommand::with_name("sc")
+        .about("gather stats on a local sccache cache")
+        .arg(&time_source);
     //</sccache>
 
     //<clean-unref>
@@ -179,31 +223,259 @@ pub(crate) fn gen_clap<'a>() -> ArgMatches<'a> {
     // try to emulate this:
     let manifest_path = Arg::with_name("manifest-path")
         .long("manifest-path")
-        .help("Path to Cargo.toml")
+        .help("Path to Cargo.toml, can be passed several times")
         .takes_value(true)
+        .multiple(true)
+        .number_of_values(1)
         .value_name("PATH");
 
+    let manifest_list = Arg::with_name("manifest-list")
+        .long("manifest-list")
+        .help("file containing paths to Cargo.toml files, one per line")
+        .takes_value(true)
+        .value_name("FILE");
+
+    let offline = Arg::with_name("offline")
+        .long("offline")
+        .help("parse the Cargo.lock files directly instead of running \"cargo metadata\", needs no toolchain or network");
+
+    let scan = Arg::with_name("scan")
+        .long("scan")
+        .help("use all Cargo.toml workspaces found below this directory")
+        .takes_value(true)
+        .value_name("DIR");
+
+    let aggressive = Arg::with_name("aggressive")
+        .long("aggressive")
+        .help("remove all registry sources and git checkouts, even the referenced ones");
+
     let clean_unref = SubCommand::with_name("clean-unref")
         .about("remove crates that are not referenced in a Cargo.toml from the cache")
         .arg(&manifest_path)
-        .arg(&dry_run);
+        .arg(&manifest_list)
+        .arg(&scan)
+        .arg(&offline)
+        .arg(&aggressive)
+        .arg(&dry_run)
+        .arg(&quarantine)
+        .arg(&yes);
     //</clean-unref>
 
     //<trim>
     let size_limit = Arg::with_name("trim_limit")
         .long("limit")
         .short("l")
-        .help("size that the cache will be reduced to")
+        .help("size that the whole cargo home will be reduced to: 500M (binary), 1.5GB (decimal) or 2GiB (binary)")
+        .takes_value(true)
+        .value_name("LIMIT");
+
+    let quota = Arg::with_name("quota")
+        .long("quota")
+        .short("q")
+        .help("maximum size of a single component, can be passed several times: \"git-repos=500M\"")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1)
+        .value_name("COMPONENT=SIZE");
+
+    let ensure_free = Arg::with_name("ensure-free")
+        .long("ensure-free")
+        .help("remove the least recently used items until this much space is free on the file system of the cargo home")
         .takes_value(true)
-        .value_name("LIMIT")
-        .required(true);
+        .value_name("SIZE|PERCENT");
+
+    let strategy = Arg::with_name("strategy")
+        .long("strategy")
+        .help("the order in which items are removed: least recently used, sources and checkouts before archives and git repos or biggest first")
+        .takes_value(true)
+        .possible_values(&["lru", "rebuild-cost", "size"])
+        .value_name("STRATEGY");
+
+    let trim_format = Arg::with_name("format")
+        .long("format")
+        .help("print the items that would be removed as a table or as json, requires --dry-run for json")
+        .takes_value(true)
+        .possible_values(&["table", "json"])
+        .default_value("table")
+        .value_name("FORMAT");
+
+    let config_file = Arg::with_name("config")
+        .long("config")
+        .help("read the limits from this file instead of ${CARGO_HOME}/.cargo-cache/config.toml")
+        .takes_value(true)
+        .value_name("FILE");
+
+    let remove_bins = Arg::with_name("remove-bins")
+        .long("remove-bins")
+        .help("allow removing installed binaries that were not used recently, rustup proxies are always kept");
 
     let trim = SubCommand::with_name("trim")
         .about("trim old items from the cache until maximum cache size limit is reached")
         .arg(&size_limit)
-        .arg(&dry_run);
+        .arg(&quota)
+        .arg(&ensure_free)
+        .arg(&strategy)
+        .arg(&trim_format)
+        .arg(&time_source)
+        .arg(&config_file)
+        .arg(&remove_bins)
+        .arg(&dry_run)
+        .arg(&quarantine)
+        .arg(&yes);
 
     // </trim>
+
+    //<undo>
+    let undo = SubCommand::with_name("undo")
+        .about("restore the items removed by the last operation that was run with --quarantine")
+        .arg(&dry_run);
+    //</undo>
+
+    //<empty-trash>
+    let trash_older_than = Arg::with_name("older-than")
+        .long("older-than")
+        .help("Only remove items trashed before the specified date: YYYY.MM.DD, YYYY-MM-DD, HH:MM:SS, ISO 8601 or relative (30d, 2w, 6months)")
+        .takes_value(true)
+        .value_name("date");
+
+    let empty_trash = SubCommand::with_name("empty-trash")
+        .about("permanently remove the items that were moved to the trash by --quarantine")
+        .arg(&trash_older_than)
+        .arg(&yes)
+        .arg(&dry_run);
+    //</empty-trash>
+
+    //<log>
+    let operation_names = Operation::names();
+    let log_operation = Arg::with_name("operation")
+        .long("operation")
+        .short("o")
+        .help("only show items removed by this operation")
+        .takes_value(true)
+        .possible_values(&operation_names);
+
+    let log_since = Arg::with_name("since")
+        .long("since")
+        .short("s")
+        .help("only show items removed after the specified date: YYYY.MM.DD, YYYY-MM-DD, HH:MM:SS, ISO 8601 or relative (30d, 2w, 6months)")
+        .takes_value(true)
+        .value_name("date");
+
+    let log_command = Arg::with_name("command")
+        .long("command")
+        .short("c")
+        .help("also print the command line that removed the item");
+
+    let log = SubCommand::with_name("log")
+        .about("show the audit log of removed items, optionally filtered by a regex on the path")
+        .arg(Arg::with_name("QUERY"))
+        .arg(&log_operation)
+        .arg(&log_since)
+        .arg(&log_command);
+    //</log>
+
+    //<backup>
+    let backup_components = Arg::with_name("components")
+        .long("components")
+        .short("c")
+        .help("components to include in the backup: all,git-db,git-repos,registry-sources,registry-crate-cache,registry-index")
+        .takes_value(true)
+        .value_name("components")
+        .default_value("registry-crate-cache,registry-index,git-db");
+
+    let backup = SubCommand::with_name("backup")
+        .about("write components of the cargo home into a compressed archive")
+        .arg(Arg::with_name("ARCHIVE").required(true))
+        .arg(&backup_components);
+    //</backup>
+
+    //<restore>
+    let restore = SubCommand::with_name("restore")
+        .about("restore an archive created by \"cargo cache backup\", existing files are kept")
+        .arg(Arg::with_name("ARCHIVE").required(true))
+        .arg(&dry_run);
+    //</restore>
+
+    //<export>
+    let export_index_repo = Arg::with_name("index-repo")
+        .long("index-repo")
+        .help("also export the whole index repo of git registries, for crates.io that is several hundred MB");
+
+    let export = SubCommand::with_name("export")
+        .about("export the .crate archives, git repos and index entries a crate needs into a directory or .tar.gz")
+        .arg(Arg::with_name("TARGET").required(true))
+        .arg(&manifest_path)
+        .arg(&manifest_list)
+        .arg(&scan)
+        .arg(&offline)
+        .arg(&export_index_repo)
+        .arg(&dry_run);
+    //</export>
+
+    //<import>
+    let import = SubCommand::with_name("import")
+        .about("merge the registry cache, registry index and git db of another cargo home or archive into the local one")
+        .arg(Arg::with_name("SOURCE").required(true))
+        .arg(&dry_run);
+    //</import>
+
+    //<fingerprint>
+    let fingerprint_list = Arg::with_name("list")
+        .long("list")
+        .short("l")
+        .help("print the items that went into the fingerprint");
+
+    let fingerprint_no_toolchain = Arg::with_name("no-toolchain")
+        .long("no-toolchain")
+        .help("don't include the rustc version in the fingerprint");
+
+    let fingerprint = SubCommand::with_name("fingerprint")
+        .about("print a hash of the cache items (crates and checksums, git revisions) a crate needs, usable as CI cache key")
+        .arg(&manifest_path)
+        .arg(&manifest_list)
+        .arg(&scan)
+        .arg(&offline)
+        .arg(&fingerprint_list)
+        .arg(&fingerprint_no_toolchain);
+    //</fingerprint>
+
+    //<why>
+    let why_crate = Arg::with_name("CRATE")
+        .help("name of the crate or git repo, optionally with a version: \"serde@1.0.116\"")
+        .required_unless("unreferenced")
+        .conflicts_with("unreferenced");
+
+    let why_root = Arg::with_name("root")
+        .long("root")
+        .short("r")
+        .help("scan this directory for Cargo.lock files, can be passed several times (default: $CARGO_CACHE_PROJECT_ROOTS or the current directory)")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1)
+        .value_name("DIR");
+
+    let why_unreferenced = Arg::with_name("unreferenced")
+        .long("unreferenced")
+        .short("u")
+        .help("list the cache items that no project and no installed binary references");
+
+    let why = SubCommand::with_name("why")
+        .about("show which local projects and installed binaries reference a crate")
+        .arg(&why_crate)
+        .arg(&why_root)
+        .arg(&why_unreferenced);
+    //</why>
+
+    //<tui>
+    // subcommands that are only available with their cargo feature
+    let optional_subcmds: Vec<App<'_, '_>> = vec![
+        #[cfg(feature = "tui")]
+        SubCommand::with_name("tui")
+            .about("browse the cache interactively and remove marked items")
+            .arg(&time_source)
+            .arg(&quarantine),
+    ];
+    //</tui>
     // now thread all of these together
 
     // subcommand hack to have "cargo cache --foo" and "cargo-cache --foo" work equally
@@ -227,6 +499,15 @@ pub(crate) fn gen_clap<'a>() -> ArgMatches<'a> {
         .subcommand(sccache_short.clone())
         .subcommand(clean_unref.clone())
         .subcommand(trim.clone())
+        .subcommand(undo.clone())
+        .subcommand(empty_trash.clone())
+        .subcommand(log.clone())
+        .subcommand(backup.clone())
+        .subcommand(restore.clone())
+        .subcommand(export.clone())
+        .subcommand(import.clone())
+        .subcommand(fingerprint.clone())
+        .subcommand(why.clone())
         .arg(&list_dirs)
         .arg(&remove_dir)
         .arg(&gc_repos)
@@ -239,7 +520,12 @@ pub(crate) fn gen_clap<'a>() -> ArgMatches<'a> {
         .arg(&list_top_cache_items)
         .arg(&remove_if_younger)
         .arg(&remove_if_older)
+        .arg(&outside_range)
+        .arg(&time_source)
+        .arg(&quarantine)
+        .arg(&yes)
         .arg(&debug)
+        .subcommands(optional_subcmds.clone())
         .setting(AppSettings::Hidden);
 
     App::new("cargo-cache")
@@ -260,6 +546,15 @@ pub(crate) fn gen_clap<'a>() -> ArgMatches<'a> {
         .subcommand(sccache_short)
         .subcommand(clean_unref)
         .subcommand(trim)
+        .subcommand(undo)
+        .subcommand(empty_trash)
+        .subcommand(log)
+        .subcommand(backup)
+        .subcommand(restore)
+        .subcommand(export)
+        .subcommand(import)
+        .subcommand(fingerprint)
+        .subcommand(why)
         .arg(&list_dirs)
         .arg(&remove_dir)
         .arg(&gc_repos)
@@ -272,7 +567,12 @@ pub(crate) fn gen_clap<'a>() -> ArgMatches<'a> {
         .arg(&list_top_cache_items)
         .arg(&remove_if_younger)
         .arg(&remove_if_older)
+        .arg(&outside_range)
+        .arg(&time_source)
+        .arg(&quarantine)
+        .arg(&yes)
         .arg(&debug)
+        .subcommands(optional_subcmds)
         .get_matches()
 }
 
@@ -308,26 +608,53 @@ FLAGS:
     -h, --help                   Prints help information
     -i, --info                   Print information cache directories, what they are for and what can be safely deleted
     -l, --list-dirs              List all found directory paths
-    -V, --version                Prints version information\n
+        --outside-range          together with -o and -y: remove the items used before the date of -o or after the date
+                                 of -y instead
+        --quarantine             Move removed items into a trash inside the cargo home instead of deleting them
+    -V, --version                Prints version information
+        --yes                    Don't ask for confirmation before removing items\n
 OPTIONS:
     -k, --keep-duplicate-crates <N>        Remove all but N versions of crate in the source archives directory
     -r, --remove-dir <dir1,dir2,dir3>      Remove directories, accepted values: all,git-db,git-repos,
-                                           registry-sources,registry-crate-cache,registry-index,registry
-    -o, --remove-if-older-than <date>      Removes items older than specified date: YYYY.MM.DD or HH:MM:SS
-    -y, --remove-if-younger-than <date>    Removes items younger than the specified date: YYYY.MM.DD or HH:MM:SS
+                                           registry-sources,registry-crate-cache,registry-index,registry,
+                                           bin (installed binaries except for the rustup proxies, not part of \"all\")
+    -o, --remove-if-older-than <date>      Removes items older than specified date: YYYY.MM.DD, YYYY-MM-DD, HH:MM:SS,
+                                           ISO 8601 or relative (30d, 2w, 6months)
+    -y, --remove-if-younger-than <date>    Removes items younger than the specified date (same formats as -o), together
+                                           with -o: items used between both dates
+        --time-source <SOURCE>             the timestamp that tells when an item was last used: access, modification or
+                                           status change time or the newest of them [default: atime]  [possible values:
+                                           atime, mtime, ctime, newest]
     -t, --top-cache-items <N>              List the top N items taking most space in the cache\n
 SUBCOMMANDS:
+    backup         write components of the cargo home into a compressed archive
     clean-unref    remove crates that are not referenced in a Cargo.toml from the cache
+    empty-trash    permanently remove the items that were moved to the trash by --quarantine
+    export         export the .crate archives, git repos and index entries a crate needs into a directory or .tar.gz
+    fingerprint    print a hash of the cache items (crates and checksums, git revisions) a crate needs, usable as CI
+                   cache key
     help           Prints this message or the help of the given subcommand(s)
+    import         merge the registry cache, registry index and git db of another cargo home or archive into the
+                   local one
     l              check local build cache (target) of a rust project
     local          check local build cache (target) of a rust project
+    log            show the audit log of removed items, optionally filtered by a regex on the path
     q              run a query
     query          run a query
     r              query each package registry separately
     registry       query each package registry separately
+    restore        restore an archive created by \"cargo cache backup\", existing files are kept
     sc             gather stats on a local sccache cache
     sccache        gather stats on a local sccache cache
-    trim           trim old items from the cache until maximum cache size limit is reached\n");
+    trim           trim old items from the cache until maximum cache size limit is reached
+");
+        if cfg!(feature = "tui") {
+            help_desired.push_str(
+                "    tui            browse the cache interactively and remove marked items\n",
+            );
+        }
+        help_desired.push_str("    undo           restore the items removed by the last operation that was run with --quarantine
+    why            show which local projects and installed binaries reference a crate\n");
         assert_eq!(help_desired, help_real);
     }
     #[test]
@@ -355,26 +682,53 @@ FLAGS:
     -h, --help                   Prints help information
     -i, --info                   Print information cache directories, what they are for and what can be safely deleted
     -l, --list-dirs              List all found directory paths
-    -V, --version                Prints version information\n
+        --outside-range          together with -o and -y: remove the items used before the date of -o or after the date
+                                 of -y instead
+        --quarantine             Move removed items into a trash inside the cargo home instead of deleting them
+    -V, --version                Prints version information
+        --yes                    Don't ask for confirmation before removing items\n
 OPTIONS:
     -k, --keep-duplicate-crates <N>        Remove all but N versions of crate in the source archives directory
     -r, --remove-dir <dir1,dir2,dir3>      Remove directories, accepted values: all,git-db,git-repos,
-                                           registry-sources,registry-crate-cache,registry-index,registry
-    -o, --remove-if-older-than <date>      Removes items older than specified date: YYYY.MM.DD or HH:MM:SS
-    -y, --remove-if-younger-than <date>    Removes items younger than the specified date: YYYY.MM.DD or HH:MM:SS
+                                           registry-sources,registry-crate-cache,registry-index,registry,
+                                           bin (installed binaries except for the rustup proxies, not part of \"all\")
+    -o, --remove-if-older-than <date>      Removes items older than specified date: YYYY.MM.DD, YYYY-MM-DD, HH:MM:SS,
+                                           ISO 8601 or relative (30d, 2w, 6months)
+    -y, --remove-if-younger-than <date>    Removes items younger than the specified date (same formats as -o), together
+                                           with -o: items used between both dates
+        --time-source <SOURCE>             the timestamp that tells when an item was last used: access, modification or
+                                           status change time or the newest of them [default: atime]  [possible values:
+                                           atime, mtime, ctime, newest]
     -t, --top-cache-items <N>              List the top N items taking most space in the cache\n
 SUBCOMMANDS:
+    backup         write components of the cargo home into a compressed archive
     clean-unref    remove crates that are not referenced in a Cargo.toml from the cache
+    empty-trash    permanently remove the items that were moved to the trash by --quarantine
+    export         export the .crate archives, git repos and index entries a crate needs into a directory or .tar.gz
+    fingerprint    print a hash of the cache items (crates and checksums, git revisions) a crate needs, usable as CI
+                   cache key
     help           Prints this message or the help of the given subcommand(s)
+    import         merge the registry cache, registry index and git db of another cargo home or archive into the
+                   local one
     l              check local build cache (target) of a rust project
     local          check local build cache (target) of a rust project
+    log            show the audit log of removed items, optionally filtered by a regex on the path
     q              run a query
     query          run a query
     r              query each package registry separately
     registry       query each package registry separately
+    restore        restore an archive created by \"cargo cache backup\", existing files are kept
     sc             gather stats on a local sccache cache
     sccache        gather stats on a local sccache cache
-    trim           trim old items from the cache until maximum cache size limit is reached\n");
+    trim           trim old items from the cache until maximum cache size limit is reached
+");
+        if cfg!(feature = "tui") {
+            help_desired.push_str(
+                "    tui            browse the cache interactively and remove marked items\n",
+            );
+        }
+        help_desired.push_str("    undo           restore the items removed by the last operation that was run with --quarantine
+    why            show which local projects and installed binaries reference a crate\n");
 
         assert_eq!(help_desired, help_real);
     }
@@ -0,0 +1,405 @@
+// Copyright 2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// "cargo cache backup <archive>" and "cargo cache restore <archive>"
+// write selected components of the cargo home into a .tar.gz and restore them from there.
+// The first entry of the archive is a json manifest that describes its content,
+// all other paths inside the archive are relative to the ${CARGO_HOME}.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::{Component as PathComponent, Path, PathBuf};
+
+use crate::library::*;
+use crate::timestamps::open_without_atime;
+
+use chrono::Local;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use humansize::{file_size_opts, FileSize};
+use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
+
+/// name of the manifest inside the archive
+pub(crate) const MANIFEST_NAME: &str = "cargo-cache-manifest.json";
+
+/// by default, only back up what can not be rebuilt without network access
+pub(crate) const DEFAULT_COMPONENTS: &str = "registry-crate-cache,registry-index,git-db";
+
+/// describes the content of an archive
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Manifest {
+    /// version of cargo-cache that created the archive
+    pub(crate) cargo_cache_version: String,
+    /// creation time (rfc3339)
+    pub(crate) created: String,
+    /// names of the components contained in the archive
+    pub(crate) components: Vec<String>,
+    /// number of files in the archive, not counting the manifest
+    pub(crate) number_of_files: u64,
+    /// uncompressed size of all files
+    pub(crate) total_size: u64,
+}
+
+/// a file that goes into an archive
+#[derive(Debug, Clone)]
+pub(crate) struct ArchiveFile {
+    /// absolute path of the file on disk
+    pub(crate) path: PathBuf,
+    /// path inside the archive (relative to the cargo home)
+    pub(crate) name: PathBuf,
+    pub(crate) size: u64,
+}
+
+/// collect all files below a directory, sorted so that archives are reproducible
+pub(crate) fn files_below(dir: &Path, cargo_home: &Path) -> Vec<ArchiveFile> {
+    if !dir.exists() {
+        return Vec::new();
+    }
+    WalkDir::new(dir)
+        .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| !entry.file_type().is_dir())
+        .filter_map(|entry| {
+            let path = entry.path().to_path_buf();
+            let name = path.strip_prefix(cargo_home).ok()?.to_path_buf();
+            let size = entry.metadata().map_or(0, |m| m.len());
+            Some(ArchiveFile { path, name, size })
+        })
+        .collect()
+}
+
+/// write a list of files and a manifest into a gzip compressed tarball
+pub(crate) fn write_archive(
+    archive_path: &Path,
+    components: &[String],
+    files: &[ArchiveFile],
+) -> Result<Manifest, Error> {
+    let manifest = Manifest {
+        cargo_cache_version: crate::cli::get_version(),
+        created: Local::now().to_rfc3339(),
+        components: components.to_vec(),
+        number_of_files: files.len() as u64,
+        total_size: files.iter().map(|f| f.size).sum(),
+    };
+
+    let io_err = |e| Error::ArchiveIo(archive_path.to_path_buf(), e);
+
+    let archive = File::create(archive_path).map_err(io_err)?;
+    let mut builder = tar::Builder::new(GzEncoder::new(archive, Compression::default()));
+    // keep symlinks as symlinks
+    builder.follow_symlinks(false);
+
+    let manifest_json =
+        serde_json::to_vec_pretty(&manifest).expect("failed to serialize manifest, this is a bug");
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest_json.len() as u64);
+    header.set_mode(0o644);
+    #[allow(clippy::cast_sign_loss)]
+    header.set_mtime(Local::now().timestamp() as u64);
+    header.set_cksum();
+    builder
+        .append_data(&mut header, MANIFEST_NAME, manifest_json.as_slice())
+        .map_err(io_err)?;
+
+    for file in files {
+        let is_file = matches!(file.path.symlink_metadata(), Ok(m) if m.file_type().is_file());
+        if is_file {
+            // don't make the backed up items look recently used
+            open_without_atime(&file.path).and_then(|mut f| builder.append_file(&file.name, &mut f))
+        } else {
+            builder.append_path_with_name(&file.path, &file.name)
+        }
+        .map_err(|e| Error::ArchiveIo(file.path.clone(), e))?;
+    }
+
+    let _ = builder
+        .into_inner()
+        .and_then(GzEncoder::finish)
+        .map_err(io_err)?;
+
+    Ok(manifest)
+}
+
+/// open an archive and read the manifest from its first entry
+/// returns the manifest and the archive, positioned after the manifest
+pub(crate) fn open_archive(
+    archive_path: &Path,
+) -> Result<(Manifest, tar::Archive<GzDecoder<File>>), Error> {
+    let io_err = |e| Error::ArchiveIo(archive_path.to_path_buf(), e);
+    let mut archive = tar::Archive::new(GzDecoder::new(File::open(archive_path).map_err(io_err)?));
+
+    let manifest = {
+        let mut entries = archive.entries().map_err(io_err)?;
+        let mut first = match entries.next() {
+            Some(entry) => entry.map_err(io_err)?,
+            None => return Err(Error::ArchiveNoManifest(archive_path.to_path_buf())),
+        };
+        if first.path().map_err(io_err)?.as_ref() != Path::new(MANIFEST_NAME) {
+            return Err(Error::ArchiveNoManifest(archive_path.to_path_buf()));
+        }
+        let mut content = String::new();
+        let _ = first.read_to_string(&mut content).map_err(io_err)?;
+        serde_json::from_str(&content)
+            .map_err(|e| Error::ArchiveManifestCorrupt(archive_path.to_path_buf(), e))?
+    };
+
+    // entries() can only be called once per archive, reopen and skip the manifest
+    let reopened = File::open(archive_path).map_err(io_err)?;
+    Ok((manifest, tar::Archive::new(GzDecoder::new(reopened))))
+}
+
+/// "cargo cache backup": archive the selected components of the cargo home
+pub(crate) fn backup(
+    cargo_cache_paths: &CargoCachePaths,
+    archive_path: &Path,
+    components: Option<&str>,
+) -> Result<(), Error> {
+    let selected = components_from_names(components.unwrap_or(DEFAULT_COMPONENTS))?;
+
+    let mut files = Vec::new();
+    for component in &selected {
+        files.extend(files_below(
+            cargo_cache_paths.component_path(*component),
+            &cargo_cache_paths.cargo_home,
+        ));
+    }
+
+    let names: Vec<String> = selected.iter().map(ToString::to_string).collect();
+    let manifest = write_archive(archive_path, &names, &files)?;
+
+    println!(
+        "Wrote {} files ({}) of {} to '{}'",
+        manifest.number_of_files,
+        manifest
+            .total_size
+            .file_size(file_size_opts::DECIMAL)
+            .unwrap(),
+        names.join(", "),
+        archive_path.display()
+    );
+    Ok(())
+}
+
+/// the path `unpack_in()` writes an entry to, None for entries it refuses because they would
+/// end up outside of the cargo home
+fn entry_target(cargo_home: &Path, name: &Path) -> Option<PathBuf> {
+    let mut target = cargo_home.to_path_buf();
+    for component in name.components() {
+        match component {
+            PathComponent::Normal(part) => target.push(part),
+            PathComponent::ParentDir => return None,
+            // `unpack_in()` strips leading "/" and "."
+            PathComponent::Prefix(_) | PathComponent::RootDir | PathComponent::CurDir => {}
+        }
+    }
+    Some(target)
+}
+
+/// unpack all entries of an archive into the cargo home, files that already exist are kept
+/// and entries that would end up outside of the cargo home are refused
+/// returns the number of files and bytes that were added and the number of refused files
+pub(crate) fn unpack_archive(
+    archive_path: &Path,
+    archive: &mut tar::Archive<GzDecoder<File>>,
+    cargo_home: &Path,
+    dry_run: bool,
+) -> Result<(u64, u64, u64), Error> {
+    let io_err = |e| Error::ArchiveIo(archive_path.to_path_buf(), e);
+    let refuse = |name: &Path| {
+        eprintln!(
+            "Warning: '{}' points outside of the cargo home, skipping it.",
+            name.display()
+        );
+    };
+
+    let mut added_files = 0;
+    let mut added_size = 0;
+    let mut refused_files = 0;
+
+    for entry_result in archive.entries().map_err(io_err)? {
+        let mut entry = entry_result.map_err(io_err)?;
+        let name = entry.path().map_err(io_err)?.to_path_buf();
+        if name == Path::new(MANIFEST_NAME) {
+            continue;
+        }
+
+        let target = if let Some(target) = entry_target(cargo_home, &name) {
+            target
+        } else {
+            refuse(&name);
+            refused_files += 1;
+            continue;
+        };
+        if target.exists() {
+            continue;
+        }
+
+        let size = entry.header().size().unwrap_or(0);
+        if dry_run {
+            println!("dry-run: would restore '{}'", target.display());
+        } else if !entry.unpack_in(cargo_home).map_err(io_err)? {
+            refuse(&name);
+            refused_files += 1;
+            continue;
+        }
+        added_files += 1;
+        added_size += size;
+    }
+
+    Ok((added_files, added_size, refused_files))
+}
+
+/// "cargo cache restore": unpack an archive created by "cargo cache backup" into the cargo home
+pub(crate) fn restore(
+    cargo_cache_paths: &CargoCachePaths,
+    archive_path: &Path,
+    dry_run: bool,
+) -> Result<(), Error> {
+    let (manifest, mut archive) = open_archive(archive_path)?;
+
+    println!(
+        "Archive created {} by cargo-cache {}, contains {} files ({}) of {}",
+        manifest.created,
+        manifest.cargo_cache_version,
+        manifest.number_of_files,
+        manifest
+            .total_size
+            .file_size(file_size_opts::DECIMAL)
+            .unwrap(),
+        manifest.components.join(", ")
+    );
+
+    let (added_files, added_size, refused_files) = unpack_archive(
+        archive_path,
+        &mut archive,
+        &cargo_cache_paths.cargo_home,
+        dry_run,
+    )?;
+
+    println!(
+        "{} {} files ({}), {} files were already present",
+        if dry_run {
+            "dry-run: would restore"
+        } else {
+            "Restored"
+        },
+        added_files,
+        added_size.file_size(file_size_opts::DECIMAL).unwrap(),
+        manifest
+            .number_of_files
+            .saturating_sub(added_files + refused_files)
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod backuptests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::fs;
+
+    #[test]
+    fn backup_and_restore_roundtrip() {
+        let root = PathBuf::from("target/backup_tests/roundtrip");
+        let _ = fs::remove_dir_all(&root);
+        let cargo_home = root.join("cargo_home");
+        let crate_dir = cargo_home
+            .join("registry")
+            .join("cache")
+            .join("github.com-1ecc6299db9ec823");
+        fs::create_dir_all(&crate_dir).unwrap();
+        fs::write(crate_dir.join("a-0.1.0.crate"), "aaaa").unwrap();
+        fs::write(crate_dir.join("b-0.1.0.crate"), "bb").unwrap();
+
+        let archive_path = root.join("backup.tar.gz");
+        let files = files_below(&cargo_home.join("registry").join("cache"), &cargo_home);
+        let names: Vec<PathBuf> = files.iter().map(|f| f.name.clone()).collect();
+        assert_eq!(
+            names,
+            vec![
+                PathBuf::from("registry/cache/github.com-1ecc6299db9ec823/a-0.1.0.crate"),
+                PathBuf::from("registry/cache/github.com-1ecc6299db9ec823/b-0.1.0.crate"),
+            ]
+        );
+
+        let manifest =
+            write_archive(&archive_path, &["registry-crate-cache".into()], &files).unwrap();
+        assert_eq!(manifest.number_of_files, 2);
+        assert_eq!(manifest.total_size, 6);
+
+        // restore into an empty cargo home
+        let restored_home = root.join("restored");
+        fs::create_dir_all(&restored_home).unwrap();
+        let (read_manifest, mut archive) = open_archive(&archive_path).unwrap();
+        assert_eq!(read_manifest, manifest);
+        let (added, size, refused) =
+            unpack_archive(&archive_path, &mut archive, &restored_home, false).unwrap();
+        assert_eq!((added, size, refused), (2, 6, 0));
+        assert_eq!(
+            fs::read_to_string(
+                restored_home.join("registry/cache/github.com-1ecc6299db9ec823/a-0.1.0.crate")
+            )
+            .unwrap(),
+            "aaaa"
+        );
+
+        // everything is present now, nothing should be added
+        let (_, mut archive_again) = open_archive(&archive_path).unwrap();
+        let (added_again, _, _) =
+            unpack_archive(&archive_path, &mut archive_again, &restored_home, false).unwrap();
+        assert_eq!(added_again, 0);
+    }
+
+    #[test]
+    fn entries_outside_of_the_cargo_home_are_refused() {
+        let root = PathBuf::from("target/backup_tests/outside");
+        let _ = fs::remove_dir_all(&root);
+        let cargo_home = root.join("cargo_home");
+        fs::create_dir_all(&cargo_home).unwrap();
+
+        // tar::Builder refuses to write ".." paths, so set the raw name ourselves
+        let archive_path = root.join("evil.tar.gz");
+        let mut builder = tar::Builder::new(GzEncoder::new(
+            File::create(&archive_path).unwrap(),
+            Compression::default(),
+        ));
+        let manifest = serde_json::to_vec(&Manifest {
+            cargo_cache_version: "test".into(),
+            created: "2020-08-01T12:00:00+02:00".into(),
+            components: vec!["registry-crate-cache".into()],
+            number_of_files: 2,
+            total_size: 8,
+        })
+        .unwrap();
+        for (name, content) in &[
+            (MANIFEST_NAME, manifest.as_slice()),
+            ("../outside.crate", b"evil".as_ref()),
+            ("registry/cache/a-0.1.0.crate", b"good".as_ref()),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append(&header, *content).unwrap();
+        }
+        let _ = builder.into_inner().unwrap().finish().unwrap();
+
+        for dry_run in &[true, false] {
+            let (_, mut archive) = open_archive(&archive_path).unwrap();
+            let result = unpack_archive(&archive_path, &mut archive, &cargo_home, *dry_run);
+            assert_eq!(result.unwrap(), (1, 4, 1));
+        }
+        assert!(cargo_home.join("registry/cache/a-0.1.0.crate").is_file());
+        assert!(!root.join("outside.crate").exists());
+    }
+}
@@ -0,0 +1,327 @@
+// Copyright 2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// "cargo cache export <target>" writes everything a workspace needs to build offline
+// (.crate archives, bare git repos and the cached index entries of the crates) into a
+// directory or a .tar.gz that is laid out like a cargo home.
+// Only the cached index entries of the needed crates (registry/index/<registry>/.cache/<prefix>/<name>)
+// are exported, "import" merges them into the local index. Sparse registries also need their config.json.
+// Cargo only trusts the cached entries of git registries together with the index repo they were
+// read from, "--index-repo" adds registry/index/<registry>/.git to the bundle for machines that
+// don't have the index yet. For crates.io that is the whole index history (several hundred MB).
+// The dependencies are resolved the same way "clean-unref" does it.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::clean_unref::required_items;
+use crate::commands::backup::{files_below, write_archive, ArchiveFile};
+use crate::library::*;
+
+use humansize::{file_size_opts, FileSize};
+
+/// archives are written if the target ends with one of these, otherwise we write a directory
+const ARCHIVE_EXTENSIONS: [&str; 2] = [".tar.gz", ".tgz"];
+
+/// collect the files of all items a workspace needs, items missing from the cache are returned separately
+fn collect_files(
+    cargo_cache_paths: &CargoCachePaths,
+    manifests: &[PathBuf],
+    offline: bool,
+    index_repo: bool,
+) -> Result<(Vec<ArchiveFile>, Vec<PathBuf>), Error> {
+    let required = required_items(cargo_cache_paths, manifests, offline)?;
+    let cargo_home = &cargo_cache_paths.cargo_home;
+
+    let mut files = Vec::new();
+    let mut missing = Vec::new();
+
+    let mut indices: Vec<PathBuf> = required
+        .crates
+        .iter()
+        .filter_map(|krate| krate.parent()?.file_name())
+        .map(|registry| cargo_cache_paths.registry_index.join(registry))
+        .collect();
+    indices.sort();
+    indices.dedup();
+
+    let mut index_configs = Vec::new();
+    for index in &indices {
+        let repo = index.join(".git");
+        // sparse registries keep their config next to the cached entries
+        let config = index.join("config.json");
+        if config.is_file() {
+            index_configs.push(config);
+        } else if !index_repo {
+            // the cached entries of a git registry are merged into the index of the other machine
+        } else if repo.is_dir() {
+            files.extend(files_below(&repo, cargo_home));
+        } else {
+            missing.push(repo);
+        }
+    }
+
+    for file in required
+        .crates
+        .iter()
+        .chain(required.index_entries.iter())
+        .chain(index_configs.iter())
+    {
+        match (file.strip_prefix(cargo_home), fs::metadata(file)) {
+            (Ok(name), Ok(metadata)) if metadata.is_file() => files.push(ArchiveFile {
+                path: file.clone(),
+                name: name.to_path_buf(),
+                size: metadata.len(),
+            }),
+            _ => missing.push(file.clone()),
+        }
+    }
+
+    for repo in &required.git_repos {
+        if repo.is_dir() {
+            files.extend(files_below(repo, cargo_home));
+        } else {
+            missing.push(repo.clone());
+        }
+    }
+
+    Ok((files, missing))
+}
+
+/// copy the files into a directory, files that already exist there are kept
+fn copy_to_dir(target: &Path, files: &[ArchiveFile]) -> Result<(), Error> {
+    for file in files {
+        let destination = target.join(&file.name);
+        if destination.exists() {
+            continue;
+        }
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent).map_err(|e| Error::ArchiveIo(parent.to_path_buf(), e))?;
+        }
+        let _ = fs::copy(&file.path, &destination)
+            .map_err(|e| Error::ArchiveIo(file.path.clone(), e))?;
+    }
+    Ok(())
+}
+
+/// "cargo cache export": write the items a workspace needs into a directory or archive
+pub(crate) fn export(
+    cargo_cache_paths: &CargoCachePaths,
+    target: &Path,
+    manifests: &[PathBuf],
+    offline: bool,
+    index_repo: bool,
+    dry_run: bool,
+) -> Result<(), Error> {
+    let (files, missing) = collect_files(cargo_cache_paths, manifests, offline, index_repo)?;
+
+    for item in &missing {
+        eprintln!(
+            "Warning: '{}' is not in the cache and can not be exported.",
+            item.display()
+        );
+    }
+
+    let total_size: u64 = files.iter().map(|f| f.size).sum();
+    let is_archive = ARCHIVE_EXTENSIONS
+        .iter()
+        .any(|ext| target.to_string_lossy().ends_with(ext));
+
+    if dry_run {
+        for file in &files {
+            println!("dry-run: would export '{}'", file.path.display());
+        }
+    } else if is_archive {
+        let components = vec![
+            Component::RegistryCrateCache.to_string(),
+            Component::RegistryIndex.to_string(),
+            Component::GitDB.to_string(),
+        ];
+        let _ = write_archive(target, &components, &files)?;
+    } else {
+        copy_to_dir(target, &files)?;
+    }
+
+    println!(
+        "{} {} files ({}) to '{}'",
+        if dry_run {
+            "dry-run: would export"
+        } else {
+            "Exported"
+        },
+        files.len(),
+        total_size.file_size(file_size_opts::DECIMAL).unwrap(),
+        target.display()
+    );
+    if !dry_run && is_archive {
+        println!(
+            "Run \"cargo cache restore {}\" to seed another cargo home with it.",
+            target.display()
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod exporttests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn copy_keeps_layout() {
+        let root = PathBuf::from("target/export_tests/copy_keeps_layout");
+        let _ = fs::remove_dir_all(&root);
+        let cargo_home = root.join("cargo_home");
+        let cache = cargo_home
+            .join("registry")
+            .join("cache")
+            .join("github.com-1ecc6299db9ec823");
+        fs::create_dir_all(&cache).unwrap();
+        fs::write(cache.join("serde-1.0.0.crate"), "serde").unwrap();
+
+        let files = files_below(&cargo_home.join("registry"), &cargo_home);
+        let target = root.join("bundle");
+        copy_to_dir(&target, &files).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(
+                target
+                    .join("registry")
+                    .join("cache")
+                    .join("github.com-1ecc6299db9ec823")
+                    .join("serde-1.0.0.crate")
+            )
+            .unwrap(),
+            "serde"
+        );
+    }
+
+    #[test]
+    fn reimported_bundle_resolves_offline() {
+        let root = PathBuf::from("target/export_tests/reimported_bundle_resolves_offline");
+        let _ = fs::remove_dir_all(&root);
+        let source = CargoCachePaths::from_cargo_home(root.join("source"));
+        let local = CargoCachePaths::from_cargo_home(root.join("local"));
+        fs::create_dir_all(&local.cargo_home).unwrap();
+
+        // a git registry (crates.io) and a sparse one
+        let git_registry = "github.com-1ecc6299db9ec823";
+        let sparse_registry = "cargo.example.com-0123456789abcdef";
+        for (registry, krate) in &[(git_registry, "serde"), (sparse_registry, "internal")] {
+            let cache = source.registry_pkg_cache.join(registry);
+            fs::create_dir_all(&cache).unwrap();
+            fs::write(cache.join(format!("{}-1.0.0.crate", krate)), *krate).unwrap();
+            let entry =
+                crate::clean_unref::index_cache_entry(&source.registry_index.join(registry), krate);
+            fs::create_dir_all(entry.parent().unwrap()).unwrap();
+            fs::write(&entry, "entry").unwrap();
+        }
+        let git_index = source.registry_index.join(git_registry).join(".git");
+        fs::create_dir_all(git_index.join("objects")).unwrap();
+        fs::write(git_index.join("HEAD"), "ref: refs/heads/master").unwrap();
+        fs::write(
+            source
+                .registry_index
+                .join(sparse_registry)
+                .join("config.json"),
+            "{}",
+        )
+        .unwrap();
+
+        let project = root.join("project");
+        fs::create_dir_all(&project).unwrap();
+        let manifest = project.join("Cargo.toml");
+        fs::write(
+            &manifest,
+            "[package]\nname = \"project\"\nversion = \"0.1.0\"\n",
+        )
+        .unwrap();
+        fs::write(
+            project.join("Cargo.lock"),
+            r#"
+[[package]]
+name = "project"
+version = "0.1.0"
+
+[[package]]
+name = "serde"
+version = "1.0.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "internal"
+version = "1.0.0"
+source = "sparse+https://cargo.example.com/index/"
+"#,
+        )
+        .unwrap();
+
+        // by default only the cached entries of the git index are exported
+        let (files, missing) =
+            collect_files(&source, std::slice::from_ref(&manifest), true, false).unwrap();
+        assert!(missing.is_empty());
+        assert!(files.iter().all(|file| !file.name.starts_with(
+            Path::new("registry")
+                .join("index")
+                .join(git_registry)
+                .join(".git")
+        )));
+        assert_eq!(files.len(), 5);
+
+        let bundle = root.join("bundle.tar.gz");
+        export(
+            &source,
+            &bundle,
+            std::slice::from_ref(&manifest),
+            true,
+            true,
+            false,
+        )
+        .unwrap();
+        crate::commands::import::import(&local, &bundle, false).unwrap();
+
+        // everything the project needs is in the new cargo home, including the git index
+        let required = required_items(&local, std::slice::from_ref(&manifest), true).unwrap();
+        assert_eq!(required.crates.len(), 2);
+        assert_eq!(required.index_entries.len(), 2);
+        for item in required.crates.iter().chain(required.index_entries.iter()) {
+            assert!(item.is_file(), "missing: {}", item.display());
+        }
+        assert!(local
+            .registry_index
+            .join(git_registry)
+            .join(".git")
+            .join("HEAD")
+            .is_file());
+        assert!(local
+            .registry_index
+            .join(sparse_registry)
+            .join("config.json")
+            .is_file());
+
+        // a cargo home that already has the git index gets the cached entries merged into it
+        let existing = CargoCachePaths::from_cargo_home(root.join("existing"));
+        let existing_index = existing.registry_index.join(git_registry);
+        fs::create_dir_all(existing_index.join(".git")).unwrap();
+        let dir_bundle = root.join("bundle");
+        export(
+            &source,
+            &dir_bundle,
+            std::slice::from_ref(&manifest),
+            true,
+            false,
+            false,
+        )
+        .unwrap();
+        crate::commands::import::import(&existing, &dir_bundle, false).unwrap();
+        assert!(crate::clean_unref::index_cache_entry(&existing_index, "serde").is_file());
+        assert!(!existing_index.join(".git").join("HEAD").exists());
+    }
+}
@@ -0,0 +1,213 @@
+// Copyright 2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// "cargo cache fingerprint" prints a hash over everything a workspace needs from the cargo home
+// which can be used as a key to cache the ${CARGO_HOME} on CI.
+// Unlike a hash of the Cargo.lock, this takes the registry a crate comes from,
+// the checksum of the .crate archive and the rustc version into account.
+
+use std::env;
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::clean_unref::{index_cache_entry, required_items, RequiredPackage};
+use crate::commands::import::checksums_from_index_entry;
+use crate::library::*;
+
+use sha2::{Digest, Sha256};
+
+/// the checksum of a registry crate as recorded in the lockfile or the registry index,
+/// the local .crate archive is never looked at so that the fingerprint only depends on the lockfile
+fn crate_checksum(
+    cargo_cache_paths: &CargoCachePaths,
+    package: &RequiredPackage,
+) -> Result<String, Error> {
+    // the Cargo.lock already told us
+    if let Some(checksum) = &package.checksum {
+        return Ok(checksum.clone());
+    }
+
+    package
+        .path
+        .parent()
+        .and_then(|registry_dir| registry_dir.file_name())
+        .and_then(|registry| {
+            let entry = index_cache_entry(
+                &cargo_cache_paths.registry_index.join(registry),
+                &package.name,
+            );
+            checksums_from_index_entry(&entry).remove(&package.version)
+        })
+        .ok_or_else(|| Error::NoChecksum(package.name.clone(), package.version.clone()))
+}
+
+/// the version of the rustc that would build the crate, if we can find one
+fn rustc_version() -> Option<String> {
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let output = Command::new(rustc).arg("--version").output().ok()?;
+    if output.status.success() {
+        Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    } else {
+        None
+    }
+}
+
+/// one line per item that goes into the fingerprint, sorted so that the result is deterministic
+fn fingerprint_lines(
+    packages: &[RequiredPackage],
+    checksum: impl Fn(&RequiredPackage) -> Result<String, Error>,
+    toolchain: Option<String>,
+) -> Result<Vec<String>, Error> {
+    let mut lines = packages
+        .iter()
+        .map(|package| {
+            if package.source.starts_with("git+") {
+                // the source of git deps already contains the revision: git+<url>#<rev>
+                Ok(format!(
+                    "git {} {} {}",
+                    package.name, package.version, package.source
+                ))
+            } else {
+                Ok(format!(
+                    "crate {} {} {} {}",
+                    package.name,
+                    package.version,
+                    package.source,
+                    checksum(package)?
+                ))
+            }
+        })
+        .collect::<Result<Vec<String>, Error>>()?;
+    lines.sort();
+    lines.dedup();
+
+    if let Some(version) = toolchain {
+        lines.push(format!("toolchain {}", version));
+    }
+    Ok(lines)
+}
+
+/// hash the lines into a hex string
+fn hash_lines(lines: &[String]) -> String {
+    let mut hasher = Sha256::new();
+    for line in lines {
+        hasher.update(line.as_bytes());
+        hasher.update(b"\n");
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// "cargo cache fingerprint": print a fingerprint of the cache items a crate needs
+pub(crate) fn fingerprint(
+    cargo_cache_paths: &CargoCachePaths,
+    manifests: &[PathBuf],
+    offline: bool,
+    list: bool,
+    no_toolchain: bool,
+) -> Result<(), Error> {
+    let required = required_items(cargo_cache_paths, manifests, offline)?;
+    let toolchain = if no_toolchain { None } else { rustc_version() };
+
+    let lines = fingerprint_lines(
+        &required.packages,
+        |package| crate_checksum(cargo_cache_paths, package),
+        toolchain,
+    )?;
+
+    if list {
+        for line in &lines {
+            println!("{}", line);
+        }
+    }
+    println!("{}", hash_lines(&lines));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod fingerprinttests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn packages() -> Vec<RequiredPackage> {
+        vec![
+            RequiredPackage {
+                name: "serde".into(),
+                version: "1.0.116".into(),
+                source: "registry+https://github.com/rust-lang/crates.io-index".into(),
+                path: PathBuf::from(
+                    "registry/cache/github.com-1ecc6299db9ec823/serde-1.0.116.crate",
+                ),
+                checksum: None,
+            },
+            RequiredPackage {
+                name: "cargo".into(),
+                version: "0.48.0".into(),
+                source: "git+https://github.com/rust-lang/cargo#258c896".into(),
+                path: PathBuf::from("git/db/cargo-e7ff1db891893a9e"),
+                checksum: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn lines_are_sorted() {
+        let lines = fingerprint_lines(
+            &packages(),
+            |_| Ok("abcd".to_string()),
+            Some("rustc 1.47.0".into()),
+        )
+        .unwrap();
+        assert_eq!(
+            lines,
+            vec![
+                "crate serde 1.0.116 registry+https://github.com/rust-lang/crates.io-index abcd",
+                "git cargo 0.48.0 git+https://github.com/rust-lang/cargo#258c896",
+                "toolchain rustc 1.47.0",
+            ]
+        );
+    }
+
+    #[test]
+    fn fingerprint_is_deterministic() {
+        let mut reversed = packages();
+        reversed.reverse();
+
+        let first =
+            hash_lines(&fingerprint_lines(&packages(), |_| Ok("abcd".into()), None).unwrap());
+        let second =
+            hash_lines(&fingerprint_lines(&reversed, |_| Ok("abcd".into()), None).unwrap());
+        assert_eq!(first, second);
+
+        // a different checksum changes the fingerprint
+        let third =
+            hash_lines(&fingerprint_lines(&packages(), |_| Ok("efgh".into()), None).unwrap());
+        assert!(first != third);
+    }
+
+    #[test]
+    fn no_checksum_is_an_error() {
+        // neither the lockfile nor the (empty) index know the checksum of serde
+        let cargo_home = PathBuf::from("target/fingerprint_tests/no_checksum");
+        std::fs::create_dir_all(&cargo_home).unwrap();
+        let paths = CargoCachePaths::new(cargo_home).unwrap();
+        let serde = &packages()[0];
+        match crate_checksum(&paths, serde) {
+            Err(Error::NoChecksum(name, version)) => {
+                assert_eq!((name.as_str(), version.as_str()), ("serde", "1.0.116"));
+            }
+            other => panic!("expected NoChecksum, got {:?}", other),
+        }
+
+        // the one from the lockfile is used as is
+        let mut locked = serde.clone();
+        locked.checksum = Some("abcd".into());
+        assert_eq!(crate_checksum(&paths, &locked).unwrap(), "abcd");
+    }
+}
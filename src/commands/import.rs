@@ -0,0 +1,471 @@
+// Copyright 2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// "cargo cache import <dir-or-archive>" merges the registry cache, registry index and git db
+// of another cargo home (or of an archive created by "backup" or "export") into the local one.
+// Items that are already present are skipped, .crate archives are verified against the
+// checksums found in the registry index before they are copied.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use crate::commands::backup::open_archive;
+use crate::library::*;
+use crate::remove::parse_version;
+use crate::tables::format_table;
+use crate::timestamps::open_without_atime;
+
+use humansize::{file_size_opts, FileSize};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use walkdir::WalkDir;
+
+/// what happened to the items of a single component
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct ImportStats {
+    added_items: u64,
+    added_size: u64,
+    skipped_items: u64,
+    /// .crate archives whose checksum did not match the index
+    corrupt_items: u64,
+    /// .crate archives which had no checksum in any index
+    unverified_items: u64,
+}
+
+/// the parts of a line of the registry index we care about
+#[derive(Debug, Deserialize)]
+struct IndexLine {
+    vers: String,
+    cksum: String,
+}
+
+/// copy a file or a directory recursively, returns the number of bytes copied
+fn copy_path(source: &Path, destination: &Path, dry_run: bool) -> Result<u64, Error> {
+    let mut size = 0;
+    for entry in WalkDir::new(source).into_iter().filter_map(Result::ok) {
+        let relative = entry
+            .path()
+            .strip_prefix(source)
+            .expect("walkdir returned path outside of the walked directory");
+        // copying a single file yields an empty relative path
+        let target = if relative.as_os_str().is_empty() {
+            destination.to_path_buf()
+        } else {
+            destination.join(relative)
+        };
+        if entry.file_type().is_dir() {
+            if !dry_run {
+                fs::create_dir_all(&target).map_err(|e| Error::ArchiveIo(target.clone(), e))?;
+            }
+        } else {
+            if !dry_run {
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent)
+                        .map_err(|e| Error::ArchiveIo(parent.to_path_buf(), e))?;
+                }
+                let _ = fs::copy(entry.path(), &target)
+                    .map_err(|e| Error::ArchiveIo(entry.path().to_path_buf(), e))?;
+            }
+            size += entry.metadata().map_or(0, |m| m.len());
+        }
+    }
+    Ok(size)
+}
+
+/// the direct children of a directory, sorted
+fn children(dir: &Path) -> Vec<PathBuf> {
+    let mut children = match fs::read_dir(dir) {
+        Ok(read_dir) => read_dir
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .collect::<Vec<PathBuf>>(),
+        Err(_) => Vec::new(),
+    };
+    children.sort();
+    children
+}
+
+/// sha256 of a file as lowercase hex string, the format used by the registry index
+pub(crate) fn sha256_of_file(path: &Path) -> io::Result<String> {
+    let mut file = open_without_atime(path)?;
+    let mut hasher = Sha256::new();
+    let _ = io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// get the checksums (version => checksum) of a crate from a cached index entry
+/// an entry consists of a small header followed by "<version>\0<json>\0" pairs;
+/// we only look at the json lines so that we don't depend on the exact header version
+pub(crate) fn checksums_from_index_entry(entry: &Path) -> HashMap<String, String> {
+    let mut content = Vec::new();
+    if open_without_atime(entry)
+        .and_then(|mut file| file.read_to_end(&mut content))
+        .is_err()
+    {
+        return HashMap::new();
+    }
+
+    content
+        .split(|byte| *byte == 0)
+        .filter(|chunk| chunk.starts_with(b"{"))
+        .filter_map(|chunk| serde_json::from_slice::<IndexLine>(chunk).ok())
+        .map(|line| (line.vers, line.cksum))
+        .collect()
+}
+
+/// look up the checksum of a .crate in the indices of the given cargo homes
+fn expected_checksum(
+    cargo_homes: &[&CargoCachePaths],
+    registry: &Path,
+    name: &str,
+    version: &str,
+) -> Option<String> {
+    cargo_homes.iter().find_map(|cargo_home| {
+        let entry =
+            crate::clean_unref::index_cache_entry(&cargo_home.registry_index.join(registry), name);
+        checksums_from_index_entry(&entry).remove(version)
+    })
+}
+
+/// merge the registry indices: new indices are copied as a whole, for existing ones we only
+/// add the cached entries (and the config of sparse registries) that are missing
+fn import_indices(
+    source: &CargoCachePaths,
+    local: &CargoCachePaths,
+    dry_run: bool,
+) -> Result<ImportStats, Error> {
+    let mut stats = ImportStats::default();
+
+    for source_index in children(&source.registry_index) {
+        let name = source_index.file_name().unwrap();
+        let local_index = local.registry_index.join(name);
+
+        if !local_index.exists() {
+            stats.added_size += copy_path(&source_index, &local_index, dry_run)?;
+            stats.added_items += 1;
+            continue;
+        }
+
+        let entries = WalkDir::new(source_index.join(".cache"))
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_file())
+            .map(walkdir::DirEntry::into_path)
+            .chain(std::iter::once(source_index.join("config.json")))
+            .filter(|path| path.is_file());
+
+        for entry in entries {
+            let target = local_index.join(entry.strip_prefix(&source_index).unwrap());
+            if target.exists() {
+                stats.skipped_items += 1;
+            } else {
+                stats.added_size += copy_path(&entry, &target, dry_run)?;
+                stats.added_items += 1;
+            }
+        }
+    }
+
+    Ok(stats)
+}
+
+/// merge the .crate archives, verifying their checksums
+fn import_crates(
+    source: &CargoCachePaths,
+    local: &CargoCachePaths,
+    dry_run: bool,
+) -> Result<ImportStats, Error> {
+    let mut stats = ImportStats::default();
+
+    for source_registry in children(&source.registry_pkg_cache) {
+        let registry = PathBuf::from(source_registry.file_name().unwrap());
+
+        for krate in children(&source_registry) {
+            let target = local
+                .registry_pkg_cache
+                .join(&registry)
+                .join(krate.file_name().unwrap());
+            if target.exists() {
+                stats.skipped_items += 1;
+                continue;
+            }
+
+            let (name, version) = parse_version(&krate)?;
+            match expected_checksum(&[source, local], &registry, &name, &version) {
+                Some(expected) => {
+                    let actual =
+                        sha256_of_file(&krate).map_err(|e| Error::ArchiveIo(krate.clone(), e))?;
+                    if actual != expected {
+                        eprintln!(
+                            "Warning: checksum of '{}' does not match the registry index, skipping it.",
+                            krate.display()
+                        );
+                        stats.corrupt_items += 1;
+                        continue;
+                    }
+                }
+                None => stats.unverified_items += 1,
+            }
+
+            stats.added_size += copy_path(&krate, &target, dry_run)?;
+            stats.added_items += 1;
+        }
+    }
+
+    Ok(stats)
+}
+
+/// merge the bare git repos, repos are only copied as a whole
+fn import_git_dbs(
+    source: &CargoCachePaths,
+    local: &CargoCachePaths,
+    dry_run: bool,
+) -> Result<ImportStats, Error> {
+    let mut stats = ImportStats::default();
+
+    for repo in children(&source.git_repos_bare) {
+        let target = local.git_repos_bare.join(repo.file_name().unwrap());
+        if target.exists() {
+            stats.skipped_items += 1;
+        } else {
+            stats.added_size += copy_path(&repo, &target, dry_run)?;
+            stats.added_items += 1;
+        }
+    }
+
+    Ok(stats)
+}
+
+/// merge everything and print a summary per component
+fn import_cargo_home(
+    source: &CargoCachePaths,
+    local: &CargoCachePaths,
+    dry_run: bool,
+) -> Result<(), Error> {
+    // import the indices first so that we can verify crates against them
+    let results = vec![
+        (
+            Component::RegistryIndex,
+            import_indices(source, local, dry_run)?,
+        ),
+        (
+            Component::RegistryCrateCache,
+            import_crates(source, local, dry_run)?,
+        ),
+        (Component::GitDB, import_git_dbs(source, local, dry_run)?),
+    ];
+
+    let mut table = vec![vec![
+        "Component".to_string(),
+        "Added".to_string(),
+        "Size".to_string(),
+        "Already present".to_string(),
+        "Unverified".to_string(),
+        "Checksum mismatch".to_string(),
+    ]];
+    for (component, stats) in &results {
+        table.push(vec![
+            component.to_string(),
+            stats.added_items.to_string(),
+            stats.added_size.file_size(file_size_opts::DECIMAL).unwrap(),
+            stats.skipped_items.to_string(),
+            stats.unverified_items.to_string(),
+            stats.corrupt_items.to_string(),
+        ]);
+    }
+
+    if dry_run {
+        println!("dry-run: would import:");
+    }
+    println!("{}", format_table(&table, 1).trim_end());
+
+    Ok(())
+}
+
+/// "cargo cache import": merge a cargo home, or an archive, into the local cargo home
+pub(crate) fn import(
+    cargo_cache_paths: &CargoCachePaths,
+    source: &Path,
+    dry_run: bool,
+) -> Result<(), Error> {
+    if source.is_dir() {
+        let source_paths = CargoCachePaths::from_cargo_home(source.to_path_buf());
+        if !source_paths.registry.is_dir() && !source_paths.git_repos_bare.is_dir() {
+            return Err(Error::ImportNoCargoHome(source.to_path_buf()));
+        }
+        return import_cargo_home(&source_paths, cargo_cache_paths, dry_run);
+    }
+
+    if !source.is_file() {
+        return Err(Error::ImportNoCargoHome(source.to_path_buf()));
+    }
+
+    // unpack the archive next to the local cache so that we can verify it before merging,
+    // a dry-run must not write into the cargo home though
+    let staging = if dry_run {
+        std::env::temp_dir().join(format!("cargo-cache-import-{}", std::process::id()))
+    } else {
+        cargo_cache_paths
+            .cargo_home
+            .join(".cargo-cache")
+            .join("import-staging")
+    };
+    if staging.exists() {
+        remove_dir_all::remove_dir_all(&staging)
+            .map_err(|e| Error::ArchiveIo(staging.clone(), e))?;
+    }
+    let (_manifest, mut archive) = open_archive(source)?;
+
+    let result = archive
+        .unpack(&staging)
+        .map_err(|e| Error::ArchiveIo(source.to_path_buf(), e))
+        .and_then(|()| {
+            import_cargo_home(
+                &CargoCachePaths::from_cargo_home(staging.clone()),
+                cargo_cache_paths,
+                dry_run,
+            )
+        });
+
+    // a broken archive may leave a partially unpacked staging directory behind
+    let cleanup = if staging.exists() {
+        remove_dir_all::remove_dir_all(&staging).map_err(|e| Error::ArchiveIo(staging, e))
+    } else {
+        Ok(())
+    };
+
+    // the error of the import is more interesting than the one of the cleanup
+    result.and(cleanup)
+}
+
+#[cfg(test)]
+mod importtests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    // crate "ab" version 1.0.0, checksum of the content "ab"
+    const CKSUM_AB: &str = "fb8e20fc2e4c3f248c60c39bd652f3c1347298bb977b8b4d5903b85055620603";
+
+    fn setup(name: &str) -> (CargoCachePaths, CargoCachePaths) {
+        let root = PathBuf::from("target/import_tests").join(name);
+        let _ = fs::remove_dir_all(&root);
+        let source = CargoCachePaths::from_cargo_home(root.join("source"));
+        let local = CargoCachePaths::from_cargo_home(root.join("local"));
+
+        let registry = "github.com-1ecc6299db9ec823";
+        let cache = source.registry_pkg_cache.join(registry);
+        fs::create_dir_all(&cache).unwrap();
+        fs::write(cache.join("ab-1.0.0.crate"), "ab").unwrap();
+        fs::write(cache.join("ab-2.0.0.crate"), "corrupted").unwrap();
+        fs::write(cache.join("ab-3.0.0.crate"), "not in index").unwrap();
+
+        let entry =
+            crate::clean_unref::index_cache_entry(&source.registry_index.join(registry), "ab");
+        fs::create_dir_all(entry.parent().unwrap()).unwrap();
+        let content = format!(
+            "\u{3}\u{2}\0\0\0Unknown\u{0}1.0.0\u{0}{{\"name\":\"ab\",\"vers\":\"1.0.0\",\"cksum\":\"{}\"}}\u{0}2.0.0\u{0}{{\"name\":\"ab\",\"vers\":\"2.0.0\",\"cksum\":\"{}\"}}\u{0}",
+            CKSUM_AB, CKSUM_AB
+        );
+        fs::write(&entry, content).unwrap();
+
+        fs::create_dir_all(source.git_repos_bare.join("repo-abc").join("objects")).unwrap();
+        fs::write(source.git_repos_bare.join("repo-abc").join("HEAD"), "ref").unwrap();
+
+        fs::create_dir_all(&local.cargo_home).unwrap();
+        (source, local)
+    }
+
+    #[test]
+    fn checksums_of_index_entries() {
+        let (source, _) = setup("checksums_of_index_entries");
+        let entry = crate::clean_unref::index_cache_entry(
+            &source.registry_index.join("github.com-1ecc6299db9ec823"),
+            "ab",
+        );
+        let checksums = checksums_from_index_entry(&entry);
+        assert_eq!(checksums.len(), 2);
+        assert_eq!(checksums["1.0.0"], CKSUM_AB);
+    }
+
+    #[test]
+    fn import_verifies_and_skips() {
+        let (source, local) = setup("import_verifies_and_skips");
+
+        let indices = import_indices(&source, &local, false).unwrap();
+        assert_eq!(indices.added_items, 1);
+
+        let crates = import_crates(&source, &local, false).unwrap();
+        assert_eq!(
+            crates,
+            ImportStats {
+                added_items: 2,
+                added_size: 14,
+                skipped_items: 0,
+                corrupt_items: 1,
+                unverified_items: 1,
+            }
+        );
+        let local_cache = local.registry_pkg_cache.join("github.com-1ecc6299db9ec823");
+        assert!(local_cache.join("ab-1.0.0.crate").is_file());
+        assert!(!local_cache.join("ab-2.0.0.crate").exists());
+        assert!(local_cache.join("ab-3.0.0.crate").is_file());
+
+        let git = import_git_dbs(&source, &local, false).unwrap();
+        assert_eq!(git.added_items, 1);
+        assert!(local.git_repos_bare.join("repo-abc").join("HEAD").is_file());
+
+        // a second import does not add anything
+        let crates_again = import_crates(&source, &local, false).unwrap();
+        assert_eq!(crates_again.added_items, 0);
+        assert_eq!(crates_again.skipped_items, 2);
+        let git_again = import_git_dbs(&source, &local, false).unwrap();
+        assert_eq!(git_again.skipped_items, 1);
+    }
+
+    #[test]
+    fn import_archives() {
+        use crate::commands::backup::{files_below, write_archive};
+
+        let (source, local) = setup("import_archives");
+        let root = source.cargo_home.parent().unwrap().to_path_buf();
+        // incompressible content so that a truncated archive still has its manifest
+        let mut state: u32 = 1;
+        let noise: Vec<u8> = (0..100_000)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                state.to_le_bytes()[2]
+            })
+            .collect();
+        fs::write(source.git_repos_bare.join("repo-abc").join("pack"), &noise).unwrap();
+
+        let archive_path = root.join("source.tar.gz");
+        let mut files = files_below(&source.registry, &source.cargo_home);
+        files.extend(files_below(&source.git_repos_bare, &source.cargo_home));
+        let _ =
+            write_archive(&archive_path, &["registry".into(), "git-db".into()], &files).unwrap();
+        let cargo_cache_dir = local.cargo_home.join(".cargo-cache");
+
+        // a dry-run leaves the cargo home alone
+        import(&local, &archive_path, true).unwrap();
+        assert!(!cargo_cache_dir.exists());
+        assert!(!local.registry_pkg_cache.exists());
+
+        // a broken archive does not leave the staging directory behind
+        let broken_path = root.join("broken.tar.gz");
+        let content = fs::read(&archive_path).unwrap();
+        fs::write(&broken_path, &content[..content.len() / 2]).unwrap();
+        assert!(import(&local, &broken_path, false).is_err());
+        assert!(!cargo_cache_dir.join("import-staging").exists());
+
+        import(&local, &archive_path, false).unwrap();
+        assert!(local.git_repos_bare.join("repo-abc").join("pack").is_file());
+        assert!(!cargo_cache_dir.join("import-staging").exists());
+    }
+}
@@ -23,15 +23,28 @@
 /// release:          224.26 MB
 /// other:            360.57 MB
 /// ````
+/// `cargo cache local --scan <dir>` lists the target directories of all workspaces below a directory
+/// with their size, the time of the last build and the size of each profile:
+/// ````
+/// Target dir                      Workspaces  Size     Last build        Profiles
+/// /home/matthias/vcs/foo/target   1           3.06 GB  2020-10-01 12:00  debug: 2.48 GB, release: 224.26 MB, other: 360.57 MB
+/// /home/matthias/vcs/bar/target   1           1.20 GB  2020-06-11 09:31  debug: 1.20 GB
+/// Total                           2           4.26 GB
+/// ````
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
 use std::env;
 use std::ffi::OsStr;
-use std::fs::read_dir;
-use std::path::PathBuf;
+use std::fs::{self, read_dir};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
 use cargo_metadata::MetadataCommand;
+use chrono::{DateTime, Local};
 use humansize::{file_size_opts, FileSize};
 use walkdir::WalkDir;
 
+use crate::clean_unref::scan_for_manifests;
 use crate::library;
 use crate::library::Error;
 use crate::tables::*;
@@ -210,7 +223,7 @@ pub(crate) fn local_subcmd() -> Result<(), Error> {
         })
         .filter(|f| f.exists())
         .map(|f| {
-            std::fs::metadata(&f)
+            fs::metadata(&f)
                 .unwrap_or_else(|_| panic!("Failed to get metadata of file '{}'", &f.display()))
                 .len()
         })
@@ -230,3 +243,427 @@ pub(crate) fn local_subcmd() -> Result<(), Error> {
     println!("{}", stdout);
     Ok(())
 }
+
+/// how the target dirs found by "--scan" are sorted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TargetDirOrder {
+    /// biggest first
+    Size,
+    /// by path
+    Name,
+    /// least recently built first
+    Time,
+}
+
+impl Default for TargetDirOrder {
+    fn default() -> Self {
+        Self::Size
+    }
+}
+
+impl std::str::FromStr for TargetDirOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "size" => Ok(Self::Size),
+            "name" => Ok(Self::Name),
+            "time" => Ok(Self::Time),
+            other => Err(other.to_string()),
+        }
+    }
+}
+
+/// read "build.target-dir" from a cargo config file
+fn configured_target_dir(config_file: &Path) -> Option<String> {
+    let content = fs::read_to_string(config_file).ok()?;
+    let config: toml::Value = toml::from_str(&content).ok()?;
+    config
+        .get("build")?
+        .get("target-dir")?
+        .as_str()
+        .map(str::to_string)
+}
+
+/// the target dir cargo uses when building a workspace:
+/// `$CARGO_TARGET_DIR`, "build.target-dir" of the closest .cargo/config(.toml) above the workspace,
+/// "build.target-dir" of `${CARGO_HOME}/config(.toml)` or "target" inside the workspace
+/// a "build.target-dir" is relative to the directory that contains the ".cargo" directory
+/// config files we can not parse are ignored
+pub(crate) fn target_dir_of(
+    workspace: &Path,
+    cargo_home: &Path,
+    env_target_dir: Option<&Path>,
+) -> PathBuf {
+    if let Some(target_dir) = env_target_dir {
+        return target_dir.to_path_buf();
+    }
+
+    let config_dirs = workspace
+        .ancestors()
+        .map(|dir| dir.join(".cargo"))
+        .chain(std::iter::once(cargo_home.to_path_buf()));
+
+    for config_dir in config_dirs {
+        for name in &["config.toml", "config"] {
+            if let Some(target_dir) = configured_target_dir(&config_dir.join(name)) {
+                let base = config_dir.parent().unwrap_or(&config_dir);
+                return base.join(target_dir);
+            }
+        }
+    }
+
+    workspace.join("target")
+}
+
+/// size, last build and profiles of a target dir
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct TargetDir {
+    pub(crate) path: PathBuf,
+    /// the workspaces that build into this target dir
+    pub(crate) workspaces: Vec<PathBuf>,
+    pub(crate) size: u64,
+    /// the newest modification time of a file inside of the target dir
+    pub(crate) last_build: Option<SystemTime>,
+    /// size per profile ("debug", "x86_64-unknown-linux-gnu/release"), everything else is "other"
+    pub(crate) profiles: BTreeMap<String, u64>,
+}
+
+/// cargo creates a ".fingerprint" directory inside of each profile directory
+fn is_profile_dir(dir: &Path) -> bool {
+    dir.join(".fingerprint").is_dir()
+}
+
+fn subdirs(dir: &Path) -> Vec<PathBuf> {
+    read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .filter(|path| path.is_dir())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+impl TargetDir {
+    /// walk a target dir once and gather its size, last build and profiles
+    pub(crate) fn new(path: PathBuf, workspaces: Vec<PathBuf>) -> Self {
+        // profiles are either directly inside of the target dir or inside of a <target triple> dir
+        let mut profile_dirs: Vec<(String, PathBuf)> = Vec::new();
+        for dir in subdirs(&path) {
+            let name = dir.file_name().unwrap().to_string_lossy().to_string();
+            if is_profile_dir(&dir) {
+                profile_dirs.push((name, dir));
+            } else {
+                for sub in subdirs(&dir) {
+                    if is_profile_dir(&sub) {
+                        let profile = sub.file_name().unwrap().to_string_lossy().to_string();
+                        profile_dirs.push((format!("{}/{}", name, profile), sub));
+                    }
+                }
+            }
+        }
+
+        let mut size = 0;
+        let mut last_build = None;
+        let mut profiles: BTreeMap<String, u64> = BTreeMap::new();
+        let files = WalkDir::new(&path)
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(|entry| !entry.file_type().is_dir())
+            .filter_map(|entry| entry.metadata().ok().map(|metadata| (entry, metadata)));
+        for (entry, metadata) in files {
+            size += metadata.len();
+            last_build = last_build.max(metadata.modified().ok());
+            let profile = profile_dirs
+                .iter()
+                .find(|(_, dir)| entry.path().starts_with(dir))
+                .map_or("other", |(name, _)| name.as_str());
+            *profiles.entry(profile.to_string()).or_insert(0) += metadata.len();
+        }
+
+        Self {
+            path,
+            workspaces,
+            size,
+            last_build,
+            profiles,
+        }
+    }
+
+    /// "debug: 2.48 GB, release: 224.26 MB, other: 360.57 MB", biggest profile first, "other" last
+    fn profile_breakdown(&self) -> String {
+        let mut profiles: Vec<(&String, &u64)> = self
+            .profiles
+            .iter()
+            .filter(|(name, _)| *name != "other")
+            .collect();
+        profiles.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        profiles.extend(self.profiles.get_key_value("other"));
+        profiles
+            .iter()
+            .map(|(name, size)| {
+                format!(
+                    "{}: {}",
+                    name,
+                    size.file_size(file_size_opts::DECIMAL).unwrap()
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// find the workspaces below a directory and gather their target dirs
+/// workspaces sharing a target dir (via `$CARGO_TARGET_DIR` for example) are listed together
+pub(crate) fn scan_target_dirs(
+    dir: &Path,
+    cargo_home: &Path,
+    env_target_dir: Option<&Path>,
+) -> Result<Vec<TargetDir>, Error> {
+    let manifests = scan_for_manifests(dir, cargo_home);
+    if manifests.is_empty() {
+        return Err(Error::ScanNoManifests(dir.to_path_buf()));
+    }
+
+    let mut workspaces_of_target_dir: BTreeMap<PathBuf, Vec<PathBuf>> = BTreeMap::new();
+    for manifest in &manifests {
+        let workspace = manifest.parent().unwrap().to_path_buf();
+        let target_dir = target_dir_of(&workspace, cargo_home, env_target_dir);
+        workspaces_of_target_dir
+            .entry(target_dir)
+            .or_default()
+            .push(workspace);
+    }
+
+    Ok(workspaces_of_target_dir
+        .into_iter()
+        // nothing was built yet
+        .filter(|(target_dir, _)| target_dir.is_dir())
+        .map(|(target_dir, workspaces)| TargetDir::new(target_dir, workspaces))
+        .collect())
+}
+
+/// sort the target dirs and format them as a table
+pub(crate) fn target_dirs_table(target_dirs: &mut [TargetDir], order: TargetDirOrder) -> String {
+    match order {
+        TargetDirOrder::Size => target_dirs.sort_by_key(|target_dir| Reverse(target_dir.size)),
+        TargetDirOrder::Name => target_dirs.sort_by(|a, b| a.path.cmp(&b.path)),
+        TargetDirOrder::Time => target_dirs.sort_by_key(|target_dir| target_dir.last_build),
+    }
+
+    let human = |size: u64| size.file_size(file_size_opts::DECIMAL).unwrap();
+
+    let mut table = vec![vec![
+        "Target dir".to_string(),
+        "Workspaces".to_string(),
+        "Size".to_string(),
+        "Last build".to_string(),
+        "Profiles".to_string(),
+    ]];
+    for target_dir in target_dirs.iter() {
+        table.push(vec![
+            target_dir.path.display().to_string(),
+            target_dir.workspaces.len().to_string(),
+            human(target_dir.size),
+            target_dir.last_build.map_or_else(String::new, |time| {
+                DateTime::<Local>::from(time)
+                    .format("%Y-%m-%d %H:%M")
+                    .to_string()
+            }),
+            target_dir.profile_breakdown(),
+        ]);
+    }
+    table.push(vec![
+        "Total".to_string(),
+        target_dirs
+            .iter()
+            .map(|target_dir| target_dir.workspaces.len())
+            .sum::<usize>()
+            .to_string(),
+        human(target_dirs.iter().map(|target_dir| target_dir.size).sum()),
+        String::new(),
+        String::new(),
+    ]);
+    format_table(&table, 1)
+}
+
+/// print the target dirs of all workspaces below a directory
+pub(crate) fn scan_subcmd(
+    dir: &Path,
+    cargo_home: &Path,
+    order: TargetDirOrder,
+) -> Result<(), Error> {
+    // a relative $CARGO_TARGET_DIR is relative to the directory cargo is run from
+    let env_target_dir = env::var_os("CARGO_TARGET_DIR").map(|target_dir| {
+        env::current_dir().map_or_else(|_| PathBuf::from(&target_dir), |cwd| cwd.join(&target_dir))
+    });
+
+    // the .cargo/config files above the directory matter as well
+    let root = fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+    let mut target_dirs = scan_target_dirs(&root, cargo_home, env_target_dir.as_deref())?;
+    if target_dirs.is_empty() {
+        println!("No target dirs found below '{}'", dir.display());
+        return Ok(());
+    }
+    print!("{}", target_dirs_table(&mut target_dirs, order));
+    Ok(())
+}
+
+#[cfg(test)]
+mod localtests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn target_dir_overrides() {
+        let root = PathBuf::from("target/local_tests/target_dir_overrides");
+        let _ = fs::remove_dir_all(&root);
+        let cargo_home = root.join("cargo_home");
+        let plain = root.join("plain");
+        let configured = root.join("group").join("configured");
+        for dir in &[&cargo_home, &plain, &configured.join(".cargo")] {
+            fs::create_dir_all(dir).unwrap();
+        }
+
+        assert_eq!(
+            target_dir_of(&plain, &cargo_home, None),
+            plain.join("target")
+        );
+        // $CARGO_TARGET_DIR always wins
+        assert_eq!(
+            target_dir_of(&plain, &cargo_home, Some(Path::new("/tmp/shared"))),
+            PathBuf::from("/tmp/shared")
+        );
+
+        // relative to the directory containing .cargo
+        fs::write(
+            configured.join(".cargo").join("config.toml"),
+            "[build]\ntarget-dir = \"build\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            target_dir_of(&configured, &cargo_home, None),
+            configured.join("build")
+        );
+
+        // a config further up applies to all workspaces below it
+        fs::create_dir_all(root.join("group").join(".cargo")).unwrap();
+        fs::write(
+            root.join("group").join(".cargo").join("config"),
+            "[build]\ntarget-dir = \"/tmp/group-target\"\n",
+        )
+        .unwrap();
+        let member = root.join("group").join("other");
+        assert_eq!(
+            target_dir_of(&member, &cargo_home, None),
+            PathBuf::from("/tmp/group-target")
+        );
+        assert_eq!(
+            target_dir_of(&configured, &cargo_home, None),
+            configured.join("build")
+        );
+
+        // the config in the cargo home is relative to the parent of the cargo home
+        fs::write(
+            cargo_home.join("config.toml"),
+            "[build]\ntarget-dir = \"global-target\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            target_dir_of(&plain, &cargo_home, None),
+            root.join("global-target")
+        );
+    }
+
+    #[test]
+    fn scan_and_breakdown() {
+        let root = PathBuf::from("target/local_tests/scan_and_breakdown");
+        let _ = fs::remove_dir_all(&root);
+        let cargo_home = root.join("cargo_home");
+        fs::create_dir_all(&cargo_home).unwrap();
+
+        let write = |path: PathBuf, size: usize| {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "x".repeat(size)).unwrap();
+        };
+        // a workspace with a debug, a release and a cross compiled debug build
+        let big = root.join("big");
+        write(big.join("Cargo.toml"), 0);
+        write(
+            big.join("target")
+                .join("debug")
+                .join(".fingerprint")
+                .join("a"),
+            100,
+        );
+        write(big.join("target").join("debug").join("big"), 300);
+        write(
+            big.join("target")
+                .join("release")
+                .join(".fingerprint")
+                .join("a"),
+            50,
+        );
+        let triple = big
+            .join("target")
+            .join("wasm32-unknown-unknown")
+            .join("debug");
+        write(triple.join(".fingerprint").join("a"), 20);
+        write(big.join("target").join("doc").join("index.html"), 10);
+        write(big.join("target").join(".rustc_info.json"), 5);
+        // a workspace that was never built
+        write(root.join("unbuilt").join("Cargo.toml"), 0);
+        // two workspaces sharing a target dir
+        write(root.join("shared").join("a").join("Cargo.toml"), 0);
+        write(root.join("shared").join("b").join("Cargo.toml"), 0);
+        fs::create_dir_all(root.join("shared").join(".cargo")).unwrap();
+        fs::write(
+            root.join("shared").join(".cargo").join("config.toml"),
+            "[build]\ntarget-dir = \"target\"\n",
+        )
+        .unwrap();
+        write(
+            root.join("shared")
+                .join("target")
+                .join("debug")
+                .join(".fingerprint")
+                .join("a"),
+            40,
+        );
+
+        let mut target_dirs = scan_target_dirs(&root, &cargo_home, None).unwrap();
+        assert_eq!(target_dirs.len(), 2);
+
+        let big_target = &target_dirs[0];
+        assert_eq!(big_target.path, big.join("target"));
+        assert_eq!(big_target.workspaces, vec![big.clone()]);
+        assert_eq!(big_target.size, 485);
+        assert!(big_target.last_build.is_some());
+        assert_eq!(
+            big_target.profile_breakdown(),
+            "debug: 400 B, release: 50 B, wasm32-unknown-unknown/debug: 20 B, other: 15 B"
+        );
+
+        let shared_target = &target_dirs[1];
+        assert_eq!(shared_target.path, root.join("shared").join("target"));
+        assert_eq!(
+            shared_target.workspaces,
+            vec![root.join("shared").join("a"), root.join("shared").join("b")]
+        );
+
+        // biggest first by default, oldest build first when sorted by time
+        let table = target_dirs_table(&mut target_dirs, TargetDirOrder::Size);
+        let lines: Vec<&str> = table.lines().collect();
+        assert!(lines[0].starts_with("Target dir"));
+        assert!(lines[1].starts_with(&big.join("target").display().to_string()));
+        assert!(lines[3].starts_with("Total"));
+        assert!(lines[3].contains("525 B"));
+
+        let _ = target_dirs_table(&mut target_dirs, TargetDirOrder::Time);
+        assert!(target_dirs[0].last_build <= target_dirs[1].last_build);
+
+        assert!(scan_target_dirs(&cargo_home, &cargo_home, None).is_err());
+    }
+}
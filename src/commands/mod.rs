@@ -8,7 +8,14 @@
 // except according to those terms.
 
 // code related to subcommands is located here
+pub(crate) mod backup;
+pub(crate) mod export;
+pub(crate) mod fingerprint;
+pub(crate) mod import;
 pub(crate) mod local;
 pub(crate) mod query;
 pub(crate) mod sccache;
 pub(crate) mod trim;
+#[cfg(feature = "tui")]
+pub(crate) mod tui;
+pub(crate) mod why;
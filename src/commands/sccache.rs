@@ -19,6 +19,7 @@ use walkdir::WalkDir;
 
 use crate::library;
 use crate::tables::format_table;
+use crate::timestamps::TimeSource;
 
 #[derive(Debug, Clone)]
 struct File {
@@ -51,10 +52,10 @@ fn sccache_dir() -> Result<PathBuf, library::Error> {
         .ok_or(library::Error::NoSccacheDir)
 }
 
-pub(crate) fn sccache_stats() -> Result<(), library::Error> {
+pub(crate) fn sccache_stats(time_source: TimeSource) -> Result<(), library::Error> {
     let sccache_path: PathBuf = sccache_dir()?;
 
-    // of all the files inside the sccache cache, gather last access time and path
+    // of all the files inside the sccache cache, gather time of last use and path
     let files = WalkDir::new(sccache_path.display().to_string())
         .into_iter()
         .filter_map(|direntry| {
@@ -62,7 +63,7 @@ pub(crate) fn sccache_stats() -> Result<(), library::Error> {
                 let path = direntry.path().to_path_buf();
                 if path.is_file() {
                     if let Ok(metadata) = fs::metadata(&path) {
-                        if let Ok(access_time) = metadata.accessed() {
+                        if let Some(access_time) = time_source.of(&metadata) {
                             let access_time =
                                 chrono::DateTime::<Local>::from(access_time).naive_local();
                             let access_date = access_time.date();
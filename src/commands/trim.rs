@@ -9,232 +9,1193 @@
 
 // "cargo cache trim" command
 // trim the size of the cargo cache down to a certain limit.
-// note that this does not take account the registry indices and the installed binaries in calculations
+// the limit applies to the whole cargo home, including the registry indices and the installed binaries.
+// Additionally, each component can get its own quota which is enforced before the overall limit.
+// "--ensure-free" removes the least recently used items until the file system that holds the
+// cargo home has enough free space.
+// By default the least recently used items are removed first, "--strategy rebuild-cost" removes the
+// extracted sources and checkouts before the archives and git repos they were extracted from and
+// "--strategy size" removes the biggest items first.
+// Registry indices are only removed as a last resort since every build needs them and binaries are
+// only removed if the user explicitly allows it.
+// An extracted source or checkout is never kept once its archive or git repo is removed.
+// "--dry-run" prints the plan: every item that would be removed in the order of removal,
+// "--dry-run --format json" prints the plan as json so that it can be reviewed or diffed.
 
+use std::collections::BTreeMap;
 use std::fmt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
+use crate::audit::AuditLog;
+use crate::cache::bin::{forget_installed_binaries, is_rustup_proxy};
 use crate::cache::caches::*;
 use crate::cache::*;
+use crate::config::TrimConfig;
+use crate::confirm::{confirm_removal, Confirm, PlannedRemoval};
 use crate::library::*;
+use crate::lockfile::has_hash_suffix;
 use crate::remove::*;
+use crate::size::{parse_size, SizeError};
+use crate::tables::format_table;
+use crate::timestamps::{last_use, TimeSource};
+use crate::trash::Trash;
 
+use chrono::{DateTime, Local, SecondsFormat};
 use humansize::{file_size_opts, FileSize};
-use walkdir::WalkDir;
+use serde::{Serialize, Serializer};
 
 #[derive(Debug, Eq, PartialEq)]
 pub(crate) enum TrimError<'a> {
-    // failed to parse the unit of a `cargo cache trim --limit 123G` argument
-    TrimLimitUnitParseFailure(&'a str),
+    // failed to parse a size like `cargo cache trim --limit 123G`
+    InvalidSize(SizeError<'a>),
+    // neither --limit nor --quota nor the config file told us what to do
+    NoLimit,
+    // a quota was not of the form COMPONENT=SIZE
+    QuotaMalformed(&'a str),
+    // a quota was given for a component we don't know
+    UnknownComponent(&'a str),
+    // failed to parse the argument of --ensure-free
+    FreeSpaceParseFailure(&'a str),
+    // failed to query the free space of the file system
+    DiskSpaceUnavailable(PathBuf, String),
+    // even removing everything we may remove does not free enough space: needed, removable, binaries removable
+    CannotFreeEnough(u64, u64, bool),
+    // the strategy from the config file is not known
+    UnknownStrategy(&'a str),
+    // failed to drop the removed binaries from the install metadata
+    InstallMetadata(String),
+    // the user did not confirm the removal
+    Aborted,
+    // quarantined items stay on the file system, so they can not free any space
+    EnsureFreeQuarantined,
+    // the json plan would be mixed up with the confirmation and the progress output
+    JsonWithoutDryRun,
 }
 
 impl fmt::Display for TrimError<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match &self {
-            Self::TrimLimitUnitParseFailure(limit) => {
-                write!(f, "Failed to parse limit: \"{}\". Should be of the form 123X where X is one of B,K,M,G or T.", limit)
+            Self::InvalidSize(error) => write!(f, "{}", error),
+            Self::NoLimit => {
+                write!(f, "No limit was given, pass \"--limit\" or \"--quota\" or set them in the config file.")
+            }
+            Self::QuotaMalformed(quota) => {
+                write!(f, "Failed to parse quota: \"{}\". Should be of the form COMPONENT=SIZE, for example \"git-repos=500M\".", quota)
+            }
+            Self::UnknownComponent(component) => {
+                write!(f, "Unknown component: \"{}\". Should be one of git-db,git-repos,registry-sources,registry-crate-cache,registry-index,bin.", component)
+            }
+            Self::FreeSpaceParseFailure(free) => {
+                write!(f, "Failed to parse free space: \"{}\". Should be a size like \"10G\" or a percentage like \"15%\".", free)
+            }
+            Self::DiskSpaceUnavailable(path, error) => {
+                write!(
+                    f,
+                    "Failed to query the free space of the file system of '{}': '{}'",
+                    path.display(),
+                    error
+                )
+            }
+            Self::CannotFreeEnough(needed, removable, remove_bins) => {
+                let human = |size: &u64| size.file_size(file_size_opts::DECIMAL).unwrap();
+                write!(f, "Can not free {}: removing everything from the cargo home that may be removed only frees {}. Nothing was removed.{}",
+                    human(needed),
+                    human(removable),
+                    if *remove_bins { "" } else { " Pass \"--remove-bins\" to allow removing installed binaries." })
+            }
+            Self::UnknownStrategy(strategy) => {
+                write!(
+                    f,
+                    "Unknown trim strategy: \"{}\". Should be one of lru,rebuild-cost,size.",
+                    strategy
+                )
+            }
+            Self::InstallMetadata(error) => write!(f, "{}", error),
+            Self::Aborted => write!(f, "{}", Error::RemovalAborted),
+            Self::EnsureFreeQuarantined => {
+                write!(f, "\"--ensure-free\" can not be combined with \"--quarantine\": items in the trash still use disk space. Nothing was removed.")
+            }
+            Self::JsonWithoutDryRun => {
+                write!(f, "\"--format json\" only prints the plan, pass \"--dry-run\" as well. Nothing was removed.")
             }
         }
     }
 }
 
-fn get_last_access_of_item(path: &PathBuf) -> std::time::SystemTime {
-    if path.is_file() {
-        // if we have a file, simply get the accesss time
-        std::fs::metadata(path).unwrap().accessed().unwrap()
-    } else {
-        // if we have a directory, get the latest access of all files of that directory
-        // get the max time / the file with the youngest access date / most recently accessed
-        WalkDir::new(path)
-            .into_iter()
-            .map(|e| e.unwrap().path().to_owned())
-            .map(|path| std::fs::metadata(path).unwrap().accessed().unwrap()) //@TODO make this an reusable function/method to simplify code
-            .max()
-            .unwrap()
-    }
-}
-
-// get a list of all cache items, sorted by file access time (young to old)
-pub(crate) fn gather_all_cache_items<'a>(
-    git_checkouts_cache: &'a mut git_checkouts::GitCheckoutCache,
-    bare_repos_cache: &'a mut git_bare_repos::GitRepoCache,
-    registry_pkg_cache: &'a mut registry_pkg_cache::RegistryPkgCaches,
-    registry_sources_cache: &'a mut registry_sources::RegistrySourceCaches,
-) -> Vec<&'a PathBuf> {
-    let mut all_items: Vec<&PathBuf> = Vec::new();
-    all_items.extend(git_checkouts_cache.items());
-    all_items.extend(bare_repos_cache.items());
-    all_items.extend(registry_pkg_cache.items());
-    all_items.extend(registry_sources_cache.items());
-
-    // calculating the last access for each path ever time is not cheap, so use caching
-    // sort from youngest to oldest
-    all_items.sort_by_cached_key(|path| get_last_access_of_item(path));
-    // reverse the vec so that youngest access dates come first
-    // [2020, 2019, 2018, ....]
-    all_items.reverse();
-
-    all_items
-}
-
-/// figure out how big the cache should remain after trimming
-fn parse_size_limit_to_bytes<'a>(limit: &Option<&'a str>) -> Result<u64, TrimError<'a>> {
-    match limit {
-        None => unreachable!("No trim --limit was supplied altough clap should enforce that!"),
-        Some(limit) => {
-            // figure out the unit
-            let unit_multiplicator: Result<u64, TrimError<'a>> = match limit.chars().last() {
-                // we have no limit
-                None => Ok(0),
-                // we expect a unit such as B, K, M, G, T...
-                Some(c) => {
-                    if c.is_alphabetic() {
-                        match c {
-                            'b' | 'B' => Ok(1),
-                            'k' | 'K' => Ok(1024),
-                            'm' | 'M' => Ok(1024 * 1024),
-                            'g' | 'G' => Ok(1024 * 1024 * 1024),
-                            't' | 'T' => Ok(1024 * 1024 * 1024 * 1024),
-                            _ => Err(TrimError::TrimLimitUnitParseFailure(limit)),
-                        }
-                    } else {
-                        Err(TrimError::TrimLimitUnitParseFailure(limit))
-                    }
-                }
-            };
-            let value: f64 = limit[0..(limit.len() - 1)].parse().unwrap();
-            if value == 0.0 {
-                return Ok(0);
+/// how much space should be free on the file system of the cargo home
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum FreeSpace {
+    Bytes(u64),
+    /// percent of the total size of the file system
+    Percent(f64),
+}
+
+impl FreeSpace {
+    /// "10G" or "15%"
+    fn parse(input: &str) -> Result<Self, TrimError<'_>> {
+        let trimmed = input.trim();
+        if let Some(percent) = trimmed.strip_suffix('%') {
+            match percent.trim().parse::<f64>() {
+                Ok(value) if (0.0..=100.0).contains(&value) => Ok(Self::Percent(value)),
+                _ => Err(TrimError::FreeSpaceParseFailure(trimmed)),
             }
+        } else {
+            parse_size(trimmed)
+                .map(Self::Bytes)
+                .map_err(TrimError::InvalidSize)
+        }
+    }
+
+    /// how many bytes we have to remove to reach the requested free space
+    fn bytes_to_free(self, available: u64, total: u64) -> u64 {
+        let wanted = match self {
+            Self::Bytes(bytes) => bytes,
             // we may truncate the value here but that's ok
-            #[allow(clippy::cast_lossless)]
             #[allow(clippy::cast_sign_loss)]
             #[allow(clippy::cast_possible_truncation)]
             #[allow(clippy::cast_precision_loss)]
-            Ok((value * unit_multiplicator? as f64) as u64)
+            Self::Percent(percent) => (total as f64 * percent / 100.0) as u64,
+        };
+        wanted.saturating_sub(available)
+    }
+}
+
+/// the order in which items are removed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TrimStrategy {
+    /// least recently used items first
+    Lru,
+    /// items that are cheap to get back first: extracted sources and checkouts before
+    /// crate archives and git repos, least recently used first within each group
+    RebuildCost,
+    /// biggest items first
+    Size,
+}
+
+impl Default for TrimStrategy {
+    fn default() -> Self {
+        Self::Lru
+    }
+}
+
+impl TrimStrategy {
+    fn parse(input: &str) -> Result<Self, TrimError<'_>> {
+        match input.trim() {
+            "lru" => Ok(Self::Lru),
+            "rebuild-cost" => Ok(Self::RebuildCost),
+            "size" => Ok(Self::Size),
+            _ => Err(TrimError::UnknownStrategy(input)),
+        }
+    }
+}
+
+/// the sizes the cargo home is trimmed to
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct TrimLimits {
+    /// limit of the whole cargo home
+    overall: Option<u64>,
+    /// limits of single components: "git-repos" => 500M
+    quotas: BTreeMap<String, u64>,
+    /// free space we want to have on the file system of the cargo home
+    ensure_free: Option<FreeSpace>,
+    /// which items go first
+    strategy: TrimStrategy,
+}
+
+impl TrimLimits {
+    /// combine the limits from the command line with the ones from the config file,
+    /// the command line takes precedence
+    pub(crate) fn new<'a>(
+        limit: Option<&'a str>,
+        quotas: &[&'a str],
+        ensure_free: Option<&'a str>,
+        strategy: Option<&'a str>,
+        config: &'a TrimConfig,
+    ) -> Result<Self, TrimError<'a>> {
+        let overall = match limit.or(config.limit.as_deref()) {
+            Some(value) => Some(parse_size(value).map_err(TrimError::InvalidSize)?),
+            None => None,
+        };
+
+        let mut parsed_quotas = BTreeMap::new();
+        let from_config = config
+            .quotas
+            .iter()
+            .map(|(component, size)| (component.as_str(), size.as_str()));
+        let from_cli = quotas
+            .iter()
+            .map(|quota| match quota.find('=') {
+                Some(pos) => Ok((quota[..pos].trim(), quota[pos + 1..].trim())),
+                None => Err(TrimError::QuotaMalformed(quota)),
+            })
+            .collect::<Result<Vec<(&str, &str)>, TrimError<'a>>>()?;
+
+        // later quotas override earlier ones
+        for (component, size) in from_config.chain(from_cli) {
+            if component.parse::<Component>().is_err() {
+                return Err(TrimError::UnknownComponent(component));
+            }
+            let _ = parsed_quotas.insert(
+                component.to_string(),
+                parse_size(size).map_err(TrimError::InvalidSize)?,
+            );
+        }
+
+        let free = match ensure_free.or(config.ensure_free.as_deref()) {
+            Some(value) => Some(FreeSpace::parse(value)?),
+            None => None,
+        };
+
+        if overall.is_none() && parsed_quotas.is_empty() && free.is_none() {
+            return Err(TrimError::NoLimit);
+        }
+
+        let parsed_strategy = match strategy.or(config.strategy.as_deref()) {
+            Some(value) => TrimStrategy::parse(value)?,
+            None => TrimStrategy::default(),
+        };
+
+        Ok(Self {
+            overall,
+            quotas: parsed_quotas,
+            ensure_free: free,
+            strategy: parsed_strategy,
+        })
+    }
+}
+
+/// an item of the cargo home that counts toward the limit
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TrimItem {
+    path: PathBuf,
+    /// the part of the cargo home the item belongs to
+    component: String,
+    size: u64,
+    last_access: SystemTime,
+    /// protected items count toward the limit but are never removed
+    protected: bool,
+}
+
+impl TrimItem {
+    fn new(path: &Path, component: &str, protected: bool, time_source: TimeSource) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            component: component.to_string(),
+            size: size_of_path(&path.to_path_buf()),
+            // items without a timestamp count as never used
+            last_access: last_use(path, time_source).unwrap_or(SystemTime::UNIX_EPOCH),
+            protected,
+        }
+    }
+}
+
+/// an item that is removed, in the order of removal
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct PlanEntry {
+    component: String,
+    registry: Option<String>,
+    name: Option<String>,
+    version: Option<String>,
+    path: PathBuf,
+    size: u64,
+    #[serde(serialize_with = "serialize_time")]
+    last_use: DateTime<Local>,
+    /// size of all items removed up to and including this one
+    total: u64,
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)] // required by serde
+fn serialize_time<S: Serializer>(time: &DateTime<Local>, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&time.to_rfc3339_opts(SecondsFormat::Secs, false))
+}
+
+fn file_name(path: &Path) -> Option<String> {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+}
+
+/// strip the hash from a git repo dir: "cargo-cache-1c5d1c8d7e4b5a3f" => "cargo-cache"
+fn strip_hash(dir_name: &str) -> String {
+    match dir_name.rfind('-') {
+        Some(pos) if has_hash_suffix(dir_name, &dir_name[..=pos]) => dir_name[..pos].to_string(),
+        _ => dir_name.to_string(),
+    }
+}
+
+/// the registry, name and version of an item of the cache, as far as they are known:
+/// `registry/cache/<registry>/serde-1.0.0.crate` => (`<registry>`, serde, 1.0.0)
+/// `git/checkouts/<repo>-<hash>/<rev>` => (None, `<repo>`, `<rev>`)
+pub(crate) fn item_names(
+    component: &str,
+    path: &Path,
+) -> (Option<String>, Option<String>, Option<String>) {
+    let parent_name = path.parent().and_then(file_name);
+    if component == Component::RegistryCrateCache.to_string()
+        || component == Component::RegistrySources.to_string()
+    {
+        // parse_version() strips the extension, make sure it does not cut off the patch version of sources
+        let mut archive = path.file_name().unwrap_or_default().to_os_string();
+        if component == Component::RegistrySources.to_string() {
+            archive.push(".crate");
+        }
+        match parse_version(&PathBuf::from(archive)) {
+            Ok((name, version)) => (parent_name, Some(name), Some(version)),
+            Err(_) => (parent_name, file_name(path), None),
+        }
+    } else if component == Component::GitDB.to_string() {
+        (None, file_name(path).map(|dir| strip_hash(&dir)), None)
+    } else if component == Component::GitRepos.to_string() {
+        (
+            None,
+            parent_name.map(|dir| strip_hash(&dir)),
+            file_name(path),
+        )
+    } else if component == Component::RegistryIndex.to_string() {
+        (file_name(path), None, None)
+    } else {
+        (None, file_name(path), None)
+    }
+}
+
+impl PlanEntry {
+    fn new(item: &TrimItem, total: u64) -> Self {
+        let (registry, name, version) = item_names(&item.component, &item.path);
+
+        Self {
+            component: item.component.clone(),
+            registry,
+            name,
+            version,
+            path: item.path.clone(),
+            size: item.size,
+            last_use: DateTime::<Local>::from(item.last_access),
+            total,
+        }
+    }
+}
+
+/// the removed items in the order in which they are removed, with the running total
+fn removal_plan(removed: &[&TrimItem]) -> Vec<PlanEntry> {
+    let mut total = 0;
+    removed
+        .iter()
+        .map(|item| {
+            total += item.size;
+            PlanEntry::new(item, total)
+        })
+        .collect()
+}
+
+fn plan_table(plan: &[PlanEntry]) -> String {
+    let human = |size: u64| size.file_size(file_size_opts::DECIMAL).unwrap();
+    let mut table = vec![vec![
+        "Component".to_string(),
+        "Registry".to_string(),
+        "Name".to_string(),
+        "Version".to_string(),
+        "Size".to_string(),
+        "Last use".to_string(),
+        "Total".to_string(),
+    ]];
+    for entry in plan {
+        table.push(vec![
+            entry.component.clone(),
+            entry.registry.clone().unwrap_or_default(),
+            entry.name.clone().unwrap_or_default(),
+            entry.version.clone().unwrap_or_default(),
+            human(entry.size),
+            entry.last_use.format("%Y-%m-%d %H:%M:%S").to_string(),
+            human(entry.total),
+        ]);
+    }
+    format_table(&table, 1)
+}
+
+/// sort items by access time, youngest first
+fn sort_by_last_access(items: &mut [TrimItem]) {
+    items.sort_by_key(|item| item.last_access);
+    // reverse the vec so that youngest access dates come first
+    // [2020, 2019, 2018, ....]
+    items.reverse();
+}
+
+/// whether the item was extracted from another item of the cargo home and can be recreated from it
+fn is_extracted(item: &TrimItem) -> bool {
+    item.component == Component::RegistrySources.to_string()
+        || item.component == Component::GitRepos.to_string()
+}
+
+/// sort the removable items in the order in which we want to keep them
+fn sort_by_strategy(items: &mut [TrimItem], strategy: TrimStrategy) {
+    sort_by_last_access(items);
+    // the sorts are stable, so items of the same rebuild cost or size remain sorted by access time
+    match strategy {
+        TrimStrategy::Lru => {}
+        TrimStrategy::RebuildCost => items.sort_by_key(is_extracted),
+        TrimStrategy::Size => items.sort_by_key(|item| item.size),
+    }
+}
+
+/// the path an archive or a git repo was extracted to:
+/// `registry/cache/<registry>/<crate>.crate` => `registry/src/<registry>/<crate>`
+/// `git/db/<repo>` => `git/checkouts/<repo>`
+fn extracted_path(item: &TrimItem) -> Option<PathBuf> {
+    let parent = item.path.parent()?;
+    if item.component == Component::RegistryCrateCache.to_string() {
+        let registry = parent.file_name()?;
+        Some(
+            parent
+                .parent()?
+                .parent()?
+                .join("src")
+                .join(registry)
+                .join(item.path.file_stem()?),
+        )
+    } else if item.component == Component::GitDB.to_string() {
+        Some(
+            parent
+                .parent()?
+                .join("checkouts")
+                .join(item.path.file_name()?),
+        )
+    } else {
+        None
+    }
+}
+
+// get a list of all items of the cargo home, in the order in which we want to keep them:
+// protected items first, then the registry indices sorted from young to old and then the rest,
+// sorted by the strategy
+#[allow(clippy::too_many_arguments)]
+fn gather_all_cache_items(
+    strategy: TrimStrategy,
+    time_source: TimeSource,
+    remove_bins: bool,
+    bin_cache: &mut bin::BinaryCache,
+    git_checkouts_cache: &mut git_checkouts::GitCheckoutCache,
+    bare_repos_cache: &mut git_bare_repos::GitRepoCache,
+    registry_pkg_cache: &mut registry_pkg_cache::RegistryPkgCaches,
+    registry_sources_cache: &mut registry_sources::RegistrySourceCaches,
+    registry_index_caches: &mut registry_index::RegistryIndicesCache,
+) -> Vec<TrimItem> {
+    let mut protected: Vec<TrimItem> = Vec::new();
+    let mut pool: Vec<TrimItem> = Vec::new();
+
+    let bin = Component::Bin.to_string();
+    for binary in bin_cache.items() {
+        if remove_bins && !is_rustup_proxy(binary) {
+            pool.push(TrimItem::new(binary, &bin, false, time_source));
+        } else {
+            protected.push(TrimItem::new(binary, &bin, true, time_source));
+        }
+    }
+
+    let mut indices: Vec<TrimItem> = registry_index_caches
+        .items()
+        .iter()
+        .map(|index| {
+            TrimItem::new(
+                index,
+                &Component::RegistryIndex.to_string(),
+                false,
+                time_source,
+            )
+        })
+        .collect();
+    sort_by_last_access(&mut indices);
+
+    let components: [(&[PathBuf], Component); 4] = [
+        (git_checkouts_cache.items(), Component::GitRepos),
+        (bare_repos_cache.items(), Component::GitDB),
+        (registry_pkg_cache.items(), Component::RegistryCrateCache),
+        (registry_sources_cache.items(), Component::RegistrySources),
+    ];
+    for (items, component) in &components {
+        let name = component.to_string();
+        pool.extend(
+            items
+                .iter()
+                .map(|item| TrimItem::new(item, &name, false, time_source)),
+        );
+    }
+    sort_by_strategy(&mut pool, strategy);
+
+    protected.into_iter().chain(indices).chain(pool).collect()
+}
+
+/// walk the items in the order in which we want to keep them and pick everything for removal
+/// once we exceed a limit: first each component against its own quota, then all remaining
+/// items against the overall limit.
+/// If that did not free `bytes_to_free`, continue with the items we want to keep the least.
+/// Finally remove the sources and checkouts whose archive or git repo is removed.
+fn items_to_remove<'i>(
+    items: &'i [TrimItem],
+    limits: &TrimLimits,
+    bytes_to_free: u64,
+) -> Vec<&'i TrimItem> {
+    let mut remove = vec![false; items.len()];
+
+    for (component, quota) in &limits.quotas {
+        let mut component_size = 0;
+        for (idx, item) in items
+            .iter()
+            .enumerate()
+            .filter(|(_, item)| &item.component == component)
+        {
+            component_size += item.size;
+            // keep all items (for deletion) once we have exceeded the quota
+            remove[idx] = !item.protected && component_size > *quota;
+        }
+    }
+
+    if let Some(size_limit) = limits.overall {
+        let mut cache_size = 0;
+        for (idx, item) in items.iter().enumerate() {
+            if remove[idx] {
+                continue;
+            }
+            // add the item size to the cache size
+            cache_size += item.size;
+            // keep all items (for deletion) once we have exceeded the cache size
+            remove[idx] = !item.protected && cache_size > size_limit;
+        }
+    }
+
+    let mut freed: u64 = items
+        .iter()
+        .zip(&remove)
+        .filter(|(_, removed)| **removed)
+        .map(|(item, _)| item.size)
+        .sum();
+    for (idx, item) in items.iter().enumerate().rev() {
+        if freed >= bytes_to_free {
+            break;
         }
+        if !item.protected && !remove[idx] {
+            remove[idx] = true;
+            freed += item.size;
+        }
+    }
+
+    let orphaned: Vec<PathBuf> = items
+        .iter()
+        .zip(&remove)
+        .filter(|(_, removed)| **removed)
+        .filter_map(|(item, _)| extracted_path(item))
+        .collect();
+    for (idx, item) in items.iter().enumerate() {
+        if !item.protected && orphaned.iter().any(|dir| item.path.starts_with(dir)) {
+            remove[idx] = true;
+        }
+    }
+
+    items
+        .iter()
+        .zip(remove)
+        .filter(|(_, removed)| *removed)
+        .map(|(item, _)| item)
+        .collect()
+}
+
+/// the limits that are still exceeded after removing the items because of protected items
+fn unreachable_limits(
+    items: &[TrimItem],
+    removed: &[&TrimItem],
+    limits: &TrimLimits,
+) -> Vec<String> {
+    let remaining = |component: Option<&String>| -> u64 {
+        let matches = |item: &TrimItem| component.iter().all(|c| &item.component == *c);
+        items
+            .iter()
+            .filter(|i| matches(i))
+            .map(|i| i.size)
+            .sum::<u64>()
+            - removed
+                .iter()
+                .filter(|i| matches(i))
+                .map(|i| i.size)
+                .sum::<u64>()
+    };
+
+    let mut exceeded: Vec<String> = limits
+        .quotas
+        .iter()
+        .filter(|(component, quota)| remaining(Some(component)) > **quota)
+        .map(|(component, _)| component.clone())
+        .collect();
+    if limits.overall.iter().any(|limit| remaining(None) > *limit) {
+        exceeded.push("overall".to_string());
+    }
+    exceeded
+}
+
+/// a table with the size of each component, what we remove of it and what remains
+fn breakdown_table(items: &[TrimItem], removed: &[&TrimItem], limits: &TrimLimits) -> String {
+    let components = [
+        Component::RegistryIndex.to_string(),
+        Component::RegistryCrateCache.to_string(),
+        Component::RegistrySources.to_string(),
+        Component::GitDB.to_string(),
+        Component::GitRepos.to_string(),
+        Component::Bin.to_string(),
+    ];
+    let human = |size: u64| size.file_size(file_size_opts::DECIMAL).unwrap();
+    let size_of = |iter: &mut dyn Iterator<Item = &TrimItem>, component: Option<&String>| -> u64 {
+        iter.filter(|item| component.iter().all(|c| &item.component == *c))
+            .map(|item| item.size)
+            .sum()
+    };
+
+    let mut table = vec![vec![
+        "Component".to_string(),
+        "Size".to_string(),
+        "Removed".to_string(),
+        "Remaining".to_string(),
+        "Limit".to_string(),
+    ]];
+    for component in components.iter().map(Some).chain(std::iter::once(None)) {
+        let size = size_of(&mut items.iter(), component);
+        let removed_size = size_of(&mut removed.iter().copied(), component);
+        let limit = match component {
+            Some(name) => limits.quotas.get(name).copied(),
+            None => limits.overall,
+        };
+        table.push(vec![
+            component.cloned().unwrap_or_else(|| "Total".to_string()),
+            human(size),
+            human(removed_size),
+            human(size - removed_size),
+            limit.map_or_else(|| "-".to_string(), human),
+        ]);
     }
+    format_table(&table, 1)
 }
 
-// this is the function that trim sthe cache to a given limit
+// this is the function that trims the cache to a given limit
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn trim_cache<'a>(
-    unparsed_size_limit: &Option<&'a str>,
+    cargo_home: &Path,
+    limits: &TrimLimits,
+    time_source: TimeSource,
+    remove_bins: bool,
+    bin_cache: &mut bin::BinaryCache,
     git_checkouts_cache: &mut git_checkouts::GitCheckoutCache,
     bare_repos_cache: &mut git_bare_repos::GitRepoCache,
     registry_pkg_cache: &mut registry_pkg_cache::RegistryPkgCaches,
     registry_sources_cache: &mut registry_sources::RegistrySourceCaches,
+    registry_index_caches: &mut registry_index::RegistryIndicesCache,
     dry_run: bool,
+    confirm: Confirm,
+    json: bool,
     size_changed: &mut bool,
+    trash: Option<&Trash>,
+    audit_log: &AuditLog,
 ) -> Result<(), TrimError<'a>> {
-    // the cache should not exceed this limit
-    let size_limit = parse_size_limit_to_bytes(unparsed_size_limit)?;
-
-    // fast path:
-    // if the  limit is bigger than the cache size, we can return
-    // because we know we won't have to delete anything
-    let total_cache_size: u64 = git_checkouts_cache.total_size()
-        + bare_repos_cache.total_size()
-        + registry_pkg_cache.total_size()
-        + registry_sources_cache.total_size();
-
-    if size_limit > total_cache_size {
-        //println!("trim: limit exceeds cache-limit, doing nothing");
-        return Ok(());
+    if json && !dry_run {
+        return Err(TrimError::JsonWithoutDryRun);
+    }
+    // moving items to the trash does not free anything, we could never keep the promise
+    if limits.ensure_free.is_some() && trash.is_some() {
+        return Err(TrimError::EnsureFreeQuarantined);
     }
 
-    // get all the items of the cache
-    let all_cache_items: Vec<&PathBuf> = gather_all_cache_items(
+    // get all the items of the cargo home
+    let all_cache_items = gather_all_cache_items(
+        limits.strategy,
+        time_source,
+        remove_bins,
+        bin_cache,
         git_checkouts_cache,
         bare_repos_cache,
         registry_pkg_cache,
         registry_sources_cache,
+        registry_index_caches,
     );
 
-    // delete everything that is unneeded
-    let mut cache_size = 0;
-    let mut removed_size: u64 = 0;
-    let mut removed_item_count = 0;
+    let bytes_to_free = match limits.ensure_free {
+        Some(free_space) => {
+            let available = fs2::available_space(cargo_home).map_err(|e| {
+                TrimError::DiskSpaceUnavailable(cargo_home.to_path_buf(), e.to_string())
+            })?;
+            let total = fs2::total_space(cargo_home).map_err(|e| {
+                TrimError::DiskSpaceUnavailable(cargo_home.to_path_buf(), e.to_string())
+            })?;
+            let bytes_to_free = free_space.bytes_to_free(available, total);
+            if !json {
+                println!(
+                    "{} of {} free on the file system of '{}', {} need to be freed.",
+                    available.file_size(file_size_opts::DECIMAL).unwrap(),
+                    total.file_size(file_size_opts::DECIMAL).unwrap(),
+                    cargo_home.display(),
+                    bytes_to_free.file_size(file_size_opts::DECIMAL).unwrap()
+                );
+            }
 
-    // walk the items and collect items until we have reached the size limit
-    all_cache_items
-        // walk through the files, youngest item comes first, oldest item comes last
-        .iter()
-        .filter(|path| {
-            //@TODO query cache for size!
-            let item_size = size_of_path(path);
-            // add the item size to the cache size
-            cache_size += item_size;
-            // keep all items (for deletion) once we have exceeded the cache size
-            let keep_file = cache_size > size_limit;
-            if keep_file {
-                removed_size += item_size;
-                removed_item_count += 1;
+            // don't remove anything if we can't reach the goal anyway
+            let removable: u64 = all_cache_items
+                .iter()
+                .filter(|item| !item.protected)
+                .map(|item| item.size)
+                .sum();
+            if removable < bytes_to_free {
+                return Err(TrimError::CannotFreeEnough(
+                    bytes_to_free,
+                    removable,
+                    remove_bins,
+                ));
             }
-            keep_file
-        })
-        // .for_each(|path| println!("{}", path.display().to_string()));
-        // for debugging: the smaller the size limit is, the more items we keep for deletion
-        .for_each(|path| {
-            remove_file(
-                path,
-                dry_run,
-                size_changed,
-                None,
-                &DryRunMessage::Default,
-                None,
-            )
-        });
+            bytes_to_free
+        }
+        None => 0,
+    };
+
+    let mut removed = items_to_remove(&all_cache_items, limits, bytes_to_free);
+    // remove the items we want to keep the least first
+    removed.reverse();
+
+    if !json {
+        print!("{}", breakdown_table(&all_cache_items, &removed, limits));
+    }
+
+    let exceeded = unreachable_limits(&all_cache_items, &removed, limits);
+    if !exceeded.is_empty() {
+        let warning = format!(
+            "Warning: the {} limit can not be reached because of protected items{}.",
+            exceeded.join(", "),
+            if remove_bins {
+                ""
+            } else {
+                " (pass \"--remove-bins\" to allow removing installed binaries)"
+            }
+        );
+        // keep stdout parsable
+        if json {
+            eprintln!("{}", warning);
+        } else {
+            println!("{}", warning);
+        }
+    }
+
+    let plan = removal_plan(&removed);
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&plan).expect("failed to serialize plan, this is a bug")
+        );
+    } else if dry_run && !plan.is_empty() {
+        println!();
+        print!("{}", plan_table(&plan));
+    }
+
+    let planned_removals: Vec<PlannedRemoval> = removed
+        .iter()
+        .map(|item| PlannedRemoval::new(item.path.clone(), &item.component, item.size))
+        .collect();
+    confirm_removal(confirm, &planned_removals).map_err(|_| TrimError::Aborted)?;
+
+    for item in &removed {
+        remove_file(
+            &item.path,
+            dry_run,
+            size_changed,
+            None,
+            // the plan already lists the items
+            &DryRunMessage::None,
+            Some(item.size),
+            trash,
+            audit_log,
+        );
+    }
+
+    let removed_binaries: Vec<PathBuf> = removed
+        .iter()
+        .filter(|item| item.component == Component::Bin.to_string())
+        .map(|item| item.path.clone())
+        .collect();
+    // a quarantined binary stays installed as far as cargo knows, until the trash is emptied
+    if !dry_run && trash.is_none() {
+        forget_installed_binaries(cargo_home, &removed_binaries)
+            .map_err(|e| TrimError::InstallMetadata(e.to_string()))?;
+    }
+
+    if json {
+        return Ok(());
+    }
+    let removed_size: u64 = removed.iter().map(|item| item.size).sum();
     println!(
-        "Removed {} items totalling {}",
-        removed_item_count,
+        "{} {} items totalling {}",
+        if dry_run {
+            "dry-run: would remove"
+        } else if trash.is_some() {
+            // nothing is freed until the trash is emptied
+            "Moved to the trash:"
+        } else {
+            "Removed"
+        },
+        removed.len(),
         removed_size.file_size(file_size_opts::DECIMAL).unwrap()
     );
     Ok(())
 }
 
 #[cfg(test)]
-mod parse_size_limit {
+mod trimtests {
     use super::*;
     use pretty_assertions::assert_eq;
 
-    #[test]
-    fn size_limit() {
-        // shorter function name
-        fn p<'a>(limit: &Option<&'a str>) -> Result<u64, TrimError<'a>> {
-            parse_size_limit_to_bytes(limit)
+    fn item(name: &str, component: &str, size: u64, protected: bool) -> TrimItem {
+        TrimItem {
+            path: PathBuf::from(name),
+            component: component.to_string(),
+            size,
+            last_access: SystemTime::UNIX_EPOCH,
+            protected,
         }
+    }
 
-        assert_eq!(p(&Some("1b")), Ok(1));
-        assert_eq!(p(&Some("1B")), Ok(1));
+    fn names(items: &[&TrimItem]) -> Vec<String> {
+        items
+            .iter()
+            .map(|item| item.path.display().to_string())
+            .collect()
+    }
 
-        assert_eq!(p(&Some("1k")), Ok(1_024));
-        assert_eq!(p(&Some("1K")), Ok(1_024));
+    fn overall(limit: u64) -> TrimLimits {
+        TrimLimits {
+            overall: Some(limit),
+            ..TrimLimits::default()
+        }
+    }
 
-        assert_eq!(p(&Some("1m")), Ok(1_048_576));
-        assert_eq!(p(&Some("1M")), Ok(1_048_576));
+    #[test]
+    fn protected_items_are_never_removed() {
+        let items = vec![
+            item("cargo", "bin", 60, true),
+            item("index", "registry-index", 30, false),
+            item("young.crate", "registry-crate-cache", 20, false),
+            item("old.crate", "registry-crate-cache", 20, false),
+        ];
+        // the binary alone exceeds the limit, the index goes last since it is kept the longest
+        assert_eq!(
+            names(&items_to_remove(&items, &overall(50), 0)),
+            vec!["index", "young.crate", "old.crate"]
+        );
+        // index and binary fit, the oldest crate is removed first
+        assert_eq!(
+            names(&items_to_remove(&items, &overall(110), 0)),
+            vec!["old.crate"]
+        );
+        assert!(items_to_remove(&items, &overall(130), 0).is_empty());
+    }
 
-        assert_eq!(p(&Some("1g")), Ok(1_073_741_824));
-        assert_eq!(p(&Some("1G")), Ok(1_073_741_824));
+    #[test]
+    fn quotas_per_component() {
+        let items = vec![
+            item("young.crate", "registry-crate-cache", 20, false),
+            item("young-checkout", "git-repos", 50, false),
+            item("old.crate", "registry-crate-cache", 20, false),
+            item("old-checkout", "git-repos", 50, false),
+            item("repo", "git-db", 100, false),
+        ];
+        let mut limits = TrimLimits::default();
+        let _ = limits.quotas.insert("git-repos".into(), 60);
+        let _ = limits.quotas.insert("registry-crate-cache".into(), 40);
+        // git-db has no quota and the crates fit
+        assert_eq!(
+            names(&items_to_remove(&items, &limits, 0)),
+            vec!["old-checkout"]
+        );
+
+        // the overall limit applies to what is left after the quotas
+        limits.overall = Some(150);
+        assert_eq!(
+            names(&items_to_remove(&items, &limits, 0)),
+            vec!["old-checkout", "repo"]
+        );
+    }
+
+    #[test]
+    fn limits_from_cli_and_config() {
+        let config = TrimConfig {
+            limit: Some("1K".into()),
+            ensure_free: None,
+            strategy: Some("size".into()),
+            quotas: vec![("git-repos", "2K"), ("bin", "3K")]
+                .into_iter()
+                .map(|(component, size)| (component.to_string(), size.to_string()))
+                .collect(),
+        };
+
+        let limits = TrimLimits::new(None, &["git-repos=1K"], None, None, &config).unwrap();
+        assert_eq!(limits.overall, Some(1024));
+        assert_eq!(limits.strategy, TrimStrategy::Size);
+        // the command line wins
+        assert_eq!(limits.quotas["git-repos"], 1024);
+        assert_eq!(limits.quotas["bin"], 3072);
+
+        let cli_limit = TrimLimits::new(Some("2K"), &[], None, Some("lru"), &config).unwrap();
+        assert_eq!(cli_limit.overall, Some(2048));
+        assert_eq!(cli_limit.strategy, TrimStrategy::Lru);
+
+        let empty = TrimConfig::default();
+        assert_eq!(
+            TrimLimits::new(None, &[], None, None, &empty),
+            Err(TrimError::NoLimit)
+        );
+        assert_eq!(
+            TrimLimits::new(None, &["git-repos"], None, None, &empty),
+            Err(TrimError::QuotaMalformed("git-repos"))
+        );
+        assert_eq!(
+            TrimLimits::new(None, &["gitrepos=1K"], None, None, &empty),
+            Err(TrimError::UnknownComponent("gitrepos"))
+        );
+        assert_eq!(
+            TrimLimits::new(Some("1K"), &[], None, Some("oldest"), &empty),
+            Err(TrimError::UnknownStrategy("oldest"))
+        );
+    }
+
+    #[test]
+    fn ensure_free_space() {
+        let items = vec![
+            item("cargo", "bin", 60, true),
+            item("index", "registry-index", 30, false),
+            item("young.crate", "registry-crate-cache", 20, false),
+            item("old.crate", "registry-crate-cache", 20, false),
+        ];
+        let limits = TrimLimits::default();
+        // the least recently used items go first, the index is the last resort
+        assert_eq!(
+            names(&items_to_remove(&items, &limits, 15)),
+            vec!["old.crate"]
+        );
+        assert_eq!(
+            names(&items_to_remove(&items, &limits, 50)),
+            vec!["index", "young.crate", "old.crate"]
+        );
+        // what the limit removes counts as well
+        assert_eq!(
+            names(&items_to_remove(&items, &overall(110), 15)),
+            vec!["old.crate"]
+        );
+    }
 
-        assert_eq!(p(&Some("1t")), Ok(1_099_511_627_776));
-        assert_eq!(p(&Some("1T")), Ok(1_099_511_627_776));
+    #[test]
+    fn ensure_free_with_quarantine() {
+        use crate::audit::Operation;
+
+        let cargo_home = PathBuf::from("target/trim_tests/ensure_free_with_quarantine");
+        let _ = std::fs::remove_dir_all(&cargo_home);
+        let crate_cache = cargo_home.join("registry/cache/github.com-1ecc6299db9ec823");
+        std::fs::create_dir_all(&crate_cache).unwrap();
+        std::fs::write(crate_cache.join("serde-1.0.0.crate"), [0; 100]).unwrap();
+
+        let trash = Trash::new(&cargo_home);
+        let limits = TrimLimits {
+            ensure_free: Some(FreeSpace::Percent(100.0)),
+            ..TrimLimits::default()
+        };
+        let result = trim_cache(
+            &cargo_home,
+            &limits,
+            TimeSource::Mtime,
+            false,
+            &mut bin::BinaryCache::new(cargo_home.join("bin")),
+            &mut git_checkouts::GitCheckoutCache::new(cargo_home.join("git/checkouts")),
+            &mut git_bare_repos::GitRepoCache::new(cargo_home.join("git/db")),
+            &mut registry_pkg_cache::RegistryPkgCaches::new(cargo_home.join("registry/cache")),
+            &mut registry_sources::RegistrySourceCaches::new(cargo_home.join("registry/src")),
+            &mut registry_index::RegistryIndicesCache::new(cargo_home.join("registry/index")),
+            false,
+            Confirm::Never,
+            false,
+            &mut false,
+            Some(&trash),
+            &AuditLog::new(&cargo_home, Operation::Trim),
+        );
 
-        assert_eq!(p(&Some("4M")), Ok(4_194_304));
-        assert_eq!(p(&Some("42M")), Ok(44_040_192));
-        assert_eq!(p(&Some("1337M")), Ok(1_401_946_112));
+        // moving the crate to the trash would not free anything, so nothing is touched
+        assert_eq!(result, Err(TrimError::EnsureFreeQuarantined));
+        assert!(crate_cache.join("serde-1.0.0.crate").is_file());
+        assert!(!cargo_home.join(".cargo-cache").exists());
+    }
 
-        assert_eq!(p(&Some("1.5k")), Ok(1_536));
+    #[test]
+    fn sources_never_outlive_their_archive() {
+        let items = vec![
+            item(
+                ".cargo/registry/src/github.com-1ecc6299db9ec823/serde-1.0.116",
+                "registry-sources",
+                50,
+                false,
+            ),
+            item(
+                ".cargo/registry/src/github.com-1ecc6299db9ec823/serde-1.0.1",
+                "registry-sources",
+                50,
+                false,
+            ),
+            item(
+                ".cargo/git/checkouts/foo-abc123/1234567",
+                "git-repos",
+                50,
+                false,
+            ),
+            item(
+                ".cargo/registry/cache/github.com-1ecc6299db9ec823/serde-1.0.116.crate",
+                "registry-crate-cache",
+                10,
+                false,
+            ),
+            item(".cargo/git/db/foo-abc123", "git-db", 10, false),
+        ];
+        // only the archive and the git repo exceed the limit, but their extracted versions go as well
+        assert_eq!(
+            names(&items_to_remove(&items, &overall(150), 0)),
+            vec![
+                ".cargo/registry/src/github.com-1ecc6299db9ec823/serde-1.0.116",
+                ".cargo/git/checkouts/foo-abc123/1234567",
+                ".cargo/registry/cache/github.com-1ecc6299db9ec823/serde-1.0.116.crate",
+                ".cargo/git/db/foo-abc123",
+            ]
+        );
+    }
+
+    #[test]
+    fn strategies() {
+        let mut items = vec![
+            item("young-source", "registry-sources", 10, false),
+            item("young.crate", "registry-crate-cache", 30, false),
+            item("old-checkout", "git-repos", 20, false),
+            item("old-repo", "git-db", 40, false),
+        ];
+        // the items are already sorted by access time, keep the order within each group
+        items.sort_by_key(is_extracted);
+        assert_eq!(
+            names(&items.iter().collect::<Vec<_>>()),
+            vec!["young.crate", "old-repo", "young-source", "old-checkout"]
+        );
+        // sources and checkouts are removed first
+        assert_eq!(
+            names(&items_to_remove(&items, &overall(70), 0)),
+            vec!["young-source", "old-checkout"]
+        );
 
         assert_eq!(
-            p(&Some("1_")),
-            Err(TrimError::TrimLimitUnitParseFailure("1_"))
+            TrimStrategy::parse("rebuild-cost"),
+            Ok(TrimStrategy::RebuildCost)
         );
+        assert_eq!(TrimStrategy::parse("size"), Ok(TrimStrategy::Size));
     }
 
-    // make sure Size limit None panicss
     #[test]
-    #[should_panic(expected = "No trim --limit was supplied altough clap should enforce that!")]
-    fn size_limit_none_panics() {
-        let _ = parse_size_limit_to_bytes(&None);
+    fn plan() {
+        let items = [
+            item(
+                "/cargo/registry/src/github.com-1ecc6299db9ec823/heim-runtime-0.1.0-beta.1",
+                "registry-sources",
+                2_000,
+                false,
+            ),
+            item(
+                "/cargo/registry/cache/github.com-1ecc6299db9ec823/serde-1.0.116.crate",
+                "registry-crate-cache",
+                1_000,
+                false,
+            ),
+            item(
+                "/cargo/git/checkouts/cargo-cache-1c5d1c8d7e4b5a3f/a1b2c3d",
+                "git-repos",
+                500,
+                false,
+            ),
+            item(
+                "/cargo/git/db/cargo-cache-1c5d1c8d7e4b5a3f",
+                "git-db",
+                500,
+                false,
+            ),
+            item("/cargo/bin/rg", "bin", 3_000, false),
+        ];
+        let plan = removal_plan(&items.iter().collect::<Vec<_>>());
+        let described: Vec<_> = plan
+            .iter()
+            .map(|entry| {
+                (
+                    entry.registry.as_deref(),
+                    entry.name.as_deref(),
+                    entry.version.as_deref(),
+                    entry.total,
+                )
+            })
+            .collect();
+        assert_eq!(
+            described,
+            vec![
+                (
+                    Some("github.com-1ecc6299db9ec823"),
+                    Some("heim-runtime"),
+                    Some("0.1.0-beta.1"),
+                    2_000
+                ),
+                (
+                    Some("github.com-1ecc6299db9ec823"),
+                    Some("serde"),
+                    Some("1.0.116"),
+                    3_000
+                ),
+                (None, Some("cargo-cache"), Some("a1b2c3d"), 3_500),
+                (None, Some("cargo-cache"), None, 4_000),
+                (None, Some("rg"), None, 7_000),
+            ]
+        );
+
+        let json = serde_json::to_string(&plan[1]).unwrap();
+        assert!(json.starts_with(
+            "{\"component\":\"registry-crate-cache\",\"registry\":\"github.com-1ecc6299db9ec823\",\"name\":\"serde\",\"version\":\"1.0.116\","
+        ));
+        // the last use is in the local time zone
+        assert!(json.contains("\"size\":1000,\"last_use\":\"19"));
+        assert!(json.ends_with(",\"total\":3000}"));
+    }
+
+    #[test]
+    fn free_space() {
+        assert_eq!(FreeSpace::parse("1K"), Ok(FreeSpace::Bytes(1024)));
+        assert_eq!(FreeSpace::parse("15%"), Ok(FreeSpace::Percent(15.0)));
+        assert_eq!(FreeSpace::parse(" 2.5 %"), Ok(FreeSpace::Percent(2.5)));
+        assert_eq!(
+            FreeSpace::parse("150%"),
+            Err(TrimError::FreeSpaceParseFailure("150%"))
+        );
+
+        assert_eq!(FreeSpace::Bytes(1000).bytes_to_free(300, 5000), 700);
+        assert_eq!(FreeSpace::Bytes(1000).bytes_to_free(3000, 5000), 0);
+        assert_eq!(FreeSpace::Percent(10.0).bytes_to_free(300, 5000), 200);
+    }
+
+    #[test]
+    fn breakdown() {
+        let items = vec![
+            item("rg", "bin", 1_000, true),
+            item("index", "registry-index", 2_000, false),
+            item("serde.crate", "registry-crate-cache", 3_000, false),
+        ];
+        let mut limits = overall(3_500);
+        let _ = limits.quotas.insert("git-db".into(), 1_000);
+        let removed = items_to_remove(&items, &limits, 0);
+        assert_eq!(
+            breakdown_table(&items, &removed, &limits),
+            "Component             Size  Removed  Remaining  Limit\n\
+             registry-index        2 KB  0 B      2 KB       -\n\
+             registry-crate-cache  3 KB  3 KB     0 B        -\n\
+             registry-sources      0 B   0 B      0 B        -\n\
+             git-db                0 B   0 B      0 B        1 KB\n\
+             git-repos             0 B   0 B      0 B        -\n\
+             bin                   1 KB  0 B      1 KB       -\n\
+             Total                 6 KB  3 KB     3 KB       3.50 KB\n"
+        );
     }
 }
@@ -0,0 +1,821 @@
+// Copyright 2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// "cargo cache tui" browses the cache interactively.
+// The items of the caches are shown as a tree of components -> registries -> crates -> versions
+// (git repos -> revisions for checkouts) with their size and the time of their last use.
+// Items can be marked and are removed via remove_file() after the user confirmed a summary of
+// everything that is about to be removed, the sizes in the tree are updated afterwards.
+
+use std::convert::TryFrom;
+use std::io;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use crate::audit::AuditLog;
+use crate::cache::bin::{forget_installed_binaries, is_rustup_proxy};
+use crate::cache::caches::*;
+use crate::cache::*;
+use crate::commands::trim::item_names;
+use crate::library::*;
+use crate::remove::*;
+use crate::timestamps::{last_use, TimeSource};
+use crate::trash::Trash;
+
+use chrono::{DateTime, Local};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::execute;
+use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::tty::IsTty;
+use humansize::{file_size_opts, FileSize};
+use tui_rs::backend::{Backend, CrosstermBackend};
+use tui_rs::layout::{Constraint, Direction, Layout, Rect};
+use tui_rs::style::{Modifier, Style};
+use tui_rs::widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph};
+use tui_rs::{Frame, Terminal};
+
+/// number of paths listed in the confirmation dialog
+const LISTED_PATHS: usize = 10;
+
+fn human(size: u64) -> String {
+    size.file_size(file_size_opts::DECIMAL).unwrap()
+}
+
+/// an entry of the tree, only leaves are actual items of the cache
+#[derive(Debug)]
+struct Node {
+    label: String,
+    /// the item of the cache, None for components, registries, crates ...
+    path: Option<PathBuf>,
+    size: u64,
+    last_use: Option<SystemTime>,
+    /// rustup proxies can not be marked
+    protected: bool,
+    marked: bool,
+    expanded: bool,
+    children: Vec<Node>,
+}
+
+impl Node {
+    fn group(label: &str) -> Self {
+        Self {
+            label: label.to_string(),
+            path: None,
+            size: 0,
+            last_use: None,
+            protected: false,
+            marked: false,
+            expanded: false,
+            children: Vec::new(),
+        }
+    }
+
+    fn item(label: String, path: PathBuf, protected: bool, time_source: TimeSource) -> Self {
+        Self {
+            label,
+            size: size_of_path(&path),
+            last_use: last_use(&path, time_source),
+            path: Some(path),
+            protected,
+            marked: false,
+            expanded: false,
+            children: Vec::new(),
+        }
+    }
+
+    fn is_item(&self) -> bool {
+        self.path.is_some()
+    }
+
+    /// add an item below the groups with the given labels, the groups are created as needed
+    fn insert(&mut self, groups: &[String], item: Self) {
+        match groups.split_first() {
+            None => self.children.push(item),
+            Some((label, rest)) => {
+                let pos = self
+                    .children
+                    .iter()
+                    .position(|c| &c.label == label)
+                    .unwrap_or_else(|| {
+                        self.children.push(Self::group(label));
+                        self.children.len() - 1
+                    });
+                self.children[pos].insert(rest, item);
+            }
+        }
+    }
+
+    /// sort the children by label and sum up sizes and last uses of the groups
+    fn update_totals(&mut self) {
+        if self.is_item() {
+            return;
+        }
+        self.children.sort_by(|a, b| a.label.cmp(&b.label));
+        for child in &mut self.children {
+            child.update_totals();
+        }
+        self.size = self.children.iter().map(|c| c.size).sum();
+        self.last_use = self.children.iter().filter_map(|c| c.last_use).max();
+        self.protected = self.children.iter().all(|c| c.protected);
+        // a group is marked if everything inside of it that can be marked is
+        self.marked = !self.protected
+            && self
+                .children
+                .iter()
+                .filter(|c| !c.protected)
+                .all(|c| c.marked);
+    }
+
+    fn set_marked(&mut self, marked: bool) {
+        if self.protected {
+            return;
+        }
+        self.marked = marked;
+        for child in &mut self.children {
+            child.set_marked(marked);
+        }
+    }
+
+    fn marked_items<'a>(&'a self, items: &mut Vec<&'a Self>) {
+        if self.is_item() {
+            if self.marked {
+                items.push(self);
+            }
+        } else {
+            for child in &self.children {
+                child.marked_items(items);
+            }
+        }
+    }
+
+    /// drop items that no longer exist and groups that became empty
+    fn prune(&mut self) {
+        for child in &mut self.children {
+            child.prune();
+        }
+        self.children.retain(|c| match &c.path {
+            Some(path) => path.exists(),
+            None => !c.children.is_empty(),
+        });
+    }
+}
+
+/// the root nodes (components) of the tree of all items of the cache
+#[allow(clippy::too_many_arguments)]
+fn build_tree(
+    time_source: TimeSource,
+    bin_cache: &mut bin::BinaryCache,
+    checkouts_cache: &mut git_checkouts::GitCheckoutCache,
+    bare_repos_cache: &mut git_bare_repos::GitRepoCache,
+    registry_pkg_cache: &mut registry_pkg_cache::RegistryPkgCaches,
+    registry_sources_cache: &mut registry_sources::RegistrySourceCaches,
+    registry_index_caches: &mut registry_index::RegistryIndicesCache,
+) -> Vec<Node> {
+    let components: [(&[PathBuf], Component); 6] = [
+        (registry_index_caches.items(), Component::RegistryIndex),
+        (registry_pkg_cache.items(), Component::RegistryCrateCache),
+        (registry_sources_cache.items(), Component::RegistrySources),
+        (bare_repos_cache.items(), Component::GitDB),
+        (checkouts_cache.items(), Component::GitRepos),
+        (bin_cache.items(), Component::Bin),
+    ];
+
+    let mut roots = Vec::new();
+    for (items, component) in &components {
+        let name = component.to_string();
+        let mut root = Node::group(&name);
+        for path in *items {
+            let (registry, crate_name, version) = item_names(&name, path);
+            let mut labels: Vec<String> = vec![registry, crate_name, version]
+                .into_iter()
+                .flatten()
+                .collect();
+            let label = labels.pop().unwrap_or_else(|| path.display().to_string());
+            let protected = *component == Component::Bin && is_rustup_proxy(path);
+            root.insert(
+                &labels,
+                Node::item(label, path.clone(), protected, time_source),
+            );
+        }
+        root.update_totals();
+        roots.push(root);
+    }
+    roots
+}
+
+/// what the event loop has to do after a key was pressed
+#[derive(Debug, PartialEq, Eq)]
+enum Action {
+    None,
+    Remove,
+    Quit,
+}
+
+/// the state of the browser
+struct Browser {
+    roots: Vec<Node>,
+    /// index of the selected row
+    selected: usize,
+    /// the confirmation dialog is open
+    confirm: bool,
+    status: String,
+}
+
+impl Browser {
+    fn new(roots: Vec<Node>) -> Self {
+        Self {
+            roots,
+            selected: 0,
+            confirm: false,
+            status: String::new(),
+        }
+    }
+
+    /// the visible rows: depth and the indices leading to the node
+    fn rows(&self) -> Vec<(usize, Vec<usize>)> {
+        fn walk(nodes: &[Node], prefix: &[usize], rows: &mut Vec<(usize, Vec<usize>)>) {
+            for (idx, node) in nodes.iter().enumerate() {
+                let mut indices = prefix.to_vec();
+                indices.push(idx);
+                rows.push((prefix.len(), indices.clone()));
+                if node.expanded {
+                    walk(&node.children, &indices, rows);
+                }
+            }
+        }
+        let mut rows = Vec::new();
+        walk(&self.roots, &[], &mut rows);
+        rows
+    }
+
+    fn node(&self, indices: &[usize]) -> &Node {
+        let mut node = &self.roots[indices[0]];
+        for idx in &indices[1..] {
+            node = &node.children[*idx];
+        }
+        node
+    }
+
+    fn node_mut(&mut self, indices: &[usize]) -> &mut Node {
+        let mut node = &mut self.roots[indices[0]];
+        for idx in &indices[1..] {
+            node = &mut node.children[*idx];
+        }
+        node
+    }
+
+    fn selected_indices(&self) -> Option<Vec<usize>> {
+        self.rows()
+            .into_iter()
+            .nth(self.selected)
+            .map(|(_, indices)| indices)
+    }
+
+    fn move_selection(&mut self, up: bool) {
+        let rows = self.rows().len();
+        if up {
+            self.selected = self.selected.saturating_sub(1);
+        } else if self.selected + 1 < rows {
+            self.selected += 1;
+        }
+    }
+
+    fn expand(&mut self, expanded: bool) {
+        if let Some(indices) = self.selected_indices() {
+            let node = self.node_mut(&indices);
+            if !node.is_item() && node.expanded != expanded {
+                node.expanded = expanded;
+                return;
+            }
+            // collapsing a collapsed node selects its parent
+            if !expanded && indices.len() > 1 {
+                let parent = &indices[..indices.len() - 1];
+                if let Some(pos) = self.rows().iter().position(|(_, i)| i == parent) {
+                    self.selected = pos;
+                }
+            }
+        }
+    }
+
+    fn toggle_mark(&mut self) {
+        if let Some(indices) = self.selected_indices() {
+            let node = self.node_mut(&indices);
+            let marked = !node.marked;
+            node.set_marked(marked);
+            for root in &mut self.roots {
+                root.update_totals();
+            }
+        }
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) -> Action {
+        if self.confirm {
+            self.confirm = false;
+            return if key.code == KeyCode::Char('y') {
+                Action::Remove
+            } else {
+                Action::None
+            };
+        }
+
+        match key {
+            KeyEvent {
+                code: KeyCode::Char('c'),
+                modifiers: KeyModifiers::CONTROL,
+            } => return Action::Quit,
+            KeyEvent { code, .. } => match code {
+                KeyCode::Char('q') | KeyCode::Esc => return Action::Quit,
+                KeyCode::Up | KeyCode::Char('k') => self.move_selection(true),
+                KeyCode::Down | KeyCode::Char('j') => self.move_selection(false),
+                KeyCode::Right | KeyCode::Char('l') | KeyCode::Enter => self.expand(true),
+                KeyCode::Left | KeyCode::Char('h') => self.expand(false),
+                KeyCode::Char(' ') => self.toggle_mark(),
+                KeyCode::Char('d') | KeyCode::Delete => {
+                    self.confirm = !self.marked_items().is_empty();
+                }
+                _ => {}
+            },
+        }
+        Action::None
+    }
+
+    fn marked_items(&self) -> Vec<&Node> {
+        let mut items = Vec::new();
+        for root in &self.roots {
+            root.marked_items(&mut items);
+        }
+        items
+    }
+
+    /// what we are about to remove: total, the size per component and the first paths
+    fn removal_summary(&self) -> Vec<String> {
+        let marked = self.marked_items();
+        let total: u64 = marked.iter().map(|item| item.size).sum();
+        let mut lines = vec![format!("Remove {} items ({})?", marked.len(), human(total))];
+        lines.push(String::new());
+        for root in &self.roots {
+            let mut items = Vec::new();
+            root.marked_items(&mut items);
+            if !items.is_empty() {
+                lines.push(format!(
+                    "{}: {} items, {}",
+                    root.label,
+                    items.len(),
+                    human(items.iter().map(|item| item.size).sum())
+                ));
+            }
+        }
+        lines.push(String::new());
+        lines.extend(
+            marked
+                .iter()
+                .take(LISTED_PATHS)
+                .filter_map(|item| item.path.as_ref())
+                .map(|path| path.display().to_string()),
+        );
+        if marked.len() > LISTED_PATHS {
+            lines.push(format!("... and {} more", marked.len() - LISTED_PATHS));
+        }
+        lines.push(String::new());
+        lines.push("y: remove   any other key: cancel".to_string());
+        lines
+    }
+
+    /// remove all marked items and drop them from the tree
+    fn remove_marked(
+        &mut self,
+        cargo_cache_paths: &CargoCachePaths,
+        size_changed: &mut bool,
+        trash: Option<&Trash>,
+        audit_log: &AuditLog,
+    ) -> Result<(usize, u64), Error> {
+        let marked: Vec<(PathBuf, u64)> = self
+            .marked_items()
+            .iter()
+            .filter_map(|item| item.path.clone().map(|path| (path, item.size)))
+            .collect();
+        for (path, size) in &marked {
+            remove_file(
+                path,
+                false,
+                size_changed,
+                None,
+                &DryRunMessage::None,
+                Some(*size),
+                trash,
+                audit_log,
+            );
+        }
+
+        let removed: Vec<&(PathBuf, u64)> = marked.iter().filter(|(p, _)| !p.exists()).collect();
+        // "undo" can still bring quarantined binaries back
+        if trash.is_none() {
+            let removed_bins: Vec<PathBuf> = removed
+                .iter()
+                .map(|(path, _)| path.clone())
+                .filter(|path| path.starts_with(&cargo_cache_paths.bin_dir))
+                .collect();
+            forget_installed_binaries(&cargo_cache_paths.cargo_home, &removed_bins)?;
+        }
+
+        for root in &mut self.roots {
+            root.prune();
+            root.update_totals();
+        }
+        let rows = self.rows().len();
+        self.selected = self.selected.min(rows.saturating_sub(1));
+
+        let removed_size = removed.iter().map(|(_, size)| size).sum();
+        let failed = marked.len() - removed.len();
+        let verb = if trash.is_some() {
+            "Moved to the trash:"
+        } else {
+            "Removed"
+        };
+        self.status = if failed == 0 {
+            format!("{} {} items ({})", verb, removed.len(), human(removed_size))
+        } else {
+            format!(
+                "{} {} items ({}), failed to remove {}",
+                verb,
+                removed.len(),
+                human(removed_size),
+                failed
+            )
+        };
+        Ok((removed.len(), removed_size))
+    }
+}
+
+/// a row of the tree: marker, name, size and last use
+fn format_row(node: &Node, depth: usize, width: usize) -> String {
+    let mark = if node.protected {
+        "   "
+    } else if node.marked {
+        "[x]"
+    } else {
+        "[ ]"
+    };
+    let fold = if node.is_item() {
+        " "
+    } else if node.expanded {
+        "-"
+    } else {
+        "+"
+    };
+    let last_use = node.last_use.map_or_else(String::new, |time| {
+        DateTime::<Local>::from(time)
+            .format("%Y-%m-%d %H:%M")
+            .to_string()
+    });
+    let name = format!("{}{} {} {}", "  ".repeat(depth), fold, mark, node.label);
+    // size and date take 30 columns
+    let name_width = width.saturating_sub(30).max(name.chars().count());
+    format!(
+        "{:<name_width$} {:>12} {:>16}",
+        name,
+        human(node.size),
+        last_use,
+        name_width = name_width
+    )
+}
+
+/// a rectangle in the middle of the area
+fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let (w, h) = (width.min(area.width), height.min(area.height));
+    Rect::new(
+        area.x + (area.width - w) / 2,
+        area.y + (area.height - h) / 2,
+        w,
+        h,
+    )
+}
+
+fn draw<B: Backend>(frame: &mut Frame<'_, B>, browser: &Browser, cargo_home: &str) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(3), Constraint::Length(3)].as_ref())
+        .split(frame.size());
+
+    let width = usize::from(chunks[0].width.saturating_sub(2));
+    let items: Vec<ListItem<'_>> = browser
+        .rows()
+        .iter()
+        .map(|(depth, indices)| ListItem::new(format_row(browser.node(indices), *depth, width)))
+        .collect();
+    let total: u64 = browser.roots.iter().map(|root| root.size).sum();
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(format!(
+            "Cargo cache '{}': {}",
+            cargo_home,
+            human(total)
+        )))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let mut state = ListState::default();
+    state.select(Some(browser.selected));
+    frame.render_stateful_widget(list, chunks[0], &mut state);
+
+    let marked = browser.marked_items();
+    let help = format!(
+        "marked: {} items ({})   arrows/hjkl: move   space: mark   d: remove marked   q: quit   {}",
+        marked.len(),
+        human(marked.iter().map(|item| item.size).sum()),
+        browser.status
+    );
+    frame.render_widget(
+        Paragraph::new(help).block(Block::default().borders(Borders::ALL)),
+        chunks[1],
+    );
+
+    if browser.confirm {
+        let summary = browser.removal_summary();
+        let dialog_width = summary.iter().map(String::len).max().unwrap_or(0) + 4;
+        let area = centered(
+            frame.size(),
+            u16::try_from(dialog_width).unwrap_or(u16::MAX),
+            u16::try_from(summary.len() + 2).unwrap_or(u16::MAX),
+        );
+        frame.render_widget(Clear, area);
+        frame.render_widget(
+            Paragraph::new(summary.join("\n"))
+                .block(Block::default().borders(Borders::ALL).title("Confirm")),
+            area,
+        );
+    }
+}
+
+fn event_loop<B: Backend>(
+    terminal: &mut Terminal<B>,
+    browser: &mut Browser,
+    cargo_cache_paths: &CargoCachePaths,
+    size_changed: &mut bool,
+    trash: Option<&Trash>,
+    audit_log: &AuditLog,
+) -> Result<(usize, u64), Error> {
+    let io_err = |e: io::Error| Error::TerminalIo(e.to_string());
+    let cargo_home = cargo_cache_paths.cargo_home.display().to_string();
+    let (mut removed, mut removed_size) = (0, 0);
+
+    loop {
+        let _ = terminal
+            .draw(|frame| draw(frame, browser, &cargo_home))
+            .map_err(io_err)?;
+
+        if let Event::Key(key) = event::read().map_err(|e| Error::TerminalIo(e.to_string()))? {
+            match browser.handle_key(key) {
+                Action::Quit => break,
+                Action::Remove => {
+                    let (items, size) =
+                        browser.remove_marked(cargo_cache_paths, size_changed, trash, audit_log)?;
+                    removed += items;
+                    removed_size += size;
+                    // warnings printed while removing would mess up the screen
+                    terminal.clear().map_err(io_err)?;
+                }
+                Action::None => {}
+            }
+        }
+    }
+    Ok((removed, removed_size))
+}
+
+/// "cargo cache tui": browse the cache and remove marked items
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn tui(
+    cargo_cache_paths: &CargoCachePaths,
+    time_source: TimeSource,
+    bin_cache: &mut bin::BinaryCache,
+    checkouts_cache: &mut git_checkouts::GitCheckoutCache,
+    bare_repos_cache: &mut git_bare_repos::GitRepoCache,
+    registry_pkg_cache: &mut registry_pkg_cache::RegistryPkgCaches,
+    registry_sources_cache: &mut registry_sources::RegistrySourceCaches,
+    registry_index_caches: &mut registry_index::RegistryIndicesCache,
+    size_changed: &mut bool,
+    trash: Option<&Trash>,
+    audit_log: &AuditLog,
+) -> Result<(), Error> {
+    // bail out before scanning the whole cache if there is nothing to draw on
+    if !io::stdout().is_tty() || !io::stdin().is_tty() {
+        return Err(Error::NoTerminal);
+    }
+
+    let mut browser = Browser::new(build_tree(
+        time_source,
+        bin_cache,
+        checkouts_cache,
+        bare_repos_cache,
+        registry_pkg_cache,
+        registry_sources_cache,
+        registry_index_caches,
+    ));
+
+    let terminal_err = |e: crossterm::ErrorKind| Error::TerminalIo(e.to_string());
+    terminal::enable_raw_mode().map_err(terminal_err)?;
+    let mut stdout = io::stdout();
+    if let Err(e) = execute!(stdout, EnterAlternateScreen) {
+        let _ = terminal::disable_raw_mode();
+        return Err(terminal_err(e));
+    }
+
+    let result = Terminal::new(CrosstermBackend::new(stdout))
+        .map_err(|e| Error::TerminalIo(e.to_string()))
+        .and_then(|mut terminal| {
+            let result = event_loop(
+                &mut terminal,
+                &mut browser,
+                cargo_cache_paths,
+                size_changed,
+                trash,
+                audit_log,
+            );
+            let _ = terminal.show_cursor();
+            result
+        });
+
+    // always give the terminal back in a usable state
+    let _ = terminal::disable_raw_mode();
+    let _ = execute!(io::stdout(), LeaveAlternateScreen);
+
+    let (removed, removed_size) = result?;
+    if removed > 0 {
+        if trash.is_some() {
+            println!(
+                "Moved to the trash: {} items ({})",
+                removed,
+                human(removed_size)
+            );
+        } else {
+            println!("Removed {} items ({})", removed, human(removed_size));
+        }
+        bin_cache.invalidate();
+        checkouts_cache.invalidate();
+        bare_repos_cache.invalidate();
+        registry_pkg_cache.invalidate();
+        registry_sources_cache.invalidate();
+        registry_index_caches.invalidate();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tuitests {
+    use super::*;
+    use crate::audit::Operation;
+    use pretty_assertions::assert_eq;
+    use std::fs;
+    use std::path::Path;
+    use tui_rs::backend::TestBackend;
+
+    fn browser_of(cargo_home: &Path) -> Browser {
+        let registry = |dir: &str| cargo_home.join("registry").join(dir);
+        Browser::new(build_tree(
+            TimeSource::Mtime,
+            &mut bin::BinaryCache::new(cargo_home.join("bin")),
+            &mut git_checkouts::GitCheckoutCache::new(cargo_home.join("git").join("checkouts")),
+            &mut git_bare_repos::GitRepoCache::new(cargo_home.join("git").join("db")),
+            &mut registry_pkg_cache::RegistryPkgCaches::new(registry("cache")),
+            &mut registry_sources::RegistrySourceCaches::new(registry("src")),
+            &mut registry_index::RegistryIndicesCache::new(registry("index")),
+        ))
+    }
+
+    fn labels(nodes: &[Node]) -> Vec<&str> {
+        nodes.iter().map(|node| node.label.as_str()).collect()
+    }
+
+    #[test]
+    fn browse_and_remove() {
+        let cargo_home = PathBuf::from("target/tui_tests/browse_and_remove");
+        let _ = fs::remove_dir_all(&cargo_home);
+        let registry = "github.com-1ecc6299db9ec823";
+        let archives = cargo_home.join("registry").join("cache").join(registry);
+        let bin = cargo_home.join("bin");
+        fs::create_dir_all(&archives).unwrap();
+        fs::create_dir_all(&bin).unwrap();
+        fs::write(archives.join("serde-1.0.0.crate"), "a".repeat(100)).unwrap();
+        fs::write(archives.join("serde-1.0.1.crate"), "a".repeat(200)).unwrap();
+        fs::write(archives.join("rand-0.7.3.crate"), "a".repeat(300)).unwrap();
+        fs::write(bin.join("cargo"), "a".repeat(10)).unwrap();
+        fs::write(bin.join("rg"), "a".repeat(20)).unwrap();
+
+        let mut browser = browser_of(&cargo_home);
+        assert_eq!(
+            labels(&browser.roots),
+            vec![
+                "registry-index",
+                "registry-crate-cache",
+                "registry-sources",
+                "git-db",
+                "git-repos",
+                "bin"
+            ]
+        );
+        let crate_cache = &browser.roots[1];
+        assert_eq!(crate_cache.size, 600);
+        assert_eq!(labels(&crate_cache.children), vec![registry]);
+        assert_eq!(
+            labels(&crate_cache.children[0].children),
+            vec!["rand", "serde"]
+        );
+        let serde = &crate_cache.children[0].children[1];
+        assert_eq!(labels(&serde.children), vec!["1.0.0", "1.0.1"]);
+        assert_eq!(serde.size, 300);
+        assert!(serde.last_use.is_some());
+
+        // only expanded nodes are visible
+        assert_eq!(browser.rows().len(), 6);
+        let mut press = |code| browser.handle_key(KeyEvent::new(code, KeyModifiers::NONE));
+        for code in [
+            KeyCode::Down,
+            KeyCode::Right,
+            KeyCode::Char('j'),
+            KeyCode::Enter,
+            KeyCode::Down,
+            KeyCode::Down,
+        ] {
+            assert_eq!(press(code), Action::None);
+        }
+        // mark "serde", nothing happens without confirmation
+        assert_eq!(press(KeyCode::Char(' ')), Action::None);
+        assert_eq!(press(KeyCode::Char('d')), Action::None);
+        assert_eq!(press(KeyCode::Char('n')), Action::None);
+        assert_eq!(press(KeyCode::Char('q')), Action::Quit);
+        assert_eq!(browser.rows().len(), 9);
+        assert_eq!(browser.selected_indices().unwrap(), vec![1, 0, 1]);
+        assert!(browser.roots[1].children[0].children[1].children[0].marked);
+        assert!(!browser.roots[1].children[0].marked);
+
+        // marking a group never marks the rustup proxies
+        browser.roots[5].set_marked(true);
+        browser.roots[5].update_totals();
+        let marked: Vec<&str> = browser
+            .marked_items()
+            .iter()
+            .map(|item| item.label.as_str())
+            .collect();
+        assert_eq!(marked, vec!["1.0.0", "1.0.1", "rg"]);
+
+        assert_eq!(
+            browser.removal_summary()[..5].to_vec(),
+            vec![
+                "Remove 3 items (320 B)?".to_string(),
+                String::new(),
+                "registry-crate-cache: 2 items, 300 B".to_string(),
+                "bin: 1 items, 20 B".to_string(),
+                String::new(),
+            ]
+        );
+
+        // the confirmation dialog is drawn on top of the tree
+        assert_eq!(
+            browser.handle_key(KeyEvent::new(KeyCode::Delete, KeyModifiers::NONE)),
+            Action::None
+        );
+        assert!(browser.confirm);
+        let mut terminal = Terminal::new(TestBackend::new(100, 30)).unwrap();
+        let _ = terminal
+            .draw(|frame| draw(frame, &browser, "cargo_home"))
+            .unwrap();
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol.as_str())
+            .collect();
+        assert!(screen.contains("Cargo cache 'cargo_home': 630 B"));
+        assert!(screen.contains("Remove 3 items (320 B)?"));
+
+        assert_eq!(
+            browser.handle_key(KeyEvent::new(KeyCode::Char('y'), KeyModifiers::NONE)),
+            Action::Remove
+        );
+        let mut size_changed = false;
+        let (removed, size) = browser
+            .remove_marked(
+                &CargoCachePaths::from_cargo_home(cargo_home.clone()),
+                &mut size_changed,
+                None,
+                &AuditLog::new(&cargo_home, Operation::Tui),
+            )
+            .unwrap();
+        assert_eq!((removed, size), (3, 320));
+        assert!(size_changed);
+        assert!(!archives.join("serde-1.0.0.crate").exists());
+        assert!(archives.join("rand-0.7.3.crate").exists());
+        assert!(bin.join("cargo").exists());
+        assert!(!bin.join("rg").exists());
+
+        // the tree reflects the removal
+        assert_eq!(browser.roots[1].size, 300);
+        assert_eq!(labels(&browser.roots[1].children[0].children), vec!["rand"]);
+        assert_eq!(labels(&browser.roots[5].children), vec!["cargo"]);
+        assert!(browser.marked_items().is_empty());
+    }
+}
@@ -0,0 +1,555 @@
+// Copyright 2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// "cargo cache why <crate>[@version]" tells which local workspaces and installed binaries
+// reference a crate or git repo from the cache.
+// Workspaces are found by scanning project roots for Cargo.lock files, installed binaries
+// via cargo's install metadata (.crates2.json / .crates.toml) and the Cargo.lock that was
+// shipped with the source of the installed crate.
+// "--unreferenced" turns this around and lists all cache items that nobody references.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::cache::caches::*;
+use crate::cache::*;
+use crate::clean_unref::scan_for_manifests;
+use crate::library::*;
+use crate::lockfile::{
+    dir_names, git_repo_name, has_hash_suffix, lockfile_of_manifest, parse_lockfile, read_lockfile,
+    registry_prefixes, required_items_from_packages, LockedPackage,
+};
+use crate::timestamps::open_without_atime;
+
+use clap::ArgMatches;
+use flate2::read::GzDecoder;
+use humansize::{file_size_opts, FileSize};
+use serde::Deserialize;
+
+/// colon (or semicolon on windows) separated list of directories that are scanned if no --root is given
+const PROJECT_ROOTS_ENV: &str = "CARGO_CACHE_PROJECT_ROOTS";
+
+/// a package installed via "cargo install"
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct InstalledPackage {
+    name: String,
+    version: String,
+    source: String,
+    bins: Vec<String>,
+}
+
+impl InstalledPackage {
+    fn describe(&self) -> String {
+        format!(
+            "installed: {} {} ({})",
+            self.name,
+            self.version,
+            self.bins.join(", ")
+        )
+    }
+}
+
+/// `${CARGO_HOME}/.crates2.json`
+#[derive(Debug, Deserialize)]
+struct Crates2 {
+    #[serde(default)]
+    installs: BTreeMap<String, Crates2Entry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Crates2Entry {
+    #[serde(default)]
+    bins: Vec<String>,
+}
+
+/// `${CARGO_HOME}/.crates.toml`, written by older cargos
+#[derive(Debug, Deserialize)]
+struct CratesToml {
+    #[serde(default)]
+    v1: BTreeMap<String, Vec<String>>,
+}
+
+/// a package of a lockfile together with whoever pinned it
+#[derive(Debug, Clone)]
+struct Reference {
+    /// the workspace directory or the installed binary
+    referrer: String,
+    /// the lockfile (or archive) the package was read from
+    lockfile: PathBuf,
+    package: LockedPackage,
+}
+
+/// `ripgrep 12.1.1 (registry+https://github.com/rust-lang/crates.io-index)` => (ripgrep, 12.1.1, registry+...)
+fn parse_package_id(id: &str) -> Option<(String, String, String)> {
+    let mut parts = id.splitn(3, ' ');
+    let name = parts.next()?;
+    let version = parts.next()?;
+    let source = parts.next()?.trim_start_matches('(').trim_end_matches(')');
+    Some((name.to_string(), version.to_string(), source.to_string()))
+}
+
+/// read the install metadata of cargo, prefer .crates2.json over .crates.toml
+fn installed_packages(cargo_home: &Path) -> Result<Vec<InstalledPackage>, Error> {
+    let crates2 = cargo_home.join(".crates2.json");
+    let crates_toml = cargo_home.join(".crates.toml");
+
+    let installs: Vec<(String, Vec<String>)> = if crates2.is_file() {
+        let content = fs::read_to_string(&crates2)
+            .map_err(|e| Error::UnparsableInstallMetadata(crates2.clone(), e.to_string()))?;
+        let parsed: Crates2 = serde_json::from_str(&content)
+            .map_err(|e| Error::UnparsableInstallMetadata(crates2.clone(), e.to_string()))?;
+        parsed
+            .installs
+            .into_iter()
+            .map(|(id, entry)| (id, entry.bins))
+            .collect()
+    } else if crates_toml.is_file() {
+        let content = fs::read_to_string(&crates_toml)
+            .map_err(|e| Error::UnparsableInstallMetadata(crates_toml.clone(), e.to_string()))?;
+        let parsed: CratesToml = toml::from_str(&content)
+            .map_err(|e| Error::UnparsableInstallMetadata(crates_toml.clone(), e.to_string()))?;
+        parsed.v1.into_iter().collect()
+    } else {
+        Vec::new()
+    };
+
+    Ok(installs
+        .into_iter()
+        .filter_map(|(id, bins)| {
+            let (name, version, source) = parse_package_id(&id)?;
+            Some(InstalledPackage {
+                name,
+                version,
+                source,
+                bins,
+            })
+        })
+        .collect())
+}
+
+/// read "<name>-<version>/Cargo.lock" out of a .crate archive
+fn lockfile_from_crate_archive(
+    archive_path: &Path,
+    name: &str,
+    version: &str,
+) -> Option<Vec<LockedPackage>> {
+    let file = open_without_atime(archive_path).ok()?;
+    let mut archive = tar::Archive::new(GzDecoder::new(file));
+    let wanted = Path::new(&format!("{}-{}", name, version)).join("Cargo.lock");
+
+    for entry_result in archive.entries().ok()? {
+        let mut entry = entry_result.ok()?;
+        if entry.path().ok()?.as_ref() != wanted.as_path() {
+            continue;
+        }
+        let mut content = String::new();
+        let _ = entry.read_to_string(&mut content).ok()?;
+        return parse_lockfile(&content, archive_path).ok();
+    }
+    None
+}
+
+/// get the Cargo.lock an installed binary was built with from the cache
+/// binaries that were installed without a lockfile (or whose source is gone) have no dependencies we know of
+fn lockfile_of_installed(
+    cargo_cache_paths: &CargoCachePaths,
+    installed: &InstalledPackage,
+) -> Option<(PathBuf, Vec<LockedPackage>)> {
+    let source = &installed.source;
+    let crate_dir = format!("{}-{}", installed.name, installed.version);
+
+    if source.starts_with("registry+") || source.starts_with("sparse+") {
+        let prefixes = registry_prefixes(source);
+        let matches = |dir: &String| prefixes.iter().any(|prefix| has_hash_suffix(dir, prefix));
+
+        // the extracted source is the cheapest to look at
+        for registry in dir_names(&cargo_cache_paths.registry_sources)
+            .iter()
+            .filter(|dir| matches(dir))
+        {
+            let lockfile = cargo_cache_paths
+                .registry_sources
+                .join(registry)
+                .join(&crate_dir)
+                .join("Cargo.lock");
+            if let Ok(packages) = read_lockfile(&lockfile) {
+                return Some((lockfile, packages));
+            }
+        }
+        // otherwise peek into the .crate
+        for registry in dir_names(&cargo_cache_paths.registry_pkg_cache)
+            .iter()
+            .filter(|dir| matches(dir))
+        {
+            let archive = cargo_cache_paths
+                .registry_pkg_cache
+                .join(registry)
+                .join(format!("{}.crate", crate_dir));
+            if let Some(packages) =
+                lockfile_from_crate_archive(&archive, &installed.name, &installed.version)
+            {
+                return Some((archive, packages));
+            }
+        }
+        None
+    } else if source.starts_with("git+") {
+        let prefix = format!("{}-", git_repo_name(source));
+        let revision = source.rfind('#').map_or("", |pos| &source[pos + 1..]);
+        dir_names(&cargo_cache_paths.git_checkouts)
+            .iter()
+            .filter(|dir| has_hash_suffix(dir, &prefix))
+            .flat_map(|repo| {
+                let repo_dir = cargo_cache_paths.git_checkouts.join(repo);
+                dir_names(&repo_dir)
+                    .into_iter()
+                    .filter(|rev| !revision.is_empty() && revision.starts_with(rev.as_str()))
+                    .map(move |rev| repo_dir.join(rev).join("Cargo.lock"))
+            })
+            .find_map(|lockfile| {
+                read_lockfile(&lockfile)
+                    .ok()
+                    .map(|packages| (lockfile, packages))
+            })
+    } else if source.starts_with("path+file://") {
+        let lockfile = PathBuf::from(source.trim_start_matches("path+file://")).join("Cargo.lock");
+        read_lockfile(&lockfile)
+            .ok()
+            .map(|packages| (lockfile, packages))
+    } else {
+        None
+    }
+}
+
+/// the project roots from the command line, the environment or the current directory
+fn project_roots(config: &ArgMatches<'_>) -> Result<Vec<PathBuf>, Error> {
+    if let Some(roots) = config.values_of("root") {
+        return Ok(roots.map(PathBuf::from).collect());
+    }
+    if let Some(paths) = env::var_os(PROJECT_ROOTS_ENV) {
+        let roots: Vec<PathBuf> = env::split_paths(&paths).collect();
+        if !roots.is_empty() {
+            return Ok(roots);
+        }
+    }
+    env::current_dir()
+        .map(|cwd| vec![cwd])
+        .map_err(|_| Error::NoCWD)
+}
+
+/// collect the packages pinned by all workspaces below the roots and by all installed binaries
+fn collect_references(
+    cargo_cache_paths: &CargoCachePaths,
+    roots: &[PathBuf],
+) -> Result<Vec<Reference>, Error> {
+    let mut lockfiles: Vec<PathBuf> = roots
+        .iter()
+        .flat_map(|root| scan_for_manifests(root, &cargo_cache_paths.cargo_home))
+        .filter_map(|manifest| lockfile_of_manifest(&manifest).ok())
+        .collect();
+    lockfiles.sort();
+    lockfiles.dedup();
+
+    let mut references = Vec::new();
+    for lockfile in &lockfiles {
+        let workspace = lockfile.parent().unwrap_or(lockfile);
+        match read_lockfile(lockfile) {
+            Ok(packages) => references.extend(packages.into_iter().map(|package| Reference {
+                referrer: workspace.display().to_string(),
+                lockfile: lockfile.clone(),
+                package,
+            })),
+            // a single broken lockfile should not hide all the other projects
+            Err(error) => eprintln!("Warning: {}", error),
+        }
+    }
+
+    let install_metadata = cargo_cache_paths.cargo_home.join(".crates2.json");
+    for installed in installed_packages(&cargo_cache_paths.cargo_home)? {
+        let referrer = installed.describe();
+        // the installed crate itself
+        references.push(Reference {
+            referrer: referrer.clone(),
+            lockfile: install_metadata.clone(),
+            package: LockedPackage {
+                name: installed.name.clone(),
+                version: installed.version.clone(),
+                source: Some(installed.source.clone()),
+                checksum: None,
+            },
+        });
+        // and everything it was built with
+        if let Some((lockfile, packages)) = lockfile_of_installed(cargo_cache_paths, &installed) {
+            references.extend(packages.into_iter().map(|package| Reference {
+                referrer: referrer.clone(),
+                lockfile: lockfile.clone(),
+                package,
+            }));
+        }
+    }
+
+    Ok(references)
+}
+
+/// "serde@1.0.116" => ("serde", Some("1.0.116")), "serde" => ("serde", None)
+fn parse_crate_spec(spec: &str) -> (&str, Option<&str>) {
+    match spec.find('@') {
+        Some(pos) => (&spec[..pos], Some(&spec[pos + 1..])),
+        None => (spec, None),
+    }
+}
+
+/// does a locked package match the name (or git repo) and version we are looking for
+fn matches_spec(package: &LockedPackage, name: &str, version: Option<&str>) -> bool {
+    // workspace members and path dependencies are not inside the cache
+    if let Some(source) = &package.source {
+        let name_matches =
+            package.name == name || (source.starts_with("git+") && git_repo_name(source) == name);
+        name_matches && version.iter().all(|v| package.version == *v)
+    } else {
+        false
+    }
+}
+
+/// group the matching references by package: "serde 1.0.116 (registry+...)" => [referrers]
+fn find_referrers(
+    references: &[Reference],
+    name: &str,
+    version: Option<&str>,
+) -> BTreeMap<String, Vec<String>> {
+    let mut found: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for reference in references
+        .iter()
+        .filter(|reference| matches_spec(&reference.package, name, version))
+    {
+        let package = &reference.package;
+        let key = format!(
+            "{} {} ({})",
+            package.name,
+            package.version,
+            package.source.as_deref().unwrap_or_default()
+        );
+        let referrers = found.entry(key).or_default();
+        if !referrers.contains(&reference.referrer) {
+            referrers.push(reference.referrer.clone());
+        }
+    }
+    for referrers in found.values_mut() {
+        referrers.sort();
+    }
+    found
+}
+
+/// list the items of the cache that are not referenced by anything we found
+fn print_unreferenced(
+    cargo_cache_paths: &CargoCachePaths,
+    references: Vec<Reference>,
+    checkouts_cache: &mut git_checkouts::GitCheckoutCache,
+    bare_repos_cache: &mut git_bare_repos::GitRepoCache,
+    registry_pkg_caches: &mut registry_pkg_cache::RegistryPkgCaches,
+    registry_sources_caches: &mut registry_sources::RegistrySourceCaches,
+) {
+    let required = required_items_from_packages(
+        cargo_cache_paths,
+        references
+            .into_iter()
+            .map(|reference| (reference.lockfile, reference.package))
+            .collect(),
+    );
+
+    let mut unreferenced: Vec<PathBuf> = registry_pkg_caches
+        .files()
+        .into_iter()
+        .filter(|krate| !required.crates.contains(krate))
+        .chain(
+            registry_sources_caches
+                .items()
+                .iter()
+                .filter(|source| !required.sources.contains(source))
+                .cloned(),
+        )
+        .chain(
+            bare_repos_cache
+                .items()
+                .iter()
+                .filter(|repo| !required.git_repos.contains(repo))
+                .cloned(),
+        )
+        .chain(
+            checkouts_cache
+                .items()
+                .iter()
+                .filter(|checkout| !required.checkouts.contains(checkout))
+                .cloned(),
+        )
+        .collect();
+    unreferenced.sort();
+
+    let mut total_size = 0;
+    for item in &unreferenced {
+        let size = size_of_path(item);
+        total_size += size;
+        println!(
+            "{} ({})",
+            item.display(),
+            size.file_size(file_size_opts::DECIMAL).unwrap()
+        );
+    }
+    println!(
+        "{} items ({}) are not referenced by any project or installed binary.",
+        unreferenced.len(),
+        total_size.file_size(file_size_opts::DECIMAL).unwrap()
+    );
+}
+
+/// "cargo cache why"
+pub(crate) fn why(
+    cargo_cache_paths: &CargoCachePaths,
+    config: &ArgMatches<'_>,
+    checkouts_cache: &mut git_checkouts::GitCheckoutCache,
+    bare_repos_cache: &mut git_bare_repos::GitRepoCache,
+    registry_pkg_caches: &mut registry_pkg_cache::RegistryPkgCaches,
+    registry_sources_caches: &mut registry_sources::RegistrySourceCaches,
+) -> Result<(), Error> {
+    let roots = project_roots(config)?;
+    let references = collect_references(cargo_cache_paths, &roots)?;
+
+    if config.is_present("unreferenced") {
+        print_unreferenced(
+            cargo_cache_paths,
+            references,
+            checkouts_cache,
+            bare_repos_cache,
+            registry_pkg_caches,
+            registry_sources_caches,
+        );
+        return Ok(());
+    }
+
+    // clap makes sure we have a crate if we are not in --unreferenced mode
+    let spec = config.value_of("CRATE").unwrap_or_default();
+    let (name, version) = parse_crate_spec(spec);
+    let found = find_referrers(&references, name, version);
+
+    if found.is_empty() {
+        println!(
+            "\"{}\" is not referenced by any project below {} or any installed binary.",
+            spec,
+            roots
+                .iter()
+                .map(|root| format!("'{}'", root.display()))
+                .collect::<Vec<String>>()
+                .join(", ")
+        );
+        return Ok(());
+    }
+
+    for (package, referrers) in found {
+        println!("{}", package);
+        for referrer in referrers {
+            println!("\t{}", referrer);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod whytests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn locked(name: &str, version: &str, source: Option<&str>) -> LockedPackage {
+        LockedPackage {
+            name: name.into(),
+            version: version.into(),
+            source: source.map(String::from),
+            checksum: None,
+        }
+    }
+
+    #[test]
+    fn crate_specs() {
+        assert_eq!(parse_crate_spec("serde"), ("serde", None));
+        assert_eq!(
+            parse_crate_spec("serde@1.0.116"),
+            ("serde", Some("1.0.116"))
+        );
+    }
+
+    #[test]
+    fn package_ids() {
+        assert_eq!(
+            parse_package_id(
+                "ripgrep 12.1.1 (registry+https://github.com/rust-lang/crates.io-index)"
+            ),
+            Some((
+                "ripgrep".into(),
+                "12.1.1".into(),
+                "registry+https://github.com/rust-lang/crates.io-index".into()
+            ))
+        );
+        assert_eq!(parse_package_id("garbage"), None);
+    }
+
+    #[test]
+    fn referrers_are_grouped() {
+        let crates_io = Some("registry+https://github.com/rust-lang/crates.io-index");
+        let git = Some("git+https://github.com/rust-lang/cargo?branch=master#258c896");
+        let reference = |referrer: &str, package| Reference {
+            referrer: referrer.into(),
+            lockfile: PathBuf::from("Cargo.lock"),
+            package,
+        };
+        let references = vec![
+            reference("/b", locked("serde", "1.0.116", crates_io)),
+            reference("/a", locked("serde", "1.0.116", crates_io)),
+            reference("/a", locked("serde", "1.0.100", crates_io)),
+            reference("/c", locked("cargo", "0.48.0", git)),
+            // workspace members are never in the cache
+            reference("/d", locked("serde", "1.0.116", None)),
+        ];
+
+        let all_serdes = find_referrers(&references, "serde", None);
+        assert_eq!(all_serdes.len(), 2);
+        assert_eq!(
+            all_serdes["serde 1.0.116 (registry+https://github.com/rust-lang/crates.io-index)"],
+            vec!["/a", "/b"]
+        );
+
+        let one_serde = find_referrers(&references, "serde", Some("1.0.100"));
+        assert_eq!(one_serde.len(), 1);
+
+        let cargo = find_referrers(&references, "cargo", None);
+        assert_eq!(cargo.values().next().unwrap(), &vec!["/c".to_string()]);
+    }
+
+    #[test]
+    fn read_install_metadata() {
+        let root = PathBuf::from("target/why_tests/read_install_metadata");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        fs::write(
+            root.join(".crates2.json"),
+            r#"{"installs":{"ripgrep 12.1.1 (registry+https://github.com/rust-lang/crates.io-index)":{"version_req":null,"bins":["rg"],"features":[],"all_features":false,"no_default_features":false,"profile":"release","target":"x86_64-unknown-linux-gnu","rustc":"rustc 1.47.0"}}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            installed_packages(&root).unwrap(),
+            vec![InstalledPackage {
+                name: "ripgrep".into(),
+                version: "12.1.1".into(),
+                source: "registry+https://github.com/rust-lang/crates.io-index".into(),
+                bins: vec!["rg".into()],
+            }]
+        );
+    }
+}
@@ -0,0 +1,123 @@
+// Copyright 2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// persistent settings of cargo-cache, read from
+// ${CARGO_HOME}/.cargo-cache/config.toml
+//
+// [trim]
+// limit = "3G"
+// ensure-free = "15%"
+// strategy = "rebuild-cost"
+//
+// [trim.quotas]
+// git-repos = "500M"
+// registry-crate-cache = "2G"
+//
+// [confirm]
+// always = true
+//
+// options given on the command line always take precedence over the config file
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::library::Error;
+
+use serde::Deserialize;
+
+/// the settings of all subcommands
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Config {
+    #[serde(default)]
+    pub(crate) trim: TrimConfig,
+    #[serde(default)]
+    pub(crate) confirm: ConfirmConfig,
+}
+
+/// settings of "cargo cache trim"
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct TrimConfig {
+    /// the size the whole cargo home is trimmed to
+    pub(crate) limit: Option<String>,
+    /// free space on the file system of the cargo home: "10G" or "15%"
+    #[serde(rename = "ensure-free")]
+    pub(crate) ensure_free: Option<String>,
+    /// the order in which items are removed: "lru", "rebuild-cost" or "size"
+    pub(crate) strategy: Option<String>,
+    /// maximum size of a single component: "git-repos" = "500M"
+    #[serde(default)]
+    pub(crate) quotas: BTreeMap<String, String>,
+}
+
+/// when to ask before removing items
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ConfirmConfig {
+    /// also ask if stdout is not a terminal
+    #[serde(default)]
+    pub(crate) always: bool,
+}
+
+/// get the path of the default config file
+pub(crate) fn config_path(cargo_home: &Path) -> PathBuf {
+    cargo_home.join(".cargo-cache").join("config.toml")
+}
+
+/// read the config file passed via "--config" or the default one
+/// a missing default config is not an error, a missing explicit one is
+pub(crate) fn load_config(cargo_home: &Path, explicit: Option<&str>) -> Result<Config, Error> {
+    let path = explicit.map_or_else(|| config_path(cargo_home), PathBuf::from);
+
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound && explicit.is_none() => {
+            return Ok(Config::default())
+        }
+        Err(e) => return Err(Error::ConfigIo(path, e)),
+    };
+    toml::from_str(&content).map_err(|e| Error::UnparsableConfig(path, e))
+}
+
+#[cfg(test)]
+mod configtests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn read_config() {
+        let cargo_home = PathBuf::from("target/config_tests/read_config");
+        let _ = fs::remove_dir_all(&cargo_home);
+
+        // no config at all
+        assert_eq!(load_config(&cargo_home, None).unwrap(), Config::default());
+
+        fs::create_dir_all(cargo_home.join(".cargo-cache")).unwrap();
+        fs::write(
+            config_path(&cargo_home),
+            "[trim]\nlimit = \"3G\"\n\n[trim.quotas]\ngit-repos = \"500M\"\n\n[confirm]\nalways = true\n",
+        )
+        .unwrap();
+
+        let config = load_config(&cargo_home, None).unwrap();
+        assert_eq!(config.trim.limit, Some("3G".to_string()));
+        assert_eq!(config.trim.quotas["git-repos"], "500M");
+        assert!(config.confirm.always);
+
+        // typos are reported
+        fs::write(config_path(&cargo_home), "[trim]\nlimt = \"3G\"\n").unwrap();
+        assert!(load_config(&cargo_home, None).is_err());
+
+        // an explicit config file has to exist
+        assert!(load_config(&cargo_home, Some("target/config_tests/does_not_exist.toml")).is_err());
+    }
+}
@@ -0,0 +1,231 @@
+// Copyright 2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// ask before removing anything
+// "--remove-dir", "--autoclean", "--keep-duplicate-crates", "clean-unref", "trim" and "empty-trash" (among others)
+// print what they are about to remove and wait for a "y"
+// if stdout is a terminal. "--yes" (and "--dry-run") skip the question, with
+//
+// [confirm]
+// always = true
+//
+// in the config file we also ask if stdout is not a terminal, scripts have to pass "--yes" then.
+
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+use crate::config::ConfirmConfig;
+use crate::library::Error;
+use crate::tables::format_table;
+
+use humansize::{file_size_opts, FileSize};
+
+/// when to ask before removing items
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Confirm {
+    /// "--yes" or "--dry-run"
+    Never,
+    /// only if stdout is a terminal
+    Terminal,
+    /// even if stdout is not a terminal
+    Always,
+}
+
+impl Confirm {
+    pub(crate) fn new(yes: bool, dry_run: bool, config: &ConfirmConfig) -> Self {
+        if yes || dry_run {
+            Self::Never
+        } else if config.always {
+            Self::Always
+        } else {
+            Self::Terminal
+        }
+    }
+
+    /// do we have to ask?
+    pub(crate) fn needed(self) -> bool {
+        match self {
+            Self::Never => false,
+            Self::Terminal => stdout_is_terminal(),
+            Self::Always => true,
+        }
+    }
+}
+
+/// is stdout attached to a terminal?
+#[allow(unsafe_code)]
+fn stdout_is_terminal() -> bool {
+    // SAFETY: isatty only inspects the file descriptor and has no other preconditions
+    unsafe { libc::isatty(1) != 0 }
+}
+
+/// an item we are about to remove
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PlannedRemoval {
+    pub(crate) path: PathBuf,
+    pub(crate) component: String,
+    pub(crate) size: u64,
+}
+
+impl PlannedRemoval {
+    pub(crate) fn new(path: PathBuf, component: &str, size: u64) -> Self {
+        Self {
+            path,
+            component: component.to_string(),
+            size,
+        }
+    }
+}
+
+fn human(size: u64) -> String {
+    size.file_size(file_size_opts::DECIMAL).unwrap()
+}
+
+/// the paths, the size per component and the total size of the items
+fn describe_plan(plan: &[PlannedRemoval]) -> String {
+    let mut description = plan
+        .iter()
+        .map(|item| format!("{} ({})", item.path.display(), human(item.size)))
+        .collect::<Vec<_>>()
+        .join("\n");
+    description.push_str("\n\n");
+
+    let mut per_component: BTreeMap<&str, (usize, u64)> = BTreeMap::new();
+    for item in plan {
+        let entry = per_component.entry(&item.component).or_insert((0, 0));
+        entry.0 += 1;
+        entry.1 += item.size;
+    }
+    let mut table = vec![vec![
+        "Component".to_string(),
+        "Items".to_string(),
+        "Size".to_string(),
+    ]];
+    for (component, (items, size)) in &per_component {
+        table.push(vec![component.to_string(), items.to_string(), human(*size)]);
+    }
+    table.push(vec![
+        "Total".to_string(),
+        plan.len().to_string(),
+        human(plan.iter().map(|item| item.size).sum()),
+    ]);
+    description.push_str(&format_table(&table, 1));
+    description
+}
+
+/// print the plan and read the answer, only "y" and "yes" continue
+fn ask<R: BufRead, W: Write>(plan: &[PlannedRemoval], input: &mut R, output: &mut W) -> bool {
+    let total: u64 = plan.iter().map(|item| item.size).sum();
+    let _ = write!(
+        output,
+        "{}\nRemove {} items, freeing {}? [y/N] ",
+        describe_plan(plan),
+        plan.len(),
+        human(total)
+    );
+    let _ = output.flush();
+
+    let mut answer = String::new();
+    if input.read_line(&mut answer).is_err() {
+        return false;
+    }
+    let reply = answer.trim();
+    reply.eq_ignore_ascii_case("y") || reply.eq_ignore_ascii_case("yes")
+}
+
+/// ask the user whether the items may be removed if needed, Err if they may not
+pub(crate) fn confirm_removal(confirm: Confirm, plan: &[PlannedRemoval]) -> Result<(), Error> {
+    if plan.is_empty() || !confirm.needed() {
+        return Ok(());
+    }
+    let stdin = io::stdin();
+    if ask(plan, &mut stdin.lock(), &mut io::stdout()) {
+        Ok(())
+    } else {
+        Err(Error::RemovalAborted)
+    }
+}
+
+#[cfg(test)]
+mod confirmtests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn plan() -> Vec<PlannedRemoval> {
+        vec![
+            PlannedRemoval::new(
+                PathBuf::from("cache/a.crate"),
+                "registry-crate-cache",
+                1_000,
+            ),
+            PlannedRemoval::new(PathBuf::from("git/db/repo"), "git-db", 500),
+            PlannedRemoval::new(
+                PathBuf::from("cache/b.crate"),
+                "registry-crate-cache",
+                2_000,
+            ),
+        ]
+    }
+
+    #[test]
+    fn modes() {
+        let ask_always = ConfirmConfig { always: true };
+        assert_eq!(
+            Confirm::new(false, false, &ConfirmConfig::default()),
+            Confirm::Terminal
+        );
+        assert_eq!(Confirm::new(false, false, &ask_always), Confirm::Always);
+        assert_eq!(Confirm::new(true, false, &ask_always), Confirm::Never);
+        assert_eq!(Confirm::new(false, true, &ask_always), Confirm::Never);
+
+        // nothing to ask about
+        assert!(confirm_removal(Confirm::Always, &[]).is_ok());
+        assert!(confirm_removal(Confirm::Never, &plan()).is_ok());
+    }
+
+    #[test]
+    fn answers() {
+        for (answer, expected) in &[
+            ("y\n", true),
+            ("Yes\n", true),
+            (" y \n", true),
+            ("\n", false),
+            ("n\n", false),
+            ("yy\n", false),
+            ("", false),
+        ] {
+            let mut output = Vec::new();
+            assert_eq!(
+                ask(&plan(), &mut answer.as_bytes(), &mut output),
+                *expected,
+                "answer: {:?}",
+                answer
+            );
+        }
+    }
+
+    #[test]
+    fn shows_plan() {
+        let mut output = Vec::new();
+        let _ = ask(&plan(), &mut "n\n".as_bytes(), &mut output);
+        let printed = String::from_utf8(output).unwrap();
+        let expected = "cache/a.crate (1 KB)
+git/db/repo (500 B)
+cache/b.crate (2 KB)
+
+Component             Items  Size
+git-db                1      500 B
+registry-crate-cache  2      3 KB
+Total                 3      3.50 KB
+
+Remove 3 items, freeing 3.50 KB? [y/N] ";
+        assert_eq!(printed, expected);
+    }
+}
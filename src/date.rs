@@ -7,139 +7,286 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::audit::AuditLog;
 use crate::cache::caches::{Cache, RegistrySuperCache};
 use crate::cache::*;
+use crate::confirm::{confirm_removal, Confirm, PlannedRemoval};
 use crate::library::*;
 use crate::remove::*;
+use crate::timestamps::{last_use, TimeSource};
+use crate::trash::Trash;
 
-use chrono::{prelude::*, NaiveDateTime};
+use chrono::{prelude::*, Duration, NaiveDateTime};
 use regex::Regex;
 
-// remove cache items that are older than X or younger than Y (or between X and Y)
+// remove cache items that are older than X or younger than Y
+// items are whole .crate archives, extracted crate sources, registry indices, git repos and checkouts,
+// an item was last used when its most recently used file was
+// if both are given, remove the items used between Y and X, with "--outside-range" remove everything
+// outside of them instead (older than X or younger than Y)
 
 //  testing:
 // ./target/debug/cargo-cache --dry-run  --remove-dir=git-db  --remove-if-younger-than 08:08:08
 
 // check how to query files
-#[derive(Debug, Clone)]
-enum AgeRelation<'a> {
+// the dates are parsed only once so that relative dates mean the same for the filter and the messages
+#[derive(Debug, Clone, PartialEq)]
+enum AgeRelation {
     None,
-    FileOlderThanDate(&'a str),
-    FileYoungerThanDate(&'a str),
-    // OlderOrYounger(&'a str, &'a str),
+    FileOlderThanDate(NaiveDateTime),
+    FileYoungerThanDate(NaiveDateTime),
+    // the items used between both dates or, if "outside" is set, the items used before "older" or after "younger"
+    OlderOrYounger {
+        older: NaiveDateTime,
+        younger: NaiveDateTime,
+        outside: bool,
+    },
 }
 
-fn parse_date(date: &str) -> Result<NaiveDateTime, Error> {
-    // @TODO handle yyyyy.mm.dd hh:mm:ss
-    // @TODO  handle dd.mm.yy if yy is yy and not yyyy
-    let date_to_compare: NaiveDateTime = {
-        // xxxx.xx.xx => yyyy.mm.dd
-        // we only have a date but no time
-        if Regex::new(r"^\d{4}.\d{2}.\d{2}$").unwrap().is_match(date) {
-            // most likely a date
-            let now = Local::now();
-            let split: Result<Vec<u32>, _> = date.split('.').map(str::parse).collect();
-            let split = match split {
-                Ok(result) => result,
-                Err(a) => return Err(Error::DateParseFailure(a.to_string(), "u32".into())),
-            };
-            #[allow(clippy::cast_possible_wrap)]
-            let nd =
-                if let Some(date) = NaiveDate::from_ymd_opt(split[0] as i32, split[1], split[2]) {
-                    date
+impl AgeRelation {
+    fn new(
+        arg_older: Option<&str>,
+        arg_younger: Option<&str>,
+        outside: bool,
+    ) -> Result<Self, Error> {
+        Ok(match (arg_older, arg_younger) {
+            (None, None) => Self::None,
+            (None, Some(younger)) => Self::FileYoungerThanDate(parse_date(younger)?),
+            (Some(older), None) => Self::FileOlderThanDate(parse_date(older)?),
+            (Some(older_input), Some(younger_input)) => {
+                let older = parse_date(older_input)?;
+                let younger = parse_date(younger_input)?;
+                // an empty range would silently match nothing (or everything if inverted)
+                let valid = if outside {
+                    older < younger
                 } else {
-                    return Err(Error::DateParseFailure(
-                        format!("{}.{}.{}", split[0], split[1], split[2]),
-                        "date".into(),
-                    ));
+                    younger < older
                 };
+                if !valid {
+                    return Err(Error::DateRangeEmpty(
+                        older_input.to_string(),
+                        younger_input.to_string(),
+                        outside,
+                    ));
+                }
+                Self::OlderOrYounger {
+                    older,
+                    younger,
+                    outside,
+                }
+            }
+        })
+    }
+}
 
-            nd.and_hms(now.hour(), now.minute(), now.second())
-
-        // xx:xx:xx => hh::mm::ss
-        } else if Regex::new(r"^\d{2}:\d{2}:\d{2}$").unwrap().is_match(date) {
-            // probably a time
-            let today = Local::today();
-
-            let split: Result<Vec<u32>, _> = date.split(':').map(str::parse).collect();
-            let split = match split {
-                Ok(result) => result,
-                Err(a) => return Err(Error::DateParseFailure(a.to_string(), "u32".into())),
-            };
+/// was the item with this access date used inside of the range (or outside of it, if inverted)
+fn in_window(
+    access_date: NaiveDateTime,
+    older: NaiveDateTime,
+    younger: NaiveDateTime,
+    outside: bool,
+) -> bool {
+    if outside {
+        access_date < older || younger < access_date
+    } else {
+        younger < access_date && access_date < older
+    }
+}
 
-            let nd = if let Some(date) =
-                NaiveDate::from_ymd_opt(today.year(), today.month(), today.day())
-            {
-                date
+fn describe_relation(relation: &AgeRelation) -> String {
+    let show = |date: &NaiveDateTime| date.format("%Y-%m-%d %H:%M:%S").to_string();
+    match relation {
+        AgeRelation::FileYoungerThanDate(date) => format!("younger than {}", show(date)),
+        AgeRelation::FileOlderThanDate(date) => format!("older than {}", show(date)),
+        AgeRelation::OlderOrYounger {
+            older,
+            younger,
+            outside,
+        } => {
+            if *outside {
+                format!(
+                    "older than {} or younger than {}",
+                    show(older),
+                    show(younger)
+                )
             } else {
-                return Err(Error::DateParseFailure(
-                    format!("{}:{}:{}", today.year(), today.month(), today.day()),
-                    "date".into(),
-                ));
-            };
-
-            nd.and_hms(split[0], split[1], split[2])
-        } else {
-            return Err(Error::DateParseFailure(date.into(), "a valid date".into()));
+                format!(
+                    "younger than {} and older than {}",
+                    show(younger),
+                    show(older)
+                )
+            }
         }
+        AgeRelation::None => {
+            unreachable!("ERROR: no dates were supplied although -o or -y were passed!")
+        }
+    }
+}
+
+/// units of relative dates: "30d" or "6 months" means 30 days or 6 months ago
+/// a month is 30 days and a year 365 days
+const RELATIVE_UNITS: [(&[&str], i64); 7] = [
+    (&["s", "sec", "secs", "second", "seconds"], 1),
+    (&["min", "mins", "minute", "minutes"], 60),
+    (&["h", "hour", "hours"], 60 * 60),
+    (&["d", "day", "days"], 24 * 60 * 60),
+    (&["w", "week", "weeks"], 7 * 24 * 60 * 60),
+    (&["mo", "month", "months"], 30 * 24 * 60 * 60),
+    (&["y", "year", "years"], 365 * 24 * 60 * 60),
+];
+
+/// parse a date given on the command line, all dates are in local time unless a timezone is given
+/// YYYY.MM.DD or YYYY-MM-DD => that day at the current time of day
+/// HH:MM:SS => today at that time
+/// 2020-10-01T12:00:00+02:00, 2020-10-01T12:00:00Z or 2020-10-01T12:00:00 => ISO 8601 timestamp
+/// 30d, 2w, 6months... => relative to now
+pub(crate) fn parse_date(input: &str) -> Result<NaiveDateTime, Error> {
+    let date = input.trim();
+    let failure = |expected: &str| Error::DateParseFailure(date.into(), expected.into());
+
+    // xxxx.xx.xx => yyyy.mm.dd or xxxx-xx-xx => yyyy-mm-dd
+    // we only have a date but no time
+    let day_format = if Regex::new(r"^\d{4}\.\d{2}\.\d{2}$").unwrap().is_match(date) {
+        Some("%Y.%m.%d")
+    } else if Regex::new(r"^\d{4}-\d{2}-\d{2}$").unwrap().is_match(date) {
+        Some("%Y-%m-%d")
+    } else {
+        None
     };
-    Ok(date_to_compare)
+    if let Some(format) = day_format {
+        let day = NaiveDate::parse_from_str(date, format).map_err(|_| {
+            failure(&format!(
+                "expected a valid day in the format {}",
+                format
+                    .replace("%Y", "YYYY")
+                    .replace("%m", "MM")
+                    .replace("%d", "DD")
+            ))
+        })?;
+        let now = Local::now();
+        return Ok(day.and_hms(now.hour(), now.minute(), now.second()));
+    }
+
+    // xx:xx:xx => hh::mm::ss
+    if Regex::new(r"^\d{2}:\d{2}:\d{2}$").unwrap().is_match(date) {
+        let time = NaiveTime::parse_from_str(date, "%H:%M:%S")
+            .map_err(|_| failure("expected a valid time in the format HH:MM:SS"))?;
+        return Ok(Local::today().naive_local().and_time(time));
+    }
+
+    // 2020-10-01T12:00:00+02:00 (a space instead of the "T" is fine as well)
+    if Regex::new(r"^\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}")
+        .unwrap()
+        .is_match(date)
+    {
+        let timestamp = date.replacen(' ', "T", 1);
+        if let Ok(datetime) = DateTime::parse_from_rfc3339(&timestamp)
+            .or_else(|_| DateTime::parse_from_str(&timestamp, "%Y-%m-%dT%H:%M:%S%.f%z"))
+        {
+            return Ok(datetime.with_timezone(&Local).naive_local());
+        }
+        return NaiveDateTime::parse_from_str(&timestamp, "%Y-%m-%dT%H:%M:%S%.f").map_err(|_| {
+            failure("expected an ISO 8601 timestamp like 2020-10-01T12:00:00+02:00, 2020-10-01T12:00:00Z or 2020-10-01T12:00:00")
+        });
+    }
+
+    // 30d, 2w, 6months
+    if let Some(captures) = Regex::new(r"^(\d+)\s*([[:alpha:]]+)$")
+        .unwrap()
+        .captures(date)
+    {
+        let unit = captures[2].to_lowercase();
+        let seconds_per_unit = match RELATIVE_UNITS
+            .iter()
+            .find(|(names, _)| names.contains(&unit.as_str()))
+        {
+            Some((_, seconds)) => *seconds,
+            None => {
+                return Err(failure(&format!(
+                    "unknown unit \"{}\" of relative date, expected one of s, min, h, d, w, months or y",
+                    &captures[2]
+                )))
+            }
+        };
+        return captures[1]
+            .parse::<i64>()
+            .ok()
+            .and_then(|amount| amount.checked_mul(seconds_per_unit))
+            // chrono::Duration panics above this
+            .filter(|seconds| *seconds <= i64::MAX / 1000)
+            .and_then(|seconds| {
+                Local::now()
+                    .naive_local()
+                    .checked_sub_signed(Duration::seconds(seconds))
+            })
+            .ok_or_else(|| failure("relative date is too far in the past"));
+    }
+
+    Err(failure("expected YYYY.MM.DD, YYYY-MM-DD, HH:MM:SS, an ISO 8601 timestamp like 2020-10-01T12:00:00+02:00 or a relative date like 30d, 2w or 6months"))
 }
 
 #[derive(Debug, Clone)]
 struct FileWithDate {
-    file: std::path::PathBuf,
+    file: PathBuf,
     access_date: NaiveDateTime,
 }
 
 fn filter_files_by_date<'a>(
-    date: &AgeRelation<'_>,
+    date: &AgeRelation,
     files: &'a [FileWithDate],
-) -> Result<Vec<&'a FileWithDate>, Error> {
+) -> Vec<&'a FileWithDate> {
     match date {
         AgeRelation::None => {
             unreachable!("ERROR: no dates were supplied although -o or -y were passed!");
         }
         AgeRelation::FileYoungerThanDate(younger_date) => {
             // file is younger than date if file.date > date_param
-            let date_parameter = parse_date(younger_date)?;
-            Ok(files
+            files
                 .iter()
-                .filter(|file| file.access_date > date_parameter)
-                .collect())
+                .filter(|file| file.access_date > *younger_date)
+                .collect()
         }
         AgeRelation::FileOlderThanDate(older_date) => {
             // file is older than date if file.date < date_param
-            let date_parameter = parse_date(older_date)?;
-            Ok(files
+            files
                 .iter()
-                .filter(|file| file.access_date < date_parameter)
-                .collect())
-        } /*   DateComparison::OlderOrYounger(older_date, younger_date) => {
-              let younger_than = parse_date(younger_date)?;
-              let older_than = parse_date(older_date)?;
-
-              Ok(files
-                  .iter()
-                  // this may be bugged
-                  .filter(|file| file.access_date < younger_than || file.access_date > older_than)
-                  .collect())
-          } */
+                .filter(|file| file.access_date < *older_date)
+                .collect()
+        }
+        AgeRelation::OlderOrYounger {
+            older,
+            younger,
+            outside,
+        } => files
+            .iter()
+            .filter(|file| in_window(file.access_date, *older, *younger, *outside))
+            .collect(),
     }
 }
 
 #[allow(clippy::too_many_arguments)]
 pub(crate) fn remove_files_by_dates(
     // we need to know which part of the cargo-cache we need to clear out!
+    cargo_home: &Path,
+    bin_cache: &mut bin::BinaryCache,
     checkouts_cache: &mut git_checkouts::GitCheckoutCache,
     bare_repos_cache: &mut git_bare_repos::GitRepoCache,
     registry_pkg_caches: &mut registry_pkg_cache::RegistryPkgCaches,
+    registry_index_caches: &mut registry_index::RegistryIndicesCache,
     registry_sources_caches: &mut registry_sources::RegistrySourceCaches,
     arg_younger: &Option<&str>,
     arg_older: &Option<&str>,
+    outside_range: bool,
+    time_source: TimeSource,
     dry_run: bool,
+    confirm: Confirm,
     dirs: &Option<&str>,
     mut size_changed: &mut bool,
+    trash: Option<&Trash>,
+    audit_log: &AuditLog,
 ) -> Result<(), Error> {
     if dirs.is_none() {
         return Err(Error::RemoveDirNoArg);
@@ -149,83 +296,82 @@ pub(crate) fn remove_files_by_dates(
     let components_to_remove_from = components_from_groups(dirs)?;
     // println!("components: {:?}", components_to_remove_from);
 
-    let mut files_of_components: Vec<std::path::PathBuf> = Vec::new();
+    // try to find out how to compare dates
+    let date_comp = AgeRelation::new(*arg_older, *arg_younger, outside_range)?;
+
+    // never look at single files, removing them could leave half of an extracted crate behind
+    let mut items_of_components: Vec<PathBuf> = Vec::new();
+    // the component of each item, shown when asking for confirmation
+    let mut component_of_item: HashMap<PathBuf, Component> = HashMap::new();
 
     components_to_remove_from.iter().for_each(|component| {
-        match component {
-            Component::RegistryCrateCache => {
-                files_of_components.extend(registry_pkg_caches.files());
-            }
-            Component::RegistrySources => {
-                files_of_components.extend(registry_sources_caches.files());
-            }
-            Component::RegistryIndex => { /* ignore this case */ }
-            Component::GitRepos => {
-                files_of_components.extend(checkouts_cache.items().iter().map(|p| p.to_path_buf()));
-            }
-            Component::GitDB => {
-                files_of_components
-                    .extend(bare_repos_cache.items().iter().map(|p| p.to_path_buf()));
-            }
+        let items = match component {
+            Component::RegistryCrateCache => registry_pkg_caches.items().to_vec(),
+            Component::RegistrySources => registry_sources_caches.items().to_vec(),
+            Component::RegistryIndex => registry_index_caches.items().to_vec(),
+            Component::GitRepos => checkouts_cache.items().to_vec(),
+            Component::GitDB => bare_repos_cache.items().to_vec(),
+            // the rustup proxies are never removed
+            Component::Bin => bin_cache.removable_items(),
+        };
+        for item in &items {
+            let _ = component_of_item.insert(item.clone(), *component);
         }
+        items_of_components.extend(items);
     });
 
-    // try to find out how to compare dates
-    let date_comp: AgeRelation<'_> = match (arg_older, arg_younger) {
-        (None, None) => AgeRelation::None,
-        (None, Some(younger)) => AgeRelation::FileYoungerThanDate(younger),
-        (Some(older), None) => AgeRelation::FileOlderThanDate(older),
-        (Some(_older), Some(_younger)) => {
-            unreachable!("passing both, --remove-if-{older,younger}-than was temporarily disabled!")
-        } // (Some(older), Some(younger)) => DateComparison::OlderOrYounger(older, younger),
-    };
-
-    // for each file, get the access time
-    let mut dates: Vec<FileWithDate> = files_of_components
+    // for each item, get the time of the last use
+    let mut dates: Vec<FileWithDate> = items_of_components
         .into_iter()
-        .map(|path| {
-            let access_time = path.metadata().unwrap().accessed().unwrap();
-            let naive_datetime = chrono::DateTime::<Local>::from(access_time).naive_local();
-            FileWithDate {
+        .filter_map(|path| {
+            let last_use_time = last_use(&path, time_source)?;
+            let naive_datetime = DateTime::<Local>::from(last_use_time).naive_local();
+            Some(FileWithDate {
                 file: path,
                 access_date: naive_datetime,
-            }
+            })
         })
         .collect();
 
     dates.sort_by_key(|f| f.file.clone());
 
     // filter the files by comparing the given date and the files access time
-    let filtered_files: Vec<&FileWithDate> = filter_files_by_date(&date_comp, &dates)?;
+    let filtered_files: Vec<&FileWithDate> = filter_files_by_date(&date_comp, &dates);
 
     if dry_run {
         // dry run
         println!(
             "dry-run: would delete {} items that are {}...",
             filtered_files.len(),
-            match date_comp {
-                AgeRelation::FileYoungerThanDate(date) => format!("younger than {}", date),
-                AgeRelation::FileOlderThanDate(date) => format!("older than {}", date),
-                AgeRelation::None => unreachable!(
-                    "DateComparisonOlder and Younger or None not supported right now (dry run)"
-                ),
-            },
+            describe_relation(&date_comp),
         );
     } else {
         // no dry run / actual run
+        if confirm.needed() {
+            let plan: Vec<PlannedRemoval> = filtered_files
+                .iter()
+                .map(|fwd| {
+                    PlannedRemoval::new(
+                        fwd.file.clone(),
+                        &component_of_item[&fwd.file].to_string(),
+                        size_of_path(&fwd.file),
+                    )
+                })
+                .collect();
+            confirm_removal(confirm, &plan)?;
+        }
         println!(
-            "Deleting {} items that are {}...",
-            filtered_files.len(),
-            match date_comp {
-                AgeRelation::FileYoungerThanDate(date) => format!("younger than {}", date),
-                AgeRelation::FileOlderThanDate(date) => format!("older than {}", date),
-                AgeRelation::None => unreachable!(
-                    "DateComparisonOlder and Younger or None not supported right now (no dry run)"
-                ),
+            "{} {} items that are {}...",
+            if trash.is_some() {
+                "Moving to the trash:"
+            } else {
+                "Deleting"
             },
+            filtered_files.len(),
+            describe_relation(&date_comp),
         );
         filtered_files
-            .into_iter()
+            .iter()
             .map(|fwd| &fwd.file)
             //.inspect(|p| println!("{}", p.display()))
             .for_each(|path| {
@@ -236,9 +382,21 @@ pub(crate) fn remove_files_by_dates(
                     None,
                     &DryRunMessage::Default,
                     None,
+                    trash,
+                    audit_log,
                 )
             });
         // .collect::<Vec<_>>();
+
+        // quarantined binaries are still installed until the trash is emptied
+        if trash.is_none() {
+            let removed_bins: Vec<PathBuf> = filtered_files
+                .iter()
+                .map(|fwd| fwd.file.clone())
+                .filter(|path| path.starts_with(bin_cache.path()))
+                .collect();
+            bin::forget_installed_binaries(cargo_home, &removed_bins)?;
+        }
     }
     // summary is printed from inside main()
     Ok(())
@@ -301,20 +459,227 @@ mod libtests {
     }
 
     #[test]
-    #[should_panic(expected = "invalid time")]
-    fn parse_dates_panic1() {
-        assert!(parse_date(&String::from("24:00:00")).is_err());
+    fn date_windows() {
+        let day = |d: u32| NaiveDate::from_ymd(2020, 10, d).and_hms(12, 0, 0);
+        let files: Vec<FileWithDate> = (1..=5)
+            .map(|d| FileWithDate {
+                file: PathBuf::from(format!("day{}", d)),
+                access_date: day(d),
+            })
+            .collect();
+        let range = |older: &str, younger: &str, outside: bool| {
+            AgeRelation::new(Some(older), Some(younger), outside)
+        };
+        let names = |relation: AgeRelation| -> Vec<String> {
+            filter_files_by_date(&relation, &files)
+                .iter()
+                .map(|f| f.file.display().to_string())
+                .collect()
+        };
+
+        // used between the start of the 2nd and the start of the 4th
+        let between = range("2020-10-04T00:00:00", "2020-10-02T00:00:00", false).unwrap();
+        assert_eq!(
+            describe_relation(&between),
+            "younger than 2020-10-02 00:00:00 and older than 2020-10-04 00:00:00"
+        );
+        assert_eq!(names(between), vec!["day2", "day3"]);
+
+        // used before the start of the 2nd or after the start of the 4th
+        let outside = range("2020-10-02T00:00:00", "2020-10-04T00:00:00", true).unwrap();
+        assert_eq!(
+            describe_relation(&outside),
+            "older than 2020-10-02 00:00:00 or younger than 2020-10-04 00:00:00"
+        );
+        assert_eq!(names(outside), vec!["day1", "day4", "day5"]);
+
+        // the order of the dates does not silently switch between both modes
+        assert!(range("2020-10-02T00:00:00", "2020-10-04T00:00:00", false).is_err());
+        assert!(range("2020-10-04T00:00:00", "2020-10-02T00:00:00", true).is_err());
+        // an empty range is an error as well
+        assert!(range("2020-10-02T00:00:00", "2020-10-02T00:00:00", false).is_err());
+        assert!(range("2020-10-02T00:00:00", "2020-10-02T00:00:00", true).is_err());
+        assert!(range("2020-10-02T00:00:00", "yesterday", false).is_err());
     }
 
     #[test]
-    #[should_panic(expected = "invalid time")]
-    fn parse_dates_panic2() {
-        assert!(parse_date(&String::from("24:30:24")).is_err());
+    #[cfg(unix)]
+    fn removes_whole_items() {
+        use crate::audit::Operation;
+        use std::fs;
+
+        let cargo_home = PathBuf::from("target/date_tests/removes_whole_items");
+        let _ = fs::remove_dir_all(&cargo_home);
+        let registry = "github.com-1ecc6299db9ec823";
+        let sources = cargo_home.join("registry").join("src").join(registry);
+        let archives = cargo_home.join("registry").join("cache").join(registry);
+        let index = cargo_home.join("registry").join("index").join(registry);
+        let bin = cargo_home.join("bin");
+        for dir in &[
+            sources.join("old-1.0.0").join("src"),
+            sources.join("mixed-1.0.0").join("src"),
+            archives.clone(),
+            index.join(".cache"),
+            bin.clone(),
+        ] {
+            fs::create_dir_all(dir).unwrap();
+        }
+        let old_files = [
+            sources.join("old-1.0.0").join("Cargo.toml"),
+            sources.join("old-1.0.0").join("src").join("lib.rs"),
+            sources.join("mixed-1.0.0").join("Cargo.toml"),
+            archives.join("old-1.0.0.crate"),
+            index.join(".cache").join("old"),
+            bin.join("old-tool"),
+            bin.join("rustc"),
+        ];
+        let new_files = [
+            sources.join("mixed-1.0.0").join("src").join("lib.rs"),
+            archives.join("new-1.0.0.crate"),
+        ];
+        for file in old_files.iter().chain(new_files.iter()) {
+            fs::write(file, "").unwrap();
+        }
+        let status = std::process::Command::new("touch")
+            .args(["-m", "-t", "202001010000"])
+            .args(&old_files)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        fs::write(
+            cargo_home.join(".crates2.json"),
+            r#"{"installs":{"old-tool 1.0.0 (registry+https://github.com/rust-lang/crates.io-index)":{"bins":["old-tool"]}}}"#,
+        )
+        .unwrap();
+
+        remove_files_by_dates(
+            &cargo_home,
+            &mut bin::BinaryCache::new(bin.clone()),
+            &mut git_checkouts::GitCheckoutCache::new(cargo_home.join("git").join("checkouts")),
+            &mut git_bare_repos::GitRepoCache::new(cargo_home.join("git").join("db")),
+            &mut registry_pkg_cache::RegistryPkgCaches::new(
+                cargo_home.join("registry").join("cache"),
+            ),
+            &mut registry_index::RegistryIndicesCache::new(
+                cargo_home.join("registry").join("index"),
+            ),
+            &mut registry_sources::RegistrySourceCaches::new(
+                cargo_home.join("registry").join("src"),
+            ),
+            &None,
+            &Some("2021-01-01"),
+            false,
+            TimeSource::Mtime,
+            false,
+            Confirm::Never,
+            &Some("registry,registry-index,bin"),
+            &mut false,
+            None,
+            &AuditLog::new(&cargo_home, Operation::Date),
+        )
+        .unwrap();
+
+        // only items that were not used at all since then are removed, and always completely
+        assert!(!sources.join("old-1.0.0").exists());
+        assert!(sources.join("mixed-1.0.0").join("Cargo.toml").exists());
+        assert!(!archives.join("old-1.0.0.crate").exists());
+        assert!(archives.join("new-1.0.0.crate").exists());
+        assert!(!index.exists());
+        // rustup proxies are protected, removed binaries are no longer installed for cargo
+        assert!(!bin.join("old-tool").exists());
+        assert!(bin.join("rustc").exists());
+        assert_eq!(
+            fs::read_to_string(cargo_home.join(".crates2.json")).unwrap(),
+            r#"{"installs":{}}"#
+        );
     }
 
     #[test]
-    #[should_panic(expected = "invalid time")]
-    fn parse_dates_panic3() {
+    fn parse_invalid_times() {
+        // these used to panic
+        assert!(parse_date(&String::from("24:00:00")).is_err());
+        assert!(parse_date(&String::from("24:30:24")).is_err());
         assert!(parse_date(&String::from("30:30:24")).is_err());
     }
+
+    #[test]
+    fn parse_iso_dates() {
+        assert_eq!(
+            parse_date("2002-01-01")
+                .unwrap()
+                .format("%Y.%m.%d")
+                .to_string(),
+            String::from("2002.01.01")
+        );
+        assert_eq!(
+            parse_date("2020-10-01T12:30:00").unwrap(),
+            NaiveDate::from_ymd(2020, 10, 1).and_hms(12, 30, 0)
+        );
+        assert_eq!(
+            parse_date("2020-10-01 12:30:00.5").unwrap(),
+            NaiveDate::from_ymd(2020, 10, 1).and_hms_milli(12, 30, 0, 500)
+        );
+
+        // timestamps with a timezone are converted to local time
+        let utc = Utc.ymd(2020, 10, 1).and_hms(10, 30, 0);
+        let local = utc.with_timezone(&Local).naive_local();
+        assert_eq!(parse_date("2020-10-01T10:30:00Z").unwrap(), local);
+        assert_eq!(parse_date("2020-10-01T12:30:00+02:00").unwrap(), local);
+        assert_eq!(parse_date("2020-10-01T12:30:00+0200").unwrap(), local);
+
+        assert!(parse_date("2020-13-01").is_err());
+        assert!(parse_date("2020-10-01T25:00:00").is_err());
+    }
+
+    #[test]
+    fn parse_relative_dates() {
+        let ago = |date: &str| {
+            let parsed = parse_date(date).unwrap();
+            Local::now().naive_local() - parsed
+        };
+
+        // allow some slack for slow test runs
+        let about = |duration: Duration, expected: Duration| {
+            duration >= expected && duration < expected + Duration::minutes(1)
+        };
+        assert!(about(ago("30d"), Duration::days(30)));
+        assert!(about(ago("30 days"), Duration::days(30)));
+        assert!(about(ago("2w"), Duration::weeks(2)));
+        assert!(about(ago("6months"), Duration::days(180)));
+        assert!(about(ago("1y"), Duration::days(365)));
+        assert!(about(ago("90min"), Duration::minutes(90)));
+        assert!(about(ago("12H"), Duration::hours(12)));
+
+        assert!(parse_date("99999999999999y").is_err());
+    }
+
+    #[test]
+    fn date_errors() {
+        let message = |date: &str| parse_date(date).unwrap_err().to_string();
+
+        assert_eq!(
+            message("2002.30.30"),
+            "ERROR failed to parse \"2002.30.30\" as date: expected a valid day in the format YYYY.MM.DD"
+        );
+        assert_eq!(
+            message("2002-02-30"),
+            "ERROR failed to parse \"2002-02-30\" as date: expected a valid day in the format YYYY-MM-DD"
+        );
+        assert_eq!(
+            message("24:00:00"),
+            "ERROR failed to parse \"24:00:00\" as date: expected a valid time in the format HH:MM:SS"
+        );
+        assert_eq!(
+            message("2020-10-01T12:00:00+25:00"),
+            "ERROR failed to parse \"2020-10-01T12:00:00+25:00\" as date: expected an ISO 8601 timestamp like 2020-10-01T12:00:00+02:00, 2020-10-01T12:00:00Z or 2020-10-01T12:00:00"
+        );
+        assert_eq!(
+            message("3m"),
+            "ERROR failed to parse \"3m\" as date: unknown unit \"m\" of relative date, expected one of s, min, h, d, w, months or y"
+        );
+        assert_eq!(
+            message("yesterday"),
+            "ERROR failed to parse \"yesterday\" as date: expected YYYY.MM.DD, YYYY-MM-DD, HH:MM:SS, an ISO 8601 timestamp like 2020-10-01T12:00:00+02:00 or a relative date like 30d, 2w or 6months"
+        );
+    }
 }
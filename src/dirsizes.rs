@@ -19,6 +19,7 @@ use crate::cache::caches::RegistrySuperCache;
 use crate::cache::*;
 use crate::library::*;
 use crate::tables::*;
+use crate::trash::trash_dir;
 
 use humansize::{file_size_opts, FileSize};
 
@@ -55,6 +56,8 @@ pub(crate) struct DirSizes<'a> {
     numb_reg_cache_entries: usize,
     /// number of registry source checkouts// @TODO clarify
     numb_reg_src_checkouts: usize,
+    /// total size of the items that were quarantined but not removed yet
+    total_trash_size: u64,
     /// root path of the cache
     root_path: &'a std::path::PathBuf,
 }
@@ -119,7 +122,10 @@ impl<'a> DirSizes<'a> {
 
         let total_bin_size = bin_dir_size.unwrap();
 
-        let total_size = total_reg_size + total_git_db_size + total_bin_size;
+        // quarantined items still take up space inside the cargo home
+        let total_trash_size = cumulative_dir_size(&trash_dir(root_path)).dir_size;
+
+        let total_size = total_reg_size + total_git_db_size + total_bin_size + total_trash_size;
         Self {
             total_size,                    // total size of cargo root dir
             numb_bins: numb_bins.unwrap(), // number of binaries found
@@ -136,6 +142,7 @@ impl<'a> DirSizes<'a> {
             total_reg_index_num: registry_index_caches.number_of_subcaches() as u64, // number  of indices //@TODO parallelize like the rest
             numb_reg_cache_entries: total_reg_cache_entries.unwrap(), // number of source archives
             numb_reg_src_checkouts: numb_reg_src_checkouts.unwrap(),  // number of source checkouts
+            total_trash_size,                                         // size of the trash
             root_path,
         }
     }
@@ -186,6 +193,9 @@ impl<'a> DirSizes<'a> {
     pub(crate) fn numb_reg_src_checkouts(&self) -> usize {
         self.numb_reg_src_checkouts
     }
+    pub(crate) fn total_trash_size(&self) -> u64 {
+        self.total_trash_size
+    }
     pub(crate) fn root_path(&self) -> &'a std::path::PathBuf {
         self.root_path
     }
@@ -253,6 +263,21 @@ impl<'a> DirSizes<'a> {
         ]
     }
 
+    /// returns the size of the trash, nothing if it is empty
+    fn trash(&self) -> Vec<TableLine> {
+        if self.total_trash_size() == 0 {
+            return Vec::new();
+        }
+        vec![TableLine::new(
+            1,
+            &"Trash (cargo cache empty-trash): ".to_string(),
+            &self
+                .total_trash_size()
+                .file_size(file_size_opts::DECIMAL)
+                .unwrap(),
+        )]
+    }
+
     /// returns summary of sizes of registry indices and registries (both, .crate archives and the extracted sources)
     fn registries_summary(&self) -> Vec<TableLine> {
         let tl1 = TableLine::new(
@@ -441,6 +466,7 @@ impl<'a> fmt::Display for DirSizes<'a> {
         table.extend(self.bin());
         table.extend(self.registries_summary());
         table.extend(self.git());
+        table.extend(self.trash());
 
         let string: String = two_row_table(2, table, false);
 
@@ -465,6 +491,7 @@ pub(crate) fn per_registry_summary(
         &mut registry_sources,
     ));
     table.extend(dir_size.git());
+    table.extend(dir_size.trash());
 
     two_row_table(2, table, false)
 }
@@ -520,6 +547,7 @@ mod libtests {
 
                 total_reg_index_size: reg_index.dir_size,
                 total_reg_index_num: 1,
+                total_trash_size: 0,
                 root_path: path,
             }
         }
@@ -584,6 +612,37 @@ Total:                                    1.94 GB
         assert_eq!(output_is, output_should);
     }
 
+    #[allow(non_snake_case)]
+    #[test]
+    fn test_DirSizes_trash() {
+        let dir = DirInfo {
+            dir_size: 1_000,
+            file_number: 1,
+        };
+        let pb = PathBuf::from("/home/user/.cargo");
+
+        let mut dirSizes = DirSizes::new_manually(&dir, &dir, &dir, &dir, &dir, &dir, &pb);
+        dirSizes.total_trash_size = 2_000;
+        dirSizes.total_size += 2_000;
+
+        let output_is = format!("{}", dirSizes);
+
+        let output_should = "Cargo cache '/home/user/.cargo':
+
+Total:                                 8 KB
+  1 installed binaries:                1 KB
+  Registry:                            3 KB
+    Registry index:                    1 KB
+    1 crate archives:                  1 KB
+    1 crate source checkouts:          1 KB
+  Git db:                              2 KB
+    1 bare git repos:                  1 KB
+    1 git repo checkouts:              1 KB
+  Trash (cargo cache empty-trash):     2 KB\n";
+
+        assert_eq!(output_is, output_should);
+    }
+
     #[allow(non_snake_case)]
     #[test]
     fn test_DirSizes_gigs() {
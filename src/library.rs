@@ -88,10 +88,66 @@ pub(crate) enum Error {
     LocalNoTargetDir(PathBuf),
     // failed to parse date given to younger or older
     DateParseFailure(String, String),
+    // the dates given to older and younger leave no items to select: older, younger, outside of the range
+    DateRangeEmpty(String, String, bool),
     // cargo metadata failed to parse a cargo manifest
     UnparsableManifest(PathBuf, cargo_metadata::Error),
     // could not find sccache cache dir
     NoSccacheDir,
+    // failed to move an item into or out of the trash
+    TrashIo(PathBuf, std::io::Error),
+    // the manifest of a trashed operation could not be parsed
+    TrashManifestCorrupt(PathBuf),
+    // "undo" was called but there is nothing in the trash
+    TrashEmpty,
+    // failed to read the audit log
+    AuditLogIo(PathBuf, std::io::Error),
+    // a line of the audit log could not be parsed
+    AuditLogCorrupt(PathBuf, usize, serde_json::Error),
+    // "log --operation" got an unknown operation
+    AuditLogUnknownOperation(String),
+    // failed to read or write a backup archive
+    ArchiveIo(PathBuf, std::io::Error),
+    // the archive was not created by "cargo cache backup"
+    ArchiveNoManifest(PathBuf),
+    // the manifest inside the archive could not be parsed
+    ArchiveManifestCorrupt(PathBuf, serde_json::Error),
+    // the source passed to "import" is neither a cargo home nor an archive
+    ImportNoCargoHome(PathBuf),
+    // failed to read the file passed via --manifest-list
+    ManifestListIo(PathBuf, std::io::Error),
+    // --scan did not find a single Cargo.toml
+    ScanNoManifests(PathBuf),
+    // --offline could not find the Cargo.lock of a manifest
+    NoLockfile(PathBuf),
+    // failed to read a Cargo.lock
+    LockfileIo(PathBuf, std::io::Error),
+    // failed to parse a Cargo.lock
+    UnparsableLockfile(PathBuf, toml::de::Error),
+    // neither the Cargo.lock nor the registry index know the checksum of a crate
+    NoChecksum(String, String),
+    // a manifest inside git/checkouts is not inside a <repo>/<rev> checkout
+    MalformedGitCheckoutPath(PathBuf),
+    // a manifest inside registry/src is not inside a <registry>/<crate> source
+    MalformedRegistrySourcePath(PathBuf),
+    // a manifest inside the cargo home is neither a git checkout nor a registry source
+    UnknownSourcePath(PathBuf),
+    // failed to read cargo's .crates2.json or .crates.toml
+    UnparsableInstallMetadata(PathBuf, String),
+    // failed to write cargo's .crates2.json or .crates.toml
+    InstallMetadataIo(PathBuf, std::io::Error),
+    // failed to read the config file
+    ConfigIo(PathBuf, std::io::Error),
+    // failed to parse the config file
+    UnparsableConfig(PathBuf, toml::de::Error),
+    // failed to set up or draw the terminal ui
+    #[cfg(feature = "tui")]
+    TerminalIo(String),
+    // the terminal ui needs an interactive terminal
+    #[cfg(feature = "tui")]
+    NoTerminal,
+    // the user did not confirm the removal
+    RemovalAborted,
 }
 
 impl fmt::Display for Error {
@@ -180,13 +236,73 @@ impl fmt::Display for Error {
                 path.display()
             ),
             Self::DateParseFailure(date, error) => write!(
-                f, "ERROR failed to parse {} as date {}",
+                f, "ERROR failed to parse \"{}\" as date: {}",
                 date, error
             ),
+            Self::DateRangeEmpty(older, younger, true) => write!(f,
+                "ERROR: with \"--outside-range\" the date of --remove-if-older-than (\"{}\") has to be earlier than the date of --remove-if-younger-than (\"{}\")",
+                older, younger
+            ),
+            Self::DateRangeEmpty(older, younger, false) => write!(f,
+                "ERROR: no item can be younger than \"{}\" and older than \"{}\", pass \"--outside-range\" to remove the items outside of these dates",
+                younger, older
+            ),
             Self::UnparsableManifest(path, error) => write!(f,
             "Failed to parse Cargo.toml at '{}': '{:?}'", path.display(), error),
             Self::NoSccacheDir => write!(f,
                 "Could not find sccache cache directory at ~/.cache/sccache or ${{SCCACHE_DIR}}"),
+            Self::TrashIo(path, error) => write!(f,
+                "Failed to move '{}' to or from the trash: '{:?}'", path.display(), error),
+            Self::TrashManifestCorrupt(path) => write!(f,
+                "Failed to parse trash manifest at '{}'", path.display()),
+            Self::TrashEmpty => write!(f, "Nothing to undo, the trash is empty"),
+            Self::AuditLogIo(path, error) => write!(f,
+                "Failed to read audit log at '{}': '{:?}'", path.display(), error),
+            Self::AuditLogCorrupt(path, line, error) => write!(f,
+                "Failed to parse line {} of audit log '{}': '{}'", line, path.display(), error),
+            Self::AuditLogUnknownOperation(op) => write!(f,
+                "Unknown operation \"{}\", chose one of {}", op, crate::audit::Operation::names().join(",")),
+            Self::ArchiveIo(path, error) => write!(f,
+                "Failed to read or write archive '{}': '{:?}'", path.display(), error),
+            Self::ArchiveNoManifest(path) => write!(f,
+                "'{}' contains no cargo-cache manifest, was it created by \"cargo cache backup\"?", path.display()),
+            Self::ArchiveManifestCorrupt(path, error) => write!(f,
+                "Failed to parse the manifest of archive '{}': '{}'", path.display(), error),
+            Self::ManifestListIo(path, error) => write!(f,
+                "Failed to read manifest list '{}': '{:?}'", path.display(), error),
+            Self::ScanNoManifests(path) => write!(f,
+                "Could not find any Cargo.toml below '{}'", path.display()),
+            Self::NoLockfile(path) => write!(f,
+                "Could not find a Cargo.lock for '{}', run \"cargo generate-lockfile\" first", path.display()),
+            Self::LockfileIo(path, error) => write!(f,
+                "Failed to read lockfile '{}': '{:?}'", path.display(), error),
+            Self::UnparsableLockfile(path, error) => write!(f,
+                "Failed to parse lockfile '{}': '{}'", path.display(), error),
+            Self::NoChecksum(name, version) => write!(f,
+                "Could not find the checksum of '{} {}' in the Cargo.lock or the registry index, run \"cargo fetch\" first", name, version),
+            Self::MalformedGitCheckoutPath(path) => write!(f,
+                "Failed to find the git checkout of '{}'", path.display()),
+            Self::MalformedRegistrySourcePath(path) => write!(f,
+                "Failed to find the registry source of '{}'", path.display()),
+            Self::UnknownSourcePath(path) => write!(f,
+                "'{}' is neither a git checkout nor a registry source", path.display()),
+            Self::UnparsableInstallMetadata(path, error) => write!(f,
+                "Failed to parse install metadata '{}': '{}'", path.display(), error),
+            Self::InstallMetadataIo(path, error) => write!(f,
+                "Failed to update install metadata '{}': '{:?}'", path.display(), error),
+            Self::ConfigIo(path, error) => write!(f,
+                "Failed to read config file '{}': '{:?}'", path.display(), error),
+            Self::UnparsableConfig(path, error) => write!(f,
+                "Failed to parse config file '{}': '{}'", path.display(), error),
+            #[cfg(feature = "tui")]
+            Self::TerminalIo(error) => write!(f,
+                "Failed to draw the terminal ui: '{}'", error),
+            #[cfg(feature = "tui")]
+            Self::NoTerminal => write!(f,
+                "The terminal ui needs an interactive terminal, stdin or stdout is not a terminal."),
+            Self::RemovalAborted => write!(f, "Aborted, nothing was removed."),
+            Self::ImportNoCargoHome(path) => write!(f,
+                "'{}' is neither a cargo home nor an archive created by \"cargo cache backup\" or \"cargo cache export\"", path.display()),
         }
     }
 }
@@ -203,6 +319,12 @@ impl CargoCachePaths {
         if !cargo_home.is_dir() {
             return Err(Error::CargoHomeNotDirectory(cargo_home));
         }
+
+        Ok(Self::from_cargo_home(cargo_home))
+    }
+
+    /// returns the subpaths of an arbitrary cargo home, for example one that is about to be imported
+    pub(crate) fn from_cargo_home(cargo_home: PathBuf) -> Self {
         // get the paths to the relevant directories
         let bin = cargo_home.join("bin");
         let registry = cargo_home.join("registry");
@@ -212,7 +334,7 @@ impl CargoCachePaths {
         let git_repos_bare = cargo_home.join("git").join("db");
         let git_checkouts = cargo_home.join("git").join("checkouts");
 
-        Ok(Self {
+        Self {
             cargo_home,
             bin_dir: bin,
             registry,
@@ -221,7 +343,7 @@ impl CargoCachePaths {
             registry_sources: reg_src,
             git_repos_bare,
             git_checkouts,
-        })
+        }
     }
 } // impl CargoCachePaths
 
@@ -279,6 +401,7 @@ pub(crate) enum RemovableGroup {
     RegistryCrateCache,
     RegistryIndex,
     Registry,
+    Bin,
 }
 
 impl std::str::FromStr for RemovableGroup {
@@ -293,6 +416,7 @@ impl std::str::FromStr for RemovableGroup {
             "registry-crate-cache" => Ok(RemovableGroup::RegistryCrateCache),
             "registry-index" => Ok(RemovableGroup::RegistryIndex),
             "registry" => Ok(RemovableGroup::Registry),
+            "bin" => Ok(RemovableGroup::Bin),
             other => Err(other.to_string()),
         }
     }
@@ -300,13 +424,92 @@ impl std::str::FromStr for RemovableGroup {
 
 // these are the actual atomic components of the cache
 // we have to map the RemovableGroups to the Components, deduplicate and finally remove them
-#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd)]
+// the installed binaries are not a cache, "all" never includes them, they have to be named explicitly
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub(crate) enum Component {
     GitDB,              // git/db
     GitRepos,           // git/checkouts
     RegistrySources,    // registry/src
     RegistryCrateCache, // registry/cache
     RegistryIndex,      // registry/index
+    Bin,                // bin
+}
+
+impl fmt::Display for Component {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::GitDB => "git-db",
+            Self::GitRepos => "git-repos",
+            Self::RegistrySources => "registry-sources",
+            Self::RegistryCrateCache => "registry-crate-cache",
+            Self::RegistryIndex => "registry-index",
+            Self::Bin => "bin",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// parse a single component, unlike RemovableGroups, these are never expanded
+impl std::str::FromStr for Component {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "git-db" => Ok(Self::GitDB),
+            "git-repos" => Ok(Self::GitRepos),
+            "registry-sources" => Ok(Self::RegistrySources),
+            "registry-crate-cache" => Ok(Self::RegistryCrateCache),
+            "registry-index" => Ok(Self::RegistryIndex),
+            "bin" => Ok(Self::Bin),
+            other => Err(other.to_string()),
+        }
+    }
+}
+
+impl CargoCachePaths {
+    /// get the root directory of a component
+    pub(crate) fn component_path(&self, component: Component) -> &PathBuf {
+        match component {
+            Component::GitDB => &self.git_repos_bare,
+            Component::GitRepos => &self.git_checkouts,
+            Component::RegistrySources => &self.registry_sources,
+            Component::RegistryCrateCache => &self.registry_pkg_cache,
+            Component::RegistryIndex => &self.registry_index,
+            Component::Bin => &self.bin_dir,
+        }
+    }
+}
+
+/// parse a comma separated list of component names ("git-db,registry-index")
+/// "all" selects every component
+pub(crate) fn components_from_names(input: &str) -> Result<Vec<Component>, Error> {
+    let mut components = Vec::new();
+    let mut invalid = Vec::new();
+
+    for name in input.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+        if name == "all" {
+            components.extend(vec![
+                Component::GitDB,
+                Component::GitRepos,
+                Component::RegistrySources,
+                Component::RegistryCrateCache,
+                Component::RegistryIndex,
+            ]);
+            continue;
+        }
+        match name.parse::<Component>() {
+            Ok(component) => components.push(component),
+            Err(unknown) => invalid.push(unknown),
+        }
+    }
+
+    if !invalid.is_empty() {
+        return Err(Error::InvalidDeletableDirs(invalid.join(" ")));
+    }
+
+    components.sort();
+    components.dedup();
+    Ok(components)
 }
 
 // map a String to a list of RemovableGroups to actual Components
@@ -386,6 +589,9 @@ pub(crate) fn components_from_groups(input: &Option<&str>) -> Result<Vec<Compone
             Component::RegistrySources,
             Component::RegistryCrateCache,
         ]),
+        RemovableGroup::Bin => {
+            mapped_dirs.push(Component::Bin);
+        }
     });
 
     // remove duplicates
@@ -902,6 +1108,18 @@ mod libtests {
         let last = iter.next();
         assert!(!last.is_some(), "found another directory?!: '{:?}'", last);
     }
+
+    #[test]
+    fn bin_is_not_part_of_all() {
+        let all = components_from_groups(&Some("all")).unwrap();
+        assert!(!all.contains(&Component::Bin));
+        assert_eq!(all, components_from_names("all").unwrap());
+
+        assert_eq!(
+            components_from_groups(&Some("bin,git-repos")).unwrap(),
+            vec![Component::GitRepos, Component::Bin]
+        );
+    }
 }
 
 #[cfg(all(test, feature = "bench"))]
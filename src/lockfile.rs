@@ -0,0 +1,412 @@
+// Copyright 2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// offline dependency resolution: instead of asking "cargo metadata", read the Cargo.lock
+// directly and map its sources onto the items inside the ${CARGO_HOME}.
+// This works without a toolchain and without network access.
+//
+// registry+https://github.com/rust-lang/crates.io-index, serde 1.0.116
+//     => registry/cache/github.com-1ecc6299db9ec823/serde-1.0.116.crate
+// git+https://github.com/rust-lang/cargo?branch=master#258c896...
+//     => git/db/cargo-e7ff1db891893a9e
+//
+// The hash suffixes of the directories depend on cargo internals, so we match the
+// directories by their prefix (host of the registry, name of the git repo) instead.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::clean_unref::{index_cache_entry, RequiredItems, RequiredPackage};
+use crate::library::*;
+
+use serde::Deserialize;
+
+// crates.io can live in a git index (github.com-...) or in a sparse index (index.crates.io-...)
+const CRATES_IO_SOURCES: [&str; 2] = [
+    "registry+https://github.com/rust-lang/crates.io-index",
+    "sparse+https://index.crates.io/",
+];
+const CRATES_IO_PREFIXES: [&str; 2] = ["github.com-", "index.crates.io-"];
+
+/// the parts of a Cargo.lock we care about
+#[derive(Debug, Deserialize)]
+struct Lockfile {
+    #[serde(default)]
+    package: Vec<LockedPackage>,
+    /// lockfiles of version 1 store the checksums here:
+    /// "checksum <name> <version> (<source>)" = "<checksum>"
+    #[serde(default)]
+    metadata: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct LockedPackage {
+    pub(crate) name: String,
+    pub(crate) version: String,
+    /// None for path dependencies and workspace members
+    pub(crate) source: Option<String>,
+    pub(crate) checksum: Option<String>,
+}
+
+/// find the Cargo.lock belonging to a manifest, workspace members don't have their own
+/// lockfile so we walk upwards until we find one
+pub(crate) fn lockfile_of_manifest(manifest: &Path) -> Result<PathBuf, Error> {
+    let mut dir = manifest.parent();
+    while let Some(current) = dir {
+        let lockfile = current.join("Cargo.lock");
+        if lockfile.is_file() {
+            return Ok(lockfile);
+        }
+        dir = current.parent();
+    }
+    Err(Error::NoLockfile(manifest.to_path_buf()))
+}
+
+/// names of the subdirectories of a directory
+pub(crate) fn dir_names(dir: &Path) -> Vec<String> {
+    let mut names = match fs::read_dir(dir) {
+        Ok(read_dir) => read_dir
+            .filter_map(Result::ok)
+            .filter(|entry| entry.path().is_dir())
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .collect::<Vec<String>>(),
+        Err(_) => Vec::new(),
+    };
+    names.sort();
+    names
+}
+
+/// `registry+https://my-registry.example.com/index` => `my-registry.example.com-`
+pub(crate) fn registry_prefixes(source: &str) -> Vec<String> {
+    if CRATES_IO_SOURCES.contains(&source) {
+        return CRATES_IO_PREFIXES
+            .iter()
+            .map(|p| (*p).to_string())
+            .collect();
+    }
+    let url = source.find('+').map_or(source, |pos| &source[pos + 1..]);
+    let without_scheme = url.find("://").map_or(url, |pos| &url[pos + 3..]);
+    let host = without_scheme.split(&['/', ':'][..]).next().unwrap_or("");
+    vec![format!("{}-", host)]
+}
+
+/// `git+https://github.com/rust-lang/cargo.git?branch=master#258c896` => `cargo`
+pub(crate) fn git_repo_name(source: &str) -> String {
+    let url = source.trim_start_matches("git+");
+    let without_query = url.split(&['#', '?'][..]).next().unwrap_or(url);
+    let last_segment = without_query
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or("");
+    let name = last_segment.trim_end_matches(".git");
+    if name.is_empty() {
+        // this is what cargo names repos that have no path
+        "_empty".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// cargo appends a 16 digit hex hash to the names of its directories
+pub(crate) fn has_hash_suffix(dir_name: &str, prefix: &str) -> bool {
+    dir_name.starts_with(prefix)
+        && dir_name[prefix.len()..].len() == 16
+        && dir_name[prefix.len()..]
+            .chars()
+            .all(|c| c.is_ascii_hexdigit())
+}
+
+/// parse a lockfile and get all packages together with their checksums
+pub(crate) fn read_lockfile(path: &Path) -> Result<Vec<LockedPackage>, Error> {
+    let content = fs::read_to_string(path).map_err(|e| Error::LockfileIo(path.to_path_buf(), e))?;
+    parse_lockfile(&content, path)
+}
+
+/// parse the content of a lockfile, the path is only used for error messages
+pub(crate) fn parse_lockfile(content: &str, path: &Path) -> Result<Vec<LockedPackage>, Error> {
+    let lockfile: Lockfile =
+        toml::from_str(content).map_err(|e| Error::UnparsableLockfile(path.to_path_buf(), e))?;
+
+    let mut packages = lockfile.package;
+    // version 1 lockfiles: pick up the checksums from the metadata table
+    for package in &mut packages {
+        if package.checksum.is_none() {
+            if let Some(source) = &package.source {
+                let key = format!("checksum {} {} ({})", package.name, package.version, source);
+                package.checksum = lockfile.metadata.get(&key).cloned();
+            }
+        }
+    }
+    Ok(packages)
+}
+
+/// map the packages of several lockfiles onto the items inside the cargo home
+pub(crate) fn required_items_from_lockfiles(
+    cargo_cache_paths: &CargoCachePaths,
+    lockfiles: &[PathBuf],
+) -> Result<RequiredItems, Error> {
+    let mut packages = Vec::new();
+    for lockfile in lockfiles {
+        packages.extend(
+            read_lockfile(lockfile)?
+                .into_iter()
+                .map(|package| (lockfile.clone(), package)),
+        );
+    }
+    Ok(required_items_from_packages(cargo_cache_paths, packages))
+}
+
+/// map locked packages onto the items inside the cargo home,
+/// the path next to each package is the lockfile it came from
+pub(crate) fn required_items_from_packages(
+    cargo_cache_paths: &CargoCachePaths,
+    packages: Vec<(PathBuf, LockedPackage)>,
+) -> RequiredItems {
+    let registries = {
+        let mut names = dir_names(&cargo_cache_paths.registry_pkg_cache);
+        names.extend(dir_names(&cargo_cache_paths.registry_index));
+        names.extend(dir_names(&cargo_cache_paths.registry_sources));
+        names.sort();
+        names.dedup();
+        names
+    };
+    let git_dbs = dir_names(&cargo_cache_paths.git_repos_bare);
+    let git_checkouts = dir_names(&cargo_cache_paths.git_checkouts);
+
+    let mut required = RequiredItems::default();
+
+    // path dependencies have no source, there is nothing inside the cargo home for them
+    let with_source = packages.into_iter().filter_map(|(lockfile, mut package)| {
+        package
+            .source
+            .take()
+            .map(|source| (lockfile, package, source))
+    });
+
+    for (lockfile, package, source) in with_source {
+        let paths: Vec<PathBuf> = if source.starts_with("registry+")
+            || source.starts_with("sparse+")
+        {
+            let prefixes = registry_prefixes(&source);
+            let matching = registries
+                .iter()
+                .filter(|dir| prefixes.iter().any(|prefix| has_hash_suffix(dir, prefix)));
+
+            let mut crates = Vec::new();
+            for registry in matching {
+                crates.push(
+                    cargo_cache_paths
+                        .registry_pkg_cache
+                        .join(registry)
+                        .join(format!("{}-{}.crate", package.name, package.version)),
+                );
+                required.sources.push(
+                    cargo_cache_paths
+                        .registry_sources
+                        .join(registry)
+                        .join(format!("{}-{}", package.name, package.version)),
+                );
+                required.index_entries.push(index_cache_entry(
+                    &cargo_cache_paths.registry_index.join(registry),
+                    &package.name,
+                ));
+            }
+            required.crates.extend(crates.iter().cloned());
+            crates
+        } else if source.starts_with("git+") {
+            let prefix = format!("{}-", git_repo_name(&source));
+            let repos: Vec<PathBuf> = git_dbs
+                .iter()
+                .filter(|dir| has_hash_suffix(dir, &prefix))
+                .map(|dir| cargo_cache_paths.git_repos_bare.join(dir))
+                .collect();
+            required.git_repos.extend(repos.iter().cloned());
+            // checkouts are named after the (abbreviated) revision: checkouts/cargo-<hash>/258c896
+            let revision = source.rfind('#').map_or("", |pos| &source[pos + 1..]);
+            for checkout_repo in git_checkouts
+                .iter()
+                .filter(|dir| has_hash_suffix(dir, &prefix))
+            {
+                let repo_dir = cargo_cache_paths.git_checkouts.join(checkout_repo);
+                required.checkouts.extend(
+                    dir_names(&repo_dir)
+                        .into_iter()
+                        .filter(|rev| !revision.is_empty() && revision.starts_with(rev.as_str()))
+                        .map(|rev| repo_dir.join(rev)),
+                );
+            }
+            repos
+        } else {
+            eprintln!(
+                "Warning: unknown source \"{}\" of {} {} in '{}'",
+                source,
+                package.name,
+                package.version,
+                lockfile.display()
+            );
+            continue;
+        };
+
+        required.packages.push(RequiredPackage {
+            name: package.name,
+            version: package.version,
+            source,
+            // if the item is not in the cache, we still want to know that it is needed
+            path: paths
+                .into_iter()
+                .next()
+                .unwrap_or_else(|| cargo_cache_paths.cargo_home.clone()),
+            checksum: package.checksum,
+        });
+    }
+
+    required.packages.sort();
+    required.packages.dedup();
+    required.crates.sort();
+    required.crates.dedup();
+    required.git_repos.sort();
+    required.git_repos.dedup();
+    required.sources.sort();
+    required.sources.dedup();
+    required.checkouts.sort();
+    required.checkouts.dedup();
+    required.index_entries.sort();
+    required.index_entries.dedup();
+
+    required
+}
+
+#[cfg(test)]
+mod lockfiletests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn prefixes_of_registries() {
+        assert_eq!(
+            registry_prefixes("registry+https://github.com/rust-lang/crates.io-index"),
+            vec!["github.com-", "index.crates.io-"]
+        );
+        assert_eq!(
+            registry_prefixes("registry+https://my-registry.example.com:8080/git/index"),
+            vec!["my-registry.example.com-"]
+        );
+        assert_eq!(
+            registry_prefixes("sparse+https://cargo.example.com/index/"),
+            vec!["cargo.example.com-"]
+        );
+    }
+
+    #[test]
+    fn names_of_git_repos() {
+        assert_eq!(
+            git_repo_name("git+https://github.com/rust-lang/cargo?branch=master#258c896"),
+            "cargo"
+        );
+        assert_eq!(
+            git_repo_name("git+https://github.com/rust-lang/cargo.git#258c896"),
+            "cargo"
+        );
+        assert_eq!(
+            git_repo_name("git+https://example.com/foo/bar/?rev=1234#1234"),
+            "bar"
+        );
+    }
+
+    #[test]
+    fn hash_suffix() {
+        assert!(has_hash_suffix("cargo-e7ff1db891893a9e", "cargo-"));
+        assert!(!has_hash_suffix("cargo-foo-e7ff1db891893a9e", "cargo-"));
+        assert!(!has_hash_suffix("cargo-e7ff1db8", "cargo-"));
+    }
+
+    #[test]
+    fn map_lockfile_to_cache() {
+        let root = PathBuf::from("target/lockfile_tests/map_lockfile_to_cache");
+        let _ = fs::remove_dir_all(&root);
+        let ccp = CargoCachePaths::from_cargo_home(root.join("cargo_home"));
+        fs::create_dir_all(ccp.registry_pkg_cache.join("github.com-1ecc6299db9ec823")).unwrap();
+        fs::create_dir_all(ccp.git_repos_bare.join("cargo-e7ff1db891893a9e")).unwrap();
+        fs::create_dir_all(ccp.git_repos_bare.join("cargo-foo-0123456789abcdef")).unwrap();
+        fs::create_dir_all(
+            ccp.git_checkouts
+                .join("cargo-e7ff1db891893a9e")
+                .join("258c896"),
+        )
+        .unwrap();
+        fs::create_dir_all(
+            ccp.git_checkouts
+                .join("cargo-e7ff1db891893a9e")
+                .join("1234567"),
+        )
+        .unwrap();
+        fs::create_dir_all(ccp.registry_sources.join("github.com-1ecc6299db9ec823")).unwrap();
+
+        let lockfile = root.join("Cargo.lock");
+        fs::write(
+            &lockfile,
+            r#"
+[[package]]
+name = "mycrate"
+version = "0.1.0"
+dependencies = ["serde", "cargo"]
+
+[[package]]
+name = "serde"
+version = "1.0.116"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "cargo"
+version = "0.48.0"
+source = "git+https://github.com/rust-lang/cargo?branch=master#258c896"
+
+[metadata]
+"checksum serde 1.0.116 (registry+https://github.com/rust-lang/crates.io-index)" = "abcd"
+"#,
+        )
+        .unwrap();
+
+        let required = required_items_from_lockfiles(&ccp, &[lockfile]).unwrap();
+        assert_eq!(
+            required.crates,
+            vec![ccp
+                .registry_pkg_cache
+                .join("github.com-1ecc6299db9ec823")
+                .join("serde-1.0.116.crate")]
+        );
+        assert_eq!(
+            required.git_repos,
+            vec![ccp.git_repos_bare.join("cargo-e7ff1db891893a9e")]
+        );
+        assert_eq!(
+            required.sources,
+            vec![ccp
+                .registry_sources
+                .join("github.com-1ecc6299db9ec823")
+                .join("serde-1.0.116")]
+        );
+        assert_eq!(
+            required.checkouts,
+            vec![ccp
+                .git_checkouts
+                .join("cargo-e7ff1db891893a9e")
+                .join("258c896")]
+        );
+        assert_eq!(required.packages.len(), 2);
+        let serde = required
+            .packages
+            .iter()
+            .find(|p| p.name == "serde")
+            .unwrap();
+        assert_eq!(serde.checksum, Some("abcd".to_string()));
+    }
+}
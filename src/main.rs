@@ -54,6 +54,7 @@
 #![allow(clippy::too_many_lines, clippy::unused_self)] // I don't care
 #![allow(clippy::wildcard_imports)] // breaks code, false positives
 #![allow(clippy::option_if_let_else)] // too pedantic, not that useful...
+#![allow(clippy::derivable_impls)] // #[default] on enum variants needs rust 1.62
 
 // for the "ci-autoclean" feature, we don't need all these modules so ignore them
 cfg_if::cfg_if! {
@@ -71,20 +72,35 @@ cfg_if::cfg_if! {
         mod top_items_summary;
         mod date;
         mod clean_unref;
+        mod lockfile;
+        mod trash;
+        mod audit;
+        mod config;
+        mod size;
+        mod timestamps;
+        mod confirm;
 
         // use
         use crate::cache::caches::{Cache, RegistrySuperCache};
         use clap::value_t;
+        use std::path::PathBuf;
         use std::process;
         use std::time::SystemTime;
         use walkdir::WalkDir;
         use crate::cache::*;
-        use crate::commands::{local, query, sccache, trim};
+        use crate::commands::{backup, export, fingerprint, import, local, query, sccache, trim, why};
+        #[cfg(feature = "tui")]
+        use crate::commands::tui;
         use crate::git::*;
         use crate::library::*;
         use crate::remove::*;
         use crate::top_items_summary::*;
         use crate::clean_unref::*;
+        use crate::trash::Trash;
+        use crate::audit::{AuditLog, Operation};
+        use crate::config::load_config;
+        use crate::timestamps::TimeSource;
+        use crate::confirm::{confirm_removal, Confirm, PlannedRemoval};
     }
 }
 
@@ -119,8 +135,13 @@ fn main() {
         None
     };
 
-    if config.is_present("sc") || config.is_present("sccache") {
-        match sccache::sccache_stats() {
+    if let Some(sccache_config) = config
+        .subcommand_matches("sc")
+        .or_else(|| config.subcommand_matches("sccache"))
+    {
+        let time_source =
+            value_t!(sccache_config.value_of("time-source"), TimeSource).unwrap_or_default();
+        match sccache::sccache_stats(time_source) {
             Ok(()) => {
                 process::exit(0);
             }
@@ -148,6 +169,149 @@ fn main() {
         process::exit(0);
     }
 
+    if let Some(log_cfg) = config.subcommand_matches("log") {
+        match audit::log_subcmd(&cargo_cache.cargo_home, log_cfg) {
+            Ok(()) => process::exit(0),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+    }
+
+    if let Some(undo_cfg) = config.subcommand_matches("undo") {
+        let dry_run = config.is_present("dry-run") || undo_cfg.is_present("dry-run");
+        match trash::undo(&cargo_cache, dry_run) {
+            Ok(()) => process::exit(0),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+    }
+
+    // ask before removing items, "[confirm] always = true" in the config file asks even if stdout is no terminal
+    // trim loads its own config (which may come from "--config") and builds the Confirm from that
+    let confirmation = |subcmd_cfg: Option<&clap::ArgMatches<'_>>, dry_run: bool| {
+        let subcmd_yes = match subcmd_cfg {
+            Some(cfg) => cfg.is_present("yes"),
+            None => false,
+        };
+        load_config(&cargo_cache.cargo_home, None).map(|settings| {
+            Confirm::new(
+                config.is_present("yes") || subcmd_yes,
+                dry_run,
+                &settings.confirm,
+            )
+        })
+    };
+
+    if let Some(empty_trash_cfg) = config.subcommand_matches("empty-trash") {
+        let dry_run = config.is_present("dry-run") || empty_trash_cfg.is_present("dry-run");
+        match confirmation(Some(empty_trash_cfg), dry_run).and_then(|confirm| {
+            trash::empty_trash(
+                &cargo_cache,
+                empty_trash_cfg.value_of("older-than"),
+                dry_run,
+                confirm,
+            )
+        }) {
+            Ok(()) => process::exit(0),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+    }
+
+    if let Some(backup_cfg) = config.subcommand_matches("backup") {
+        let archive = PathBuf::from(backup_cfg.value_of("ARCHIVE").unwrap());
+        match backup::backup(&cargo_cache, &archive, backup_cfg.value_of("components")) {
+            Ok(()) => process::exit(0),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+    }
+
+    if let Some(restore_cfg) = config.subcommand_matches("restore") {
+        let dry_run = config.is_present("dry-run") || restore_cfg.is_present("dry-run");
+        let archive = PathBuf::from(restore_cfg.value_of("ARCHIVE").unwrap());
+        match backup::restore(&cargo_cache, &archive, dry_run) {
+            Ok(()) => process::exit(0),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+    }
+
+    if let Some(export_cfg) = config.subcommand_matches("export") {
+        let dry_run = config.is_present("dry-run") || export_cfg.is_present("dry-run");
+        let target = PathBuf::from(export_cfg.value_of("TARGET").unwrap());
+        match manifests_from_cli(&cargo_cache.cargo_home, export_cfg).and_then(|manifests| {
+            export::export(
+                &cargo_cache,
+                &target,
+                &manifests,
+                export_cfg.is_present("offline"),
+                export_cfg.is_present("index-repo"),
+                dry_run,
+            )
+        }) {
+            Ok(()) => process::exit(0),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+    }
+
+    if let Some(import_cfg) = config.subcommand_matches("import") {
+        let dry_run = config.is_present("dry-run") || import_cfg.is_present("dry-run");
+        let source = PathBuf::from(import_cfg.value_of("SOURCE").unwrap());
+        match import::import(&cargo_cache, &source, dry_run) {
+            Ok(()) => process::exit(0),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+    }
+
+    if let Some(fingerprint_cfg) = config.subcommand_matches("fingerprint") {
+        match manifests_from_cli(&cargo_cache.cargo_home, fingerprint_cfg).and_then(|manifests| {
+            fingerprint::fingerprint(
+                &cargo_cache,
+                &manifests,
+                fingerprint_cfg.is_present("offline"),
+                fingerprint_cfg.is_present("list"),
+                fingerprint_cfg.is_present("no-toolchain"),
+            )
+        }) {
+            Ok(()) => process::exit(0),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+    }
+
+    // in quarantine mode, items are moved into the trash instead of being removed
+    let trash = Trash::new(&cargo_cache.cargo_home);
+    let quarantine = |subcmd_cfg: Option<&clap::ArgMatches<'_>>| {
+        let subcmd_quarantine = match subcmd_cfg {
+            Some(cfg) => cfg.is_present("quarantine"),
+            None => false,
+        };
+        if config.is_present("quarantine") || subcmd_quarantine {
+            Some(&trash)
+        } else {
+            None
+        }
+    };
+
     // create cache
     let p = CargoCachePaths::default().unwrap();
 
@@ -169,37 +333,128 @@ fn main() {
         registry_index::RegistryIndicesCache::new(p2.registry_index);
 
     if let Some(trim_config) = config.subcommand_matches("trim") {
-        match trim::trim_cache(
-            &trim_config.value_of("trim_limit"),
+        let settings = match load_config(&cargo_cache.cargo_home, trim_config.value_of("config")) {
+            Ok(settings) => settings,
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        };
+        let dry_run = config.is_present("dry-run") || trim_config.is_present("dry-run");
+        // the config is already loaded, don't read it a second time for the confirmation
+        let confirm = Confirm::new(
+            config.is_present("yes") || trim_config.is_present("yes"),
+            dry_run,
+            &settings.confirm,
+        );
+        let quotas: Vec<&str> = trim_config
+            .values_of("quota")
+            .map(Iterator::collect)
+            .unwrap_or_default();
+
+        match trim::TrimLimits::new(
+            trim_config.value_of("trim_limit"),
+            &quotas,
+            trim_config.value_of("ensure-free"),
+            trim_config.value_of("strategy"),
+            &settings.trim,
+        )
+        .and_then(|limits| {
+            trim::trim_cache(
+                &cargo_cache.cargo_home,
+                &limits,
+                value_t!(trim_config.value_of("time-source"), TimeSource).unwrap_or_default(),
+                trim_config.is_present("remove-bins"),
+                &mut bin_cache,
+                &mut checkouts_cache,
+                &mut bare_repos_cache,
+                &mut registry_pkgs_cache,
+                &mut registry_sources_caches,
+                &mut registry_index_caches,
+                dry_run,
+                confirm,
+                trim_config.value_of("format") == Some("json"),
+                &mut size_changed,
+                quarantine(Some(trim_config)),
+                &AuditLog::new(&cargo_cache.cargo_home, Operation::Trim),
+            )
+        }) {
+            Ok(()) => {
+                trash.print_summary();
+                process::exit(0);
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+    }
+
+    #[cfg(feature = "tui")]
+    if let Some(tui_cfg) = config.subcommand_matches("tui") {
+        match tui::tui(
+            &cargo_cache,
+            value_t!(tui_cfg.value_of("time-source"), TimeSource).unwrap_or_default(),
+            &mut bin_cache,
             &mut checkouts_cache,
             &mut bare_repos_cache,
             &mut registry_pkgs_cache,
             &mut registry_sources_caches,
-            config.is_present("dry-run"),
+            &mut registry_index_caches,
             &mut size_changed,
+            quarantine(Some(tui_cfg)),
+            &AuditLog::new(&cargo_cache.cargo_home, Operation::Tui),
         ) {
-            Ok(_) => {
+            Ok(()) => {
+                trash.print_summary();
                 process::exit(0);
             }
             Err(e) => {
-                eprintln!("{:?}", e);
+                eprintln!("{}", e);
                 process::exit(1);
             }
         }
     }
 
-    if let Some(clean_unref_cfg) = config.subcommand_matches("clean-unref") {
-        match clean_unref(
+    if let Some(why_cfg) = config.subcommand_matches("why") {
+        match why::why(
             &cargo_cache,
-            &clean_unref_cfg.value_of("manifest-path"),
+            why_cfg,
             &mut checkouts_cache,
             &mut bare_repos_cache,
             &mut registry_pkgs_cache,
             &mut registry_sources_caches,
-            config.is_present("dry-run") || clean_unref_cfg.is_present("dry-run"),
-            &mut size_changed,
         ) {
+            Ok(()) => process::exit(0),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+    }
+
+    if let Some(clean_unref_cfg) = config.subcommand_matches("clean-unref") {
+        let dry_run = config.is_present("dry-run") || clean_unref_cfg.is_present("dry-run");
+        match confirmation(Some(clean_unref_cfg), dry_run).and_then(|confirm| {
+            let manifests = manifests_from_cli(&cargo_cache.cargo_home, clean_unref_cfg)?;
+            clean_unref(
+                &cargo_cache,
+                &manifests,
+                clean_unref_cfg.is_present("offline"),
+                clean_unref_cfg.is_present("aggressive"),
+                &mut checkouts_cache,
+                &mut bare_repos_cache,
+                &mut registry_pkgs_cache,
+                &mut registry_sources_caches,
+                dry_run,
+                confirm,
+                &mut size_changed,
+                quarantine(Some(clean_unref_cfg)),
+                &AuditLog::new(&cargo_cache.cargo_home, Operation::CleanUnref),
+            )
+        }) {
             Ok(_) => {
+                trash.print_summary();
                 process::exit(0);
             }
             Err(e) => {
@@ -252,15 +507,27 @@ fn main() {
             process::exit(0);
         }
     } else if config.is_present("local") || config.is_present("l") {
-        // this is not actually not needed and was previously passed into local_subcmd()
-        /*
         let local_config = if config.is_present("local") {
             config
                 .subcommand_matches("local")
                 .expect("unwrap failed here")
         } else {
             config.subcommand_matches("l").expect("unwrap failed there")
-        }; */
+        };
+
+        if let Some(dir) = local_config.value_of("scan") {
+            match local::scan_subcmd(
+                &PathBuf::from(dir),
+                &cargo_cache.cargo_home,
+                value_t!(local_config.value_of("sort"), local::TargetDirOrder).unwrap_or_default(),
+            ) {
+                Ok(()) => process::exit(0),
+                Err(error) => {
+                    eprintln!("{}", error);
+                    process::exit(1);
+                }
+            }
+        }
 
         match local::local_subcmd() {
             Ok(_) => {
@@ -285,24 +552,34 @@ fn main() {
     let dir_sizes_total = dir_sizes.total_size();
 
     if config.is_present("remove-if-younger-than") || config.is_present("remove-if-older-than") {
-        let res = crate::date::remove_files_by_dates(
-            &mut checkouts_cache,
-            &mut bare_repos_cache,
-            &mut registry_pkgs_cache,
-            /* &mut registry_index_cache, */
-            &mut registry_sources_caches,
-            &config.value_of("remove-if-younger-than"),
-            &config.value_of("remove-if-older-than"),
-            config.is_present("dry-run"),
-            &config.value_of("remove-dir"),
-            &mut size_changed,
-        );
+        let res = confirmation(None, config.is_present("dry-run")).and_then(|confirm| {
+            crate::date::remove_files_by_dates(
+                &cargo_cache.cargo_home,
+                &mut bin_cache,
+                &mut checkouts_cache,
+                &mut bare_repos_cache,
+                &mut registry_pkgs_cache,
+                &mut registry_index_caches,
+                &mut registry_sources_caches,
+                &config.value_of("remove-if-younger-than"),
+                &config.value_of("remove-if-older-than"),
+                config.is_present("outside-range"),
+                value_t!(config.value_of("time-source"), TimeSource).unwrap_or_default(),
+                config.is_present("dry-run"),
+                confirm,
+                &config.value_of("remove-dir"),
+                &mut size_changed,
+                quarantine(None),
+                &AuditLog::new(&cargo_cache.cargo_home, Operation::Date),
+            )
+        });
         match res {
             Err(error) => {
                 eprintln!("{}", error);
                 std::process::exit(1);
             }
             Ok(()) => {
+                trash.print_summary();
                 //@TODO we could perhaps optimize this by only querying the caches that changed
                 if !config.is_present("dry-run") {
                     print_size_changed_summary(
@@ -350,17 +627,23 @@ fn main() {
         && !(config.is_present("remove-if-younger-than")
             || config.is_present("remove-if-older-than"))
     {
-        if let Err(e) = remove_dir_via_cmdline(
-            config.value_of("remove-dir"),
-            config.is_present("dry-run"),
-            &cargo_cache,
-            &mut size_changed,
-            &mut checkouts_cache,
-            &mut bare_repos_cache,
-            &mut registry_index_caches,
-            &mut registry_pkgs_cache,
-            &mut registry_sources_caches,
-        ) {
+        if let Err(e) = confirmation(None, config.is_present("dry-run")).and_then(|confirm| {
+            remove_dir_via_cmdline(
+                config.value_of("remove-dir"),
+                config.is_present("dry-run"),
+                confirm,
+                &cargo_cache,
+                &mut size_changed,
+                &mut bin_cache,
+                &mut checkouts_cache,
+                &mut bare_repos_cache,
+                &mut registry_index_caches,
+                &mut registry_pkgs_cache,
+                &mut registry_sources_caches,
+                quarantine(None),
+                &AuditLog::new(&cargo_cache.cargo_home, Operation::RemoveDir),
+            )
+        }) {
             eprintln!("{}", e);
             process::exit(1);
         }
@@ -388,21 +671,41 @@ fn main() {
         let reg_srcs = &cargo_cache.registry_sources;
         let git_checkouts = &cargo_cache.git_checkouts;
 
+        let plan: Vec<PlannedRemoval> = [
+            (reg_srcs, Component::RegistrySources),
+            (git_checkouts, Component::GitRepos),
+        ]
+        .iter()
+        .filter(|(dir, _)| dir.is_dir())
+        .map(|(dir, component)| {
+            PlannedRemoval::new(
+                (*dir).clone(),
+                &component.to_string(),
+                cumulative_dir_size(dir).dir_size,
+            )
+        })
+        .collect();
+        if let Err(e) = confirmation(None, config.is_present("dry-run"))
+            .and_then(|confirm| confirm_removal(confirm, &plan))
+        {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+
         // depending on the size of the cache and the system (SSD, HDD...) this can take a few seconds.
         println!("\nClearing cache...");
 
-        for dir in &[reg_srcs, git_checkouts] {
-            let size = cumulative_dir_size(dir);
-            if dir.is_dir() {
-                remove_file(
-                    dir,
-                    config.is_present("dry-run"),
-                    &mut size_changed,
-                    None,
-                    &DryRunMessage::Default,
-                    Some(size.dir_size),
-                );
-            }
+        for item in &plan {
+            remove_file(
+                &item.path,
+                config.is_present("dry-run"),
+                &mut size_changed,
+                None,
+                &DryRunMessage::Default,
+                Some(item.size),
+                quarantine(None),
+                &AuditLog::new(&cargo_cache.cargo_home, Operation::Autoclean),
+            );
         }
     }
 
@@ -418,24 +721,28 @@ fn main() {
                 process::exit(1);
             }
         };
-        match rm_old_crates(
-            limit,
-            config.is_present("dry-run"),
-            &cargo_cache.registry_pkg_cache,
-            &mut size_changed,
-        ) {
+        match confirmation(None, config.is_present("dry-run")).and_then(|confirm| {
+            rm_old_crates(
+                limit,
+                config.is_present("dry-run"),
+                confirm,
+                &cargo_cache.registry_pkg_cache,
+                &mut size_changed,
+                quarantine(None),
+                &AuditLog::new(&cargo_cache.cargo_home, Operation::KeepDuplicates),
+            )
+        }) {
             Ok(()) => {}
+            Err(error @ Error::MalformedPackageName(_)) => panic!("{}", error),
             Err(error) => {
-                match error {
-                    Error::MalformedPackageName(_) => {
-                        panic!("{}", error);
-                    }
-                    _ => unreachable!(),
-                };
+                eprintln!("{}", error);
+                process::exit(1);
             }
         }
     }
 
+    trash.print_summary();
+
     if size_changed && !config.is_present("dry-run") {
         // size has changed, print summary of how size has changed
 
@@ -10,9 +10,12 @@
 use std::fs;
 use std::path::PathBuf;
 
+use crate::audit::{AuditLog, Outcome};
 use crate::cache::caches::{Cache, RegistrySuperCache};
 use crate::cache::*;
+use crate::confirm::{confirm_removal, Confirm, PlannedRemoval};
 use crate::library::*;
+use crate::trash::Trash;
 
 use humansize::{file_size_opts, FileSize};
 
@@ -20,11 +23,10 @@ use humansize::{file_size_opts, FileSize};
 pub(crate) enum DryRunMessage<'a> {
     Custom(&'a str), // use the message that is passed
     Default,         // use the default message
-    #[allow(dead_code)]
-    None, // no message
+    None,            // no message
 }
 
-fn parse_version(path: &PathBuf) -> Result<(String, String), Error> {
+pub(crate) fn parse_version(path: &PathBuf) -> Result<(String, String), Error> {
     #[allow(clippy::single_match_else)]
     let filename = match path.file_stem() {
         Some(name) => name.to_str().unwrap().to_string(),
@@ -64,14 +66,30 @@ fn parse_version(path: &PathBuf) -> Result<(String, String), Error> {
 pub(crate) fn rm_old_crates(
     amount_to_keep: u64,
     dry_run: bool,
+    confirm: Confirm,
     registry_src_path: &PathBuf,
     size_changed: &mut bool,
+    trash: Option<&Trash>,
+    audit_log: &AuditLog,
 ) -> Result<(), Error> {
     println!();
 
     // remove crate sources from cache
     // src can be completely removed since we can always rebuilt it from cache (by extracting packages)
-    let mut removed_size = 0;
+    // collect everything first so that we can ask before removing: (path, size, dry run message)
+    let mut to_remove: Vec<(PathBuf, u64, String)> = Vec::new();
+    let queue = |pkgpath: &PathBuf, pkgname: &str, pkgver: &str| {
+        let size = fs::metadata(pkgpath)
+            .unwrap_or_else(|_| panic!("Failed to get metadata of file '{}'", &pkgpath.display()))
+            .len();
+        let dryrun_msg = format!(
+            "dry run: not actually deleting {} {} at {}",
+            pkgname,
+            pkgver,
+            pkgpath.display()
+        );
+        (pkgpath.clone(), size, dryrun_msg)
+    };
     // walk registry repos
     for repo in fs::read_dir(&registry_src_path).unwrap() {
         let mut crate_list = fs::read_dir(&repo.unwrap().path())
@@ -89,27 +107,7 @@ pub(crate) fn rm_old_crates(
             let (pkgname, pkgver) = parse_version(pkgpath)?;
 
             if amount_to_keep == 0 {
-                removed_size += fs::metadata(pkgpath)
-                    .unwrap_or_else(|_| {
-                        panic!("Failed to get metadata of file '{}'", &pkgpath.display())
-                    })
-                    .len();
-
-                let dryrun_msg = format!(
-                    "dry run: not actually deleting {} {} at {}",
-                    pkgname,
-                    pkgver,
-                    pkgpath.display()
-                );
-                remove_file(
-                    pkgpath,
-                    dry_run,
-                    size_changed,
-                    None,
-                    &DryRunMessage::Custom(&dryrun_msg),
-                    None,
-                );
-
+                to_remove.push(queue(pkgpath, &pkgname, &pkgver));
                 continue;
             }
             // println!("pkgname: {:?}, pkgver: {:?}", pkgname, pkgver);
@@ -118,26 +116,7 @@ pub(crate) fn rm_old_crates(
                 versions_of_this_package += 1;
                 if versions_of_this_package == amount_to_keep {
                     // we have seen this package too many times, queue for deletion
-                    removed_size += fs::metadata(pkgpath)
-                        .unwrap_or_else(|_| {
-                            panic!("Failed to get metadata of file '{}'", &pkgpath.display())
-                        })
-                        .len();
-
-                    let dryrun_msg = format!(
-                        "dry run: not actually deleting {} {} at {}",
-                        pkgname,
-                        pkgver,
-                        pkgpath.display()
-                    );
-                    remove_file(
-                        pkgpath,
-                        dry_run,
-                        size_changed,
-                        None,
-                        &DryRunMessage::Custom(&dryrun_msg),
-                        None,
-                    );
+                    to_remove.push(queue(pkgpath, &pkgname, &pkgver));
                 }
             } else {
                 // last_pkgname != pkgname, we got to a new package, reset counter
@@ -146,10 +125,44 @@ pub(crate) fn rm_old_crates(
             } // if last_pkgname == pkgname
         } // for pkgpath in &crate_list
     }
-    println!(
-        "Removed {} of compressed crate sources.",
-        removed_size.file_size(file_size_opts::DECIMAL).unwrap()
-    );
+
+    let plan: Vec<PlannedRemoval> = to_remove
+        .iter()
+        .map(|(path, size, _)| {
+            PlannedRemoval::new(
+                path.clone(),
+                &Component::RegistryCrateCache.to_string(),
+                *size,
+            )
+        })
+        .collect();
+    confirm_removal(confirm, &plan)?;
+
+    let mut removed_size = 0;
+    for (pkgpath, size, dryrun_msg) in &to_remove {
+        removed_size += size;
+        remove_file(
+            pkgpath,
+            dry_run,
+            size_changed,
+            None,
+            &DryRunMessage::Custom(dryrun_msg),
+            Some(*size),
+            trash,
+            audit_log,
+        );
+    }
+
+    let removed_size_hr = removed_size.file_size(file_size_opts::DECIMAL).unwrap();
+    if trash.is_some() && !dry_run {
+        // nothing is freed until the trash is emptied
+        println!(
+            "Moved {} of compressed crate sources to the trash.",
+            removed_size_hr
+        );
+    } else {
+        println!("Removed {} of compressed crate sources.", removed_size_hr);
+    }
     Ok(())
 }
 
@@ -157,77 +170,90 @@ pub(crate) fn rm_old_crates(
 pub(crate) fn remove_dir_via_cmdline(
     directory: Option<&str>,
     dry_run: bool,
+    confirm: Confirm,
     ccd: &CargoCachePaths,
     size_changed: &mut bool,
+    bin_cache: &mut bin::BinaryCache,
     checkouts_cache: &mut git_checkouts::GitCheckoutCache,
     bare_repos_cache: &mut git_bare_repos::GitRepoCache,
     registry_index_caches: &mut registry_index::RegistryIndicesCache,
     registry_pkgs_cache: &mut registry_pkg_cache::RegistryPkgCaches,
     registry_sources_caches: &mut registry_sources::RegistrySourceCaches,
+    trash: Option<&Trash>,
+    audit_log: &AuditLog,
 ) -> Result<(), Error> {
     // @TODO the passing of the cache is really a mess here... :(
 
     let dirs_to_remove = components_from_groups(&directory)?;
 
-    let mut size_removed: u64 = 0;
+    // collect everything we are going to remove first so that we can ask before removing it
+    let mut plan: Vec<PlannedRemoval> = Vec::new();
+    for component in &dirs_to_remove {
+        let name = component.to_string();
+        match component {
+            Component::RegistryCrateCache => plan.push(PlannedRemoval::new(
+                ccd.registry_pkg_cache.clone(),
+                &name,
+                registry_pkgs_cache.total_size(),
+            )),
+            Component::RegistrySources => plan.push(PlannedRemoval::new(
+                ccd.registry_sources.clone(),
+                &name,
+                registry_sources_caches.total_size(),
+            )),
+            // @TODO only remove specified index
+            Component::RegistryIndex => plan.push(PlannedRemoval::new(
+                ccd.registry_index.clone(),
+                &name,
+                // sum the sizes of the separate indices
+                registry_index_caches.total_size(),
+            )),
+            Component::GitRepos => plan.push(PlannedRemoval::new(
+                ccd.git_checkouts.clone(),
+                &name,
+                checkouts_cache.total_size(),
+            )),
+            Component::GitDB => plan.push(PlannedRemoval::new(
+                ccd.git_repos_bare.clone(),
+                &name,
+                bare_repos_cache.total_size(),
+            )),
+            Component::Bin => {
+                // remove the binaries one by one, the rustup proxies have to stay
+                for binary in bin_cache.removable_items() {
+                    let size = size_of_path(&binary);
+                    plan.push(PlannedRemoval::new(binary, &name, size));
+                }
+            }
+        }
+    }
+
+    confirm_removal(confirm, &plan)?;
 
     if dry_run {
         println!(); // newline
     }
 
-    for component in dirs_to_remove {
-        match component {
-            Component::RegistryCrateCache => {
-                let size = registry_pkgs_cache.total_size();
-                size_removed += size;
-                remove_with_default_message(
-                    &ccd.registry_pkg_cache,
-                    dry_run,
-                    size_changed,
-                    Some(size),
-                )?;
-            }
-
-            Component::RegistrySources => {
-                let size = registry_sources_caches.total_size();
-                size_removed += size;
-                remove_with_default_message(
-                    &ccd.registry_sources,
-                    dry_run,
-                    size_changed,
-                    Some(size),
-                )?;
-            }
-            Component::RegistryIndex => {
-                // sum the sizes of the separate indices
-                let size_of_all_indices: u64 = registry_index_caches.total_size();
-
-                size_removed += size_of_all_indices;
-                // @TODO only remove specified index
-                remove_with_default_message(
-                    &ccd.registry_index,
-                    dry_run,
-                    size_changed,
-                    Some(size_of_all_indices),
-                )?
-            }
-            Component::GitRepos => {
-                let size = checkouts_cache.total_size();
-                size_removed += size;
-                remove_with_default_message(&ccd.git_checkouts, dry_run, size_changed, Some(size))?;
-            }
-            Component::GitDB => {
-                let size = bare_repos_cache.total_size();
-                size_removed += size;
-                remove_with_default_message(
-                    &ccd.git_repos_bare,
-                    dry_run,
-                    size_changed,
-                    Some(size),
-                )?;
-            }
+    let mut size_removed: u64 = 0;
+    let mut removed_binaries = Vec::new();
+    for item in &plan {
+        size_removed += item.size;
+        remove_with_default_message(
+            &item.path,
+            dry_run,
+            size_changed,
+            Some(item.size),
+            trash,
+            audit_log,
+        )?;
+        if item.component == Component::Bin.to_string() {
+            removed_binaries.push(item.path.clone());
         }
     }
+    // quarantined binaries may come back via "undo", "empty-trash" forgets them
+    if !dry_run && trash.is_none() && !removed_binaries.is_empty() {
+        bin::forget_installed_binaries(&ccd.cargo_home, &removed_binaries)?;
+    }
 
     if dry_run {
         println!(
@@ -245,9 +271,15 @@ pub(crate) fn remove_with_default_message(
     dry_run: bool,
     size_changed: &mut bool,
     total_size_from_cache: Option<u64>,
+    trash: Option<&Trash>,
+    audit_log: &AuditLog,
 ) -> Result<(), Error> {
     // remove a specified subdirectory from cargo cache
-    let msg = Some(format!("removing: '{}'", dir.display()));
+    let msg = Some(if trash.is_some() {
+        format!("moving to the trash: '{}'", dir.display())
+    } else {
+        format!("removing: '{}'", dir.display())
+    });
 
     remove_file(
         dir,
@@ -256,11 +288,14 @@ pub(crate) fn remove_with_default_message(
         msg,
         &DryRunMessage::Default,
         total_size_from_cache,
+        trash,
+        audit_log,
     );
     Ok(())
 }
 
 /// remove a file with a custom message
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn remove_file(
     // path of the file to be deleted
     path: &PathBuf,
@@ -274,6 +309,10 @@ pub(crate) fn remove_file(
     dry_run_msg: &DryRunMessage<'_>,
     // size of the file according to cache
     total_size_from_cache: Option<u64>,
+    // if we are in quarantine mode, move the file into the trash instead of deleting it
+    trash: Option<&Trash>,
+    // record removed items in the audit log
+    audit_log: &AuditLog,
 ) {
     if dry_run {
         match dry_run_msg {
@@ -296,6 +335,29 @@ pub(crate) fn remove_file(
             }
             DryRunMessage::None => {}
         }
+    } else if let Some(trash_bin) = trash {
+        // quarantine mode
+        if let Some(msg) = deletion_msg {
+            println!("{}", msg);
+        }
+
+        if !path.exists() {
+            return;
+        }
+        // only log the size if the caller knows it, walking the item again would be expensive
+        let size = total_size_from_cache;
+
+        if let Err(error) = trash_bin.move_to_trash(path) {
+            eprintln!(
+                "Warning: failed to move \"{}\" to the trash.",
+                path.display()
+            );
+            eprintln!("error: {}", error);
+            audit_log.record(path, size, Outcome::Failed, Some(error.to_string()));
+        } else {
+            *size_changed = true;
+            audit_log.record(path, size, Outcome::Quarantined, None);
+        }
     } else {
         // no dry run
         // print deletion message if we have one
@@ -303,10 +365,17 @@ pub(crate) fn remove_file(
             println!("{}", msg);
         }
 
-        if path.is_file() && fs::remove_file(&path).is_err() {
-            eprintln!("Warning: failed to remove file \"{}\".", path.display());
-        } else {
-            *size_changed = true;
+        // only log the size if the caller knows it, walking the item again would be expensive
+        let size = total_size_from_cache;
+
+        if path.is_file() {
+            if let Err(error) = fs::remove_file(&path) {
+                eprintln!("Warning: failed to remove file \"{}\".", path.display());
+                audit_log.record(path, size, Outcome::Failed, Some(error.to_string()));
+            } else {
+                *size_changed = true;
+                audit_log.record(path, size, Outcome::Removed, None);
+            }
         }
 
         if path.is_dir() {
@@ -316,8 +385,10 @@ pub(crate) fn remove_file(
                     path.display()
                 );
                 eprintln!("error: {:?}", error);
+                audit_log.record(path, size, Outcome::Failed, Some(error.to_string()));
             } else {
                 *size_changed = true;
+                audit_log.record(path, size, Outcome::Removed, None);
             }
         }
     }
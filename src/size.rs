@@ -0,0 +1,194 @@
+// Copyright 2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// parse sizes given on the command line or in the config file:
+// "500"    => 500 bytes
+// "1.5G"   => 1.5 * 1024^3 bytes, single letters are binary units (as they always were)
+// "1.5GB"  => 1.5 * 1000^3 bytes
+// "1.5GiB" => 1.5 * 1024^3 bytes
+// units are case insensitive and whitespace around the number and the unit is ignored
+
+use std::fmt;
+
+const UNITS: [(&str, u64); 14] = [
+    ("b", 1),
+    ("k", 1 << 10),
+    ("kib", 1 << 10),
+    ("kb", 1_000),
+    ("m", 1 << 20),
+    ("mib", 1 << 20),
+    ("mb", 1_000_000),
+    ("g", 1 << 30),
+    ("gib", 1 << 30),
+    ("gb", 1_000_000_000),
+    ("t", 1 << 40),
+    ("tib", 1 << 40),
+    ("tb", 1_000_000_000_000),
+    ("", 1),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SizeError<'a> {
+    // nothing was passed
+    Empty,
+    // the size does not start with a positive number: "G" "-1G" "1.2.3M"
+    InvalidNumber(&'a str),
+    // the unit is not known: "1X"
+    UnknownUnit(&'a str),
+    // the size does not fit into 64 bits
+    TooLarge(&'a str),
+}
+
+impl fmt::Display for SizeError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self {
+            Self::Empty => write!(f, "Failed to parse size: the size is empty."),
+            Self::InvalidNumber(size) => {
+                write!(
+                    f,
+                    "Failed to parse size: \"{}\" does not start with a positive number.",
+                    size
+                )
+            }
+            Self::UnknownUnit(unit) => {
+                write!(f, "Failed to parse size: unknown unit \"{}\". Should be one of B, K, M, G, T (binary), KB, MB, GB, TB (decimal) or KiB, MiB, GiB, TiB (binary).", unit)
+            }
+            Self::TooLarge(size) => write!(f, "Failed to parse size: \"{}\" is too large.", size),
+        }
+    }
+}
+
+/// parse a size like "500", "1.5G", "10 MB" or "2GiB" into bytes
+pub(crate) fn parse_size(size: &str) -> Result<u64, SizeError<'_>> {
+    let input = size.trim();
+    if input.is_empty() {
+        return Err(SizeError::Empty);
+    }
+
+    let unit_start = input
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(input.len());
+    let number = &input[..unit_start];
+    let unit = input[unit_start..].trim();
+
+    // "", "." and "1.2.3" are no numbers
+    if !number.chars().any(|c| c.is_ascii_digit()) || number.matches('.').count() > 1 {
+        return Err(SizeError::InvalidNumber(input));
+    }
+
+    let multiplier = match UNITS
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(unit))
+    {
+        Some((_, multiplier)) => *multiplier,
+        None => return Err(SizeError::UnknownUnit(unit)),
+    };
+
+    if number.contains('.') {
+        // a fraction, f64 is precise enough here
+        let value: f64 = number
+            .parse()
+            .map_err(|_| SizeError::InvalidNumber(input))?;
+        #[allow(clippy::cast_precision_loss)]
+        let bytes = value * multiplier as f64;
+        #[allow(clippy::cast_precision_loss)]
+        if bytes >= u64::MAX as f64 {
+            return Err(SizeError::TooLarge(input));
+        }
+        // we may truncate the value here but that's ok
+        #[allow(clippy::cast_sign_loss)]
+        #[allow(clippy::cast_possible_truncation)]
+        Ok(bytes as u64)
+    } else {
+        number
+            .parse::<u64>()
+            .ok()
+            .and_then(|value| value.checked_mul(multiplier))
+            .ok_or(SizeError::TooLarge(input))
+    }
+}
+
+#[cfg(test)]
+mod sizetests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn units() {
+        let p = parse_size;
+
+        assert_eq!(p("1b"), Ok(1));
+        assert_eq!(p("1B"), Ok(1));
+
+        assert_eq!(p("1k"), Ok(1_024));
+        assert_eq!(p("1K"), Ok(1_024));
+
+        assert_eq!(p("1m"), Ok(1_048_576));
+        assert_eq!(p("1M"), Ok(1_048_576));
+
+        assert_eq!(p("1g"), Ok(1_073_741_824));
+        assert_eq!(p("1G"), Ok(1_073_741_824));
+
+        assert_eq!(p("1t"), Ok(1_099_511_627_776));
+        assert_eq!(p("1T"), Ok(1_099_511_627_776));
+
+        assert_eq!(p("4M"), Ok(4_194_304));
+        assert_eq!(p("42M"), Ok(44_040_192));
+        assert_eq!(p("1337M"), Ok(1_401_946_112));
+
+        assert_eq!(p("1.5k"), Ok(1_536));
+
+        assert_eq!(p("1KB"), Ok(1_000));
+        assert_eq!(p("1kb"), Ok(1_000));
+        assert_eq!(p("1MB"), Ok(1_000_000));
+        assert_eq!(p("1GB"), Ok(1_000_000_000));
+        assert_eq!(p("1TB"), Ok(1_000_000_000_000));
+
+        assert_eq!(p("1KiB"), Ok(1_024));
+        assert_eq!(p("1MiB"), Ok(1_048_576));
+        assert_eq!(p("1GiB"), Ok(1_073_741_824));
+        assert_eq!(p("1TiB"), Ok(1_099_511_627_776));
+    }
+
+    #[test]
+    fn numbers_and_whitespace() {
+        let p = parse_size;
+
+        assert_eq!(p("500"), Ok(500));
+        assert_eq!(p("0"), Ok(0));
+        assert_eq!(p("0G"), Ok(0));
+        assert_eq!(p("1.5GB"), Ok(1_500_000_000));
+        assert_eq!(p(".5K"), Ok(512));
+        assert_eq!(p("2."), Ok(2));
+        assert_eq!(p(" 10 MB "), Ok(10_000_000));
+        assert_eq!(p("1.5 GiB"), Ok(1_610_612_736));
+    }
+
+    #[test]
+    fn errors() {
+        let p = parse_size;
+
+        assert_eq!(p(""), Err(SizeError::Empty));
+        assert_eq!(p("  "), Err(SizeError::Empty));
+        assert_eq!(p("1_"), Err(SizeError::UnknownUnit("_")));
+        assert_eq!(p("1X"), Err(SizeError::UnknownUnit("X")));
+        assert_eq!(p("1 G B"), Err(SizeError::UnknownUnit("G B")));
+        assert_eq!(p("G"), Err(SizeError::InvalidNumber("G")));
+        assert_eq!(p("-1G"), Err(SizeError::InvalidNumber("-1G")));
+        assert_eq!(p("1.2.3M"), Err(SizeError::InvalidNumber("1.2.3M")));
+        assert_eq!(p(".M"), Err(SizeError::InvalidNumber(".M")));
+        assert_eq!(p("nan"), Err(SizeError::InvalidNumber("nan")));
+        assert_eq!(p("20000000T"), Err(SizeError::TooLarge("20000000T")));
+        assert_eq!(
+            p("99999999999999999999"),
+            Err(SizeError::TooLarge("99999999999999999999"))
+        );
+        assert_eq!(p("20000000.5T"), Err(SizeError::TooLarge("20000000.5T")));
+    }
+}
@@ -0,0 +1,184 @@
+// Copyright 2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// when was an item of a cache last used?
+// the access time is the obvious choice, but it is not updated at all on file systems mounted
+// with "noatime" and at most once a day with "relatime", so "--time-source" allows to use the
+// modification time, the status change time or the newest of them instead.
+// Listing a directory updates its access time, so the timestamps of directories are ignored and
+// files we read are opened without updating their access time where the platform allows it.
+
+use std::fs::{self, File, Metadata};
+use std::io;
+use std::path::Path;
+use std::time::SystemTime;
+
+use walkdir::WalkDir;
+
+/// which timestamp of a file tells when it was last used
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TimeSource {
+    /// access time
+    Atime,
+    /// modification time
+    Mtime,
+    /// status change time (creation time on windows)
+    Ctime,
+    /// the newest of the above
+    Newest,
+}
+
+impl Default for TimeSource {
+    fn default() -> Self {
+        Self::Atime
+    }
+}
+
+impl std::str::FromStr for TimeSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "atime" => Ok(Self::Atime),
+            "mtime" => Ok(Self::Mtime),
+            "ctime" => Ok(Self::Ctime),
+            "newest" => Ok(Self::Newest),
+            other => Err(other.to_string()),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn ctime(metadata: &Metadata) -> Option<SystemTime> {
+    use std::convert::TryFrom;
+    use std::os::unix::fs::MetadataExt;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let secs = u64::try_from(metadata.ctime()).ok()?;
+    let nanos = u32::try_from(metadata.ctime_nsec()).ok()?;
+    UNIX_EPOCH.checked_add(Duration::new(secs, nanos))
+}
+
+#[cfg(not(unix))]
+fn ctime(metadata: &Metadata) -> Option<SystemTime> {
+    metadata.created().ok()
+}
+
+impl TimeSource {
+    /// the timestamp of a file, None if the platform does not provide it
+    pub(crate) fn of(self, metadata: &Metadata) -> Option<SystemTime> {
+        match self {
+            Self::Atime => metadata.accessed().ok(),
+            Self::Mtime => metadata.modified().ok(),
+            Self::Ctime => ctime(metadata),
+            Self::Newest => [
+                metadata.accessed().ok(),
+                metadata.modified().ok(),
+                ctime(metadata),
+            ]
+            .iter()
+            .flatten()
+            .max()
+            .copied(),
+        }
+    }
+}
+
+/// when a file or a directory was last used, for directories this is the newest timestamp of all
+/// files inside of it (or of the directory itself if it is empty)
+pub(crate) fn last_use(path: &Path, source: TimeSource) -> Option<SystemTime> {
+    WalkDir::new(path)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| !entry.file_type().is_dir())
+        .filter_map(|entry| entry.metadata().ok())
+        .filter_map(|metadata| source.of(&metadata))
+        .max()
+        .or_else(|| fs::metadata(path).ok().and_then(|m| source.of(&m)))
+}
+
+/// open a file for reading without updating its access time if possible
+/// so that inspecting the cache does not make its items look recently used
+pub(crate) fn open_without_atime(path: &Path) -> io::Result<File> {
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        // only the owner of a file may use O_NOATIME, fall back to a normal open
+        if let Ok(file) = fs::OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NOATIME)
+            .open(path)
+        {
+            return Ok(file);
+        }
+    }
+    File::open(path)
+}
+
+#[cfg(test)]
+mod timestampstests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::path::PathBuf;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn last_use_of_dirs() {
+        let dir = PathBuf::from("target/timestamps_tests/last_use_of_dirs");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("old"), "old").unwrap();
+        fs::write(dir.join("sub").join("new"), "new").unwrap();
+
+        let new_mtime = fs::metadata(dir.join("sub").join("new"))
+            .unwrap()
+            .modified()
+            .unwrap();
+        assert_eq!(last_use(&dir, TimeSource::Mtime), Some(new_mtime));
+        assert_eq!(
+            last_use(&dir.join("old"), TimeSource::Mtime),
+            fs::metadata(dir.join("old")).unwrap().modified().ok()
+        );
+        assert!(last_use(&dir.join("missing"), TimeSource::Mtime).is_none());
+
+        let newest = last_use(&dir, TimeSource::Newest).unwrap();
+        for source in &[TimeSource::Atime, TimeSource::Mtime, TimeSource::Ctime] {
+            assert!(last_use(&dir, *source).unwrap() <= newest);
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn reading_keeps_atime() {
+        use std::io::Read;
+
+        let dir = PathBuf::from("target/timestamps_tests/reading_keeps_atime");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("file");
+        fs::write(&file, "content").unwrap();
+
+        // an access time older than the modification time is updated by every read with "relatime"
+        let past = UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        let status = std::process::Command::new("touch")
+            .args(["-a", "-d", "@1000000000"])
+            .arg(&file)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let mut content = String::new();
+        let _ = open_without_atime(&file)
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "content");
+        assert_eq!(last_use(&file, TimeSource::Atime), Some(past));
+    }
+}
@@ -0,0 +1,376 @@
+// Copyright 2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// quarantine mode: instead of deleting items right away, move them into a trash directory
+// inside the ${CARGO_HOME} so that a botched cleanup can be reverted via "cargo cache undo".
+// "cargo cache empty-trash" finally frees the space.
+
+// layout of the trash:
+// ${CARGO_HOME}/.cargo-cache/trash/
+//     2020-08-01_12-00-00.123/        <= one directory per operation
+//         manifest                    <= "<index> <original path>" per line
+//         items/0                     <= the quarantined items, named by index
+//         items/1
+
+use std::cell::Cell;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::audit::{AuditLog, Operation, Outcome};
+use crate::cache::bin::forget_installed_binaries;
+use crate::confirm::{confirm_removal, Confirm, PlannedRemoval};
+use crate::date::parse_date;
+use crate::library::*;
+
+use chrono::{Local, NaiveDateTime};
+use humansize::{file_size_opts, FileSize};
+
+// format of the names of the per-operation directories, sorts chronologically
+const OPERATION_NAME_FORMAT: &str = "%Y-%m-%d_%H-%M-%S%.3f";
+
+const MANIFEST: &str = "manifest";
+const ITEMS: &str = "items";
+
+/// get the root directory of the trash
+pub(crate) fn trash_dir(cargo_home: &Path) -> PathBuf {
+    cargo_home.join(".cargo-cache").join("trash")
+}
+
+/// a single quarantined item and the path it was moved away from
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TrashEntry {
+    original_path: PathBuf,
+    trashed_path: PathBuf,
+}
+
+/// holds all items removed in a single cargo-cache invocation
+#[derive(Debug)]
+pub(crate) struct Trash {
+    /// the directory of this operation inside the trash, created on demand
+    dir: PathBuf,
+    /// number of items moved to the trash so far
+    counter: Cell<usize>,
+}
+
+impl Trash {
+    /// creates a new trash for one operation, nothing is written to disk until the first item is moved
+    pub(crate) fn new(cargo_home: &Path) -> Self {
+        let name = Local::now()
+            .naive_local()
+            .format(OPERATION_NAME_FORMAT)
+            .to_string();
+        Self {
+            dir: trash_dir(cargo_home).join(name),
+            counter: Cell::new(0),
+        }
+    }
+
+    /// number of items that were moved to the trash
+    pub(crate) fn number_of_items(&self) -> usize {
+        self.counter.get()
+    }
+
+    /// tell the user how to get the items back and that they still take up space
+    pub(crate) fn print_summary(&self) {
+        let number = self.number_of_items();
+        if number > 0 {
+            let size = cumulative_dir_size(&self.dir.join(ITEMS)).dir_size;
+            println!(
+                "Moved {} items ({}) to the trash at '{}', run \"cargo cache undo\" to restore them.",
+                number,
+                size.file_size(file_size_opts::DECIMAL).unwrap(),
+                self.dir.display()
+            );
+            println!(
+                "No space was freed, run \"cargo cache empty-trash\" to remove them for good."
+            );
+        }
+    }
+
+    /// move a file or directory into the trash and record its original location
+    pub(crate) fn move_to_trash(&self, path: &Path) -> Result<(), Error> {
+        let items_dir = self.dir.join(ITEMS);
+        fs::create_dir_all(&items_dir).map_err(|e| Error::TrashIo(items_dir.clone(), e))?;
+
+        let index = self.counter.get();
+        let target = items_dir.join(index.to_string());
+
+        // the trash lives inside the cargo home so this should not need to cross file systems
+        fs::rename(path, &target).map_err(|e| Error::TrashIo(path.to_path_buf(), e))?;
+
+        let manifest_path = self.dir.join(MANIFEST);
+        let mut manifest = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&manifest_path)
+            .map_err(|e| Error::TrashIo(manifest_path.clone(), e))?;
+        writeln!(manifest, "{} {}", index, path.display())
+            .map_err(|e| Error::TrashIo(manifest_path, e))?;
+
+        self.counter.set(index + 1);
+        Ok(())
+    }
+}
+
+/// parse a manifest of an operation directory into a list of entries
+fn parse_manifest(operation_dir: &Path) -> Result<Vec<TrashEntry>, Error> {
+    let manifest_path = operation_dir.join(MANIFEST);
+    let manifest =
+        fs::read_to_string(&manifest_path).map_err(|e| Error::TrashIo(manifest_path.clone(), e))?;
+
+    manifest
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            // "<index> <original path>", the path may contain spaces itself
+            let mut split = line.splitn(2, ' ');
+            match (split.next(), split.next()) {
+                (Some(index), Some(path)) if index.parse::<usize>().is_ok() => Ok(TrashEntry {
+                    original_path: PathBuf::from(path),
+                    trashed_path: operation_dir.join(ITEMS).join(index),
+                }),
+                _ => Err(Error::TrashManifestCorrupt(manifest_path.clone())),
+            }
+        })
+        .collect()
+}
+
+/// installed binaries of an operation that are still in the trash, cargo keeps them in its install
+/// metadata until they are removed for good
+fn trashed_binaries(operation_dir: &Path, bin_dir: &Path) -> Vec<PathBuf> {
+    parse_manifest(operation_dir)
+        .unwrap_or_default()
+        .into_iter()
+        // restored or reinstalled binaries are still installed
+        .filter(|entry| {
+            entry.original_path.starts_with(bin_dir)
+                && entry.trashed_path.exists()
+                && !entry.original_path.exists()
+        })
+        .map(|entry| entry.original_path)
+        .collect()
+}
+
+/// get all operation directories in the trash, oldest first
+fn operations(cargo_home: &Path) -> Vec<PathBuf> {
+    let trash = trash_dir(cargo_home);
+    let mut operations = match fs::read_dir(&trash) {
+        Ok(read_dir) => read_dir
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.is_dir())
+            .collect::<Vec<PathBuf>>(),
+        Err(_) => Vec::new(),
+    };
+    operations.sort();
+    operations
+}
+
+/// get the date at which an operation happened from the name of its directory
+fn operation_date(operation_dir: &Path) -> Option<NaiveDateTime> {
+    let name = operation_dir.file_name()?.to_str()?;
+    NaiveDateTime::parse_from_str(name, OPERATION_NAME_FORMAT).ok()
+}
+
+/// "cargo cache undo": restore all items of the last operation that was run in quarantine mode
+pub(crate) fn undo(cargo_cache_paths: &CargoCachePaths, dry_run: bool) -> Result<(), Error> {
+    let operation_dir = operations(&cargo_cache_paths.cargo_home)
+        .pop()
+        .ok_or(Error::TrashEmpty)?;
+
+    let entries = parse_manifest(&operation_dir)?;
+    let mut restored = 0;
+    let mut skipped = 0;
+
+    for entry in &entries {
+        if !entry.trashed_path.exists() {
+            // already restored by an earlier, partially successful undo
+            continue;
+        }
+        if entry.original_path.exists() {
+            // cargo already recreated the item in the meantime, don't clobber it
+            eprintln!(
+                "Warning: '{}' already exists, keeping the copy in the trash.",
+                entry.original_path.display()
+            );
+            skipped += 1;
+            continue;
+        }
+
+        if dry_run {
+            println!("dry-run: would restore '{}'", entry.original_path.display());
+        } else {
+            if let Some(parent) = entry.original_path.parent() {
+                fs::create_dir_all(parent).map_err(|e| Error::TrashIo(parent.to_path_buf(), e))?;
+            }
+            fs::rename(&entry.trashed_path, &entry.original_path)
+                .map_err(|e| Error::TrashIo(entry.trashed_path.clone(), e))?;
+        }
+        restored += 1;
+    }
+
+    if dry_run {
+        println!("dry-run: would restore {} items", restored);
+    } else {
+        println!("Restored {} items", restored);
+        // only drop the operation once everything made it back
+        if skipped == 0 {
+            remove_dir_all::remove_dir_all(&operation_dir)
+                .map_err(|e| Error::TrashIo(operation_dir.clone(), e))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// "cargo cache empty-trash": permanently delete quarantined items, optionally only those
+/// which were moved into the trash before a certain date
+pub(crate) fn empty_trash(
+    cargo_cache_paths: &CargoCachePaths,
+    older_than: Option<&str>,
+    dry_run: bool,
+    confirm: Confirm,
+) -> Result<(), Error> {
+    let date_limit = match older_than {
+        Some(date) => Some(parse_date(date)?),
+        None => None,
+    };
+
+    let operation_dirs: Vec<PathBuf> = operations(&cargo_cache_paths.cargo_home)
+        .into_iter()
+        .filter(|operation_dir| match date_limit {
+            // too young or not created by us
+            Some(limit) => matches!(operation_date(operation_dir), Some(date) if date < limit),
+            None => true,
+        })
+        .collect();
+
+    let plan: Vec<PlannedRemoval> = operation_dirs
+        .iter()
+        .map(|operation_dir| {
+            PlannedRemoval::new(
+                operation_dir.clone(),
+                "trash",
+                cumulative_dir_size(operation_dir).dir_size,
+            )
+        })
+        .collect();
+    confirm_removal(confirm, &plan)?;
+
+    let audit_log = AuditLog::new(&cargo_cache_paths.cargo_home, Operation::EmptyTrash);
+    let mut removed_size = 0;
+    let mut removed_operations = 0;
+
+    for item in plan {
+        let (operation_dir, size) = (item.path, item.size);
+        let binaries = trashed_binaries(&operation_dir, &cargo_cache_paths.bin_dir);
+        if dry_run {
+            println!(
+                "dry-run: would remove: '{}' ({})",
+                operation_dir.display(),
+                size.file_size(file_size_opts::DECIMAL).unwrap()
+            );
+        } else if let Err(error) = remove_dir_all::remove_dir_all(&operation_dir) {
+            audit_log.record(
+                &operation_dir,
+                Some(size),
+                Outcome::Failed,
+                Some(error.to_string()),
+            );
+            return Err(Error::TrashIo(operation_dir, error));
+        } else {
+            audit_log.record(&operation_dir, Some(size), Outcome::Removed, None);
+            forget_installed_binaries(&cargo_cache_paths.cargo_home, &binaries)?;
+        }
+        removed_size += size;
+        removed_operations += 1;
+    }
+
+    println!(
+        "{} {} operations from the trash, freeing {}",
+        if dry_run {
+            "dry-run: would remove"
+        } else {
+            "Removed"
+        },
+        removed_operations,
+        removed_size.file_size(file_size_opts::DECIMAL).unwrap()
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod trashtests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn move_and_parse_manifest() {
+        let cargo_home = PathBuf::from("target/trash_tests/move_and_parse_manifest");
+        let _ = fs::remove_dir_all(&cargo_home);
+        fs::create_dir_all(cargo_home.join("registry").join("src")).unwrap();
+        let file = cargo_home.join("registry").join("src").join("some file");
+        fs::write(&file, "hello").unwrap();
+
+        let trash = Trash::new(&cargo_home);
+        trash.move_to_trash(&file).unwrap();
+        assert!(!file.exists());
+        assert_eq!(trash.number_of_items(), 1);
+
+        let entries = parse_manifest(&trash.dir).unwrap();
+        assert_eq!(
+            entries,
+            vec![TrashEntry {
+                original_path: file,
+                trashed_path: trash.dir.join(ITEMS).join("0"),
+            }]
+        );
+        assert_eq!(
+            fs::read_to_string(&entries[0].trashed_path).unwrap(),
+            "hello"
+        );
+    }
+
+    #[test]
+    fn binaries_in_the_trash() {
+        let cargo_home = PathBuf::from("target/trash_tests/binaries_in_the_trash");
+        let _ = fs::remove_dir_all(&cargo_home);
+        let bin_dir = cargo_home.join("bin");
+        fs::create_dir_all(&bin_dir).unwrap();
+        fs::create_dir_all(cargo_home.join("git").join("db")).unwrap();
+        let binary = bin_dir.join("rg");
+        let repo = cargo_home.join("git").join("db").join("repo");
+        fs::write(&binary, "binary").unwrap();
+        fs::write(&repo, "repo").unwrap();
+
+        let trash = Trash::new(&cargo_home);
+        trash.move_to_trash(&binary).unwrap();
+        trash.move_to_trash(&repo).unwrap();
+        assert_eq!(trashed_binaries(&trash.dir, &bin_dir), vec![binary.clone()]);
+
+        // reinstalled in the meantime, emptying the trash must not forget it
+        fs::write(&binary, "binary").unwrap();
+        assert!(trashed_binaries(&trash.dir, &bin_dir).is_empty());
+    }
+
+    #[test]
+    fn operation_dates() {
+        let dir = PathBuf::from("trash").join("2020-08-01_12-30-00.123");
+        assert_eq!(
+            operation_date(&dir)
+                .unwrap()
+                .format("%Y.%m.%d %H:%M")
+                .to_string(),
+            "2020.08.01 12:30"
+        );
+        assert!(operation_date(&PathBuf::from("trash").join("foo")).is_none());
+    }
+}